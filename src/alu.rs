@@ -0,0 +1,149 @@
+use state::{SectionReader, SectionWriter};
+
+/// The 5A22's multiplier and divider. Both work a bit at a time, one step per CPU cycle, so
+/// reading the results before they're done returns the partial values the hardware has at that
+/// point. The same two result registers are shared by both units, and a multiply works by
+/// shifting its operand through RDDIV, just as the real chip does.
+#[derive(Clone)]
+pub struct ALU {
+    wrmpya: u8,
+    wrdiva: u16,
+    rddiv: u16,
+    rdmpy: u16,
+    shift: u32,
+    mpy_steps: u32,
+    div_steps: u32,
+    started: bool,
+}
+
+const MULTIPLY_STEPS: u32 = 8;
+const DIVIDE_STEPS: u32 = 16;
+
+impl ALU {
+    pub fn new() -> ALU {
+        ALU {
+            wrmpya: 0xFF,
+            wrdiva: 0xFFFF,
+            rddiv: 0,
+            rdmpy: 0,
+            shift: 0,
+            mpy_steps: 0,
+            div_steps: 0,
+            started: false,
+        }
+    }
+
+    pub fn busy(&self) -> bool {
+        self.mpy_steps > 0 || self.div_steps > 0
+    }
+
+    pub fn write(&mut self, offset: usize, data: u8) {
+        match offset {
+            0x4202 => self.wrmpya = data,
+            0x4203 => {
+                self.rdmpy = 0;
+
+                // Writes while either unit is running are lost
+                if self.busy() {
+                    return;
+                }
+
+                self.rddiv = (data as u16) << 8 | self.wrmpya as u16;
+                self.shift = data as u32;
+                self.mpy_steps = MULTIPLY_STEPS;
+                self.started = true;
+            },
+            0x4204 => self.wrdiva = (self.wrdiva & 0xFF00) | data as u16,
+            0x4205 => self.wrdiva = (self.wrdiva & 0x00FF) | (data as u16) << 8,
+            0x4206 => {
+                self.rdmpy = self.wrdiva;
+
+                if self.busy() {
+                    return;
+                }
+
+                // A zero divisor fits at every step, so dividing by zero leaves a quotient of
+                // $FFFF and the dividend as the remainder
+                self.shift = (data as u32) << 16;
+                self.div_steps = DIVIDE_STEPS;
+                self.started = true;
+            },
+            _ => unreachable!("Not an ALU register {:x}", offset)
+        }
+    }
+
+    pub fn read(&self, offset: usize) -> u8 {
+        match offset {
+            0x4214 => self.rddiv as u8,
+            0x4215 => (self.rddiv >> 8) as u8,
+            0x4216 => self.rdmpy as u8,
+            0x4217 => (self.rdmpy >> 8) as u8,
+            _ => unreachable!("Not an ALU register {:x}", offset)
+        }
+    }
+
+    pub fn save_state(&self, out: &mut SectionWriter) {
+        out.write_u8(self.wrmpya);
+        out.write_u16(self.wrdiva);
+        out.write_u16(self.rddiv);
+        out.write_u16(self.rdmpy);
+        out.write_u32(self.shift);
+        out.write_u8(self.mpy_steps as u8);
+        out.write_u8(self.div_steps as u8);
+        out.write_bool(self.started);
+    }
+
+    pub fn load_state(&mut self, input: &mut SectionReader) -> Result<(), String> {
+        self.wrmpya = input.read_u8()?;
+        self.wrdiva = input.read_u16()?;
+        self.rddiv = input.read_u16()?;
+        self.rdmpy = input.read_u16()?;
+        self.shift = input.read_u32()?;
+        self.mpy_steps = input.read_u8()? as u32;
+        self.div_steps = input.read_u8()? as u32;
+        self.started = input.read_bool()?;
+
+        Ok(())
+    }
+
+    /// Runs the units for the CPU cycles of one instruction. Bus writes land on an instruction's
+    /// last cycle, so an operation started by that instruction begins counting from the next one.
+    pub fn run(&mut self, cpu_cycles: u32) {
+        if self.started {
+            self.started = false;
+            return;
+        }
+
+        for _ in 0..cpu_cycles {
+            if !self.busy() {
+                break;
+            }
+
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        if self.mpy_steps > 0 {
+            self.mpy_steps -= 1;
+
+            if self.rddiv & 1 != 0 {
+                self.rdmpy = self.rdmpy.wrapping_add(self.shift as u16);
+            }
+
+            self.rddiv >>= 1;
+            self.shift <<= 1;
+        }
+
+        if self.div_steps > 0 {
+            self.div_steps -= 1;
+            self.rddiv <<= 1;
+            self.shift >>= 1;
+
+            if self.rdmpy as u32 >= self.shift {
+                self.rdmpy -= self.shift as u16;
+                self.rddiv |= 1;
+            }
+        }
+    }
+}
@@ -1,18 +1,57 @@
 use memory::Memory;
+use snes::Region;
+use state::{SectionReader, SectionWriter};
 
-pub struct APU;
+pub const SAMPLE_RATE: u32 = 32000;
+pub const CHANNELS: usize = 2;
+
+#[derive(Clone)]
+pub struct APU {
+    sample_clock: u64,
+    master_clock: u64,
+    samples: Vec<i16>, // interleaved left/right at SAMPLE_RATE
+}
 
 impl APU {
-    pub fn new() -> APU {
-        APU
+    pub fn new(region: Region) -> APU {
+        APU {
+            sample_clock: 0,
+            master_clock: region.master_clock(),
+            samples: Vec::new(),
+        }
     }
 
-    pub fn run(&mut self, memory: &mut Memory) {
+    pub fn run(&mut self, memory: &mut Memory, master_cycles: u32) {
         /*
         if memory.get_byte(0x2140) == 0xFF && memory.get_byte(0x2141) == 0xF0 {
             memory.set_byte(0x2140, 0xAA);
             memory.set_byte(0x2141, 0xBB);
         }
         */
+
+        // The SPC700 and DSP aren't emulated yet, so the output stream is silence
+        // produced at the DSP's rate to keep it in step with the rest of the machine
+        self.sample_clock += master_cycles as u64 * SAMPLE_RATE as u64;
+
+        while self.sample_clock >= self.master_clock {
+            self.sample_clock -= self.master_clock;
+            self.samples.push(0);
+            self.samples.push(0);
+        }
+    }
+
+    pub fn save_state(&self, out: &mut SectionWriter) {
+        out.write_u64(self.sample_clock);
+    }
+
+    pub fn load_state(&mut self, input: &mut SectionReader) -> Result<(), String> {
+        self.sample_clock = input.read_u64()?;
+
+        Ok(())
+    }
+
+    /// Hands over every sample generated since the last call
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        ::std::mem::take(&mut self.samples)
     }
 }
@@ -0,0 +1,229 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+use apu::{CHANNELS, SAMPLE_RATE};
+
+const WAV_HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+pub trait AudioSink {
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// 16-bit stereo PCM in a RIFF/WAVE container. The sizes in the header are filled in by
+/// `finish`, and stop at 4 GiB as the fields can't hold more; players read to the end anyway.
+pub struct WavWriter {
+    out: BufWriter<File>,
+    data_size: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, sample_rate: u32) -> io::Result<WavWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write_wav_header(&mut out, sample_rate, 0)?;
+
+        Ok(WavWriter {
+            out: out,
+            data_size: 0,
+            finished: false,
+        })
+    }
+
+    fn update_header(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(4))?;
+        write_u32(&mut self.out, (WAV_HEADER_SIZE - 8).saturating_add(self.data_size))?;
+        self.out.seek(SeekFrom::Start(40))?;
+        write_u32(&mut self.out, self.data_size)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        write_pcm(&mut self.out, samples)?;
+        self.data_size = self.data_size.saturating_add((samples.len() * 2) as u32);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+
+        self.finished = true;
+        self.update_header()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _res = self.finish();
+    }
+}
+
+/// Headerless 16-bit little endian interleaved stereo, suitable for piping into other tools
+pub struct PcmWriter {
+    out: Box<dyn Write>,
+}
+
+impl PcmWriter {
+    /// A path of "-" writes to stdout
+    pub fn create(path: &str) -> io::Result<PcmWriter> {
+        let out: Box<dyn Write> = if path == "-" {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+
+        Ok(PcmWriter { out: out })
+    }
+}
+
+impl AudioSink for PcmWriter {
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        write_pcm(&mut self.out, samples)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl Drop for PcmWriter {
+    fn drop(&mut self) {
+        let _res = self.finish();
+    }
+}
+
+/// Linear interpolating resampler for interleaved stereo streams
+pub struct Resampler {
+    from: u32,
+    to: u32,
+    position: u64, // fractional position into the input, in units of 1/to
+    last: [i16; CHANNELS],
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Resampler {
+        Resampler {
+            from: from,
+            to: to,
+            position: 0,
+            last: [0; CHANNELS],
+        }
+    }
+
+    pub fn process(&mut self, input: &[i16]) -> Vec<i16> {
+        let frames = (input.len() / CHANNELS) as u64;
+        let mut output = Vec::with_capacity((frames * self.to as u64 / self.from as u64) as usize * CHANNELS + CHANNELS);
+
+        // Frame -1 is the last frame of the previous call, so interpolation is continuous across calls
+        let frame_at = |index: u64, channel: usize, last: &[i16; CHANNELS]| -> i32 {
+            if index == 0 {
+                last[channel] as i32
+            } else {
+                input[(index as usize - 1) * CHANNELS + channel] as i32
+            }
+        };
+
+        while self.position / (self.to as u64) < frames {
+            let index = self.position / self.to as u64;
+            let fraction = (self.position % self.to as u64) as i32;
+
+            for channel in 0..CHANNELS {
+                let a = frame_at(index, channel, &self.last);
+                let b = frame_at(index + 1, channel, &self.last);
+
+                output.push((a + (b - a) * fraction / self.to as i32) as i16);
+            }
+
+            self.position += self.from as u64;
+        }
+
+        if frames > 0 {
+            self.position -= frames * self.to as u64;
+
+            for channel in 0..CHANNELS {
+                self.last[channel] = input[(frames as usize - 1) * CHANNELS + channel];
+            }
+        }
+
+        output
+    }
+}
+
+/// Where the APU's samples end up, optionally converted to another rate on the way
+pub struct AudioOutput {
+    sink: Box<dyn AudioSink>,
+    resampler: Option<Resampler>,
+}
+
+impl AudioOutput {
+    pub fn new(sink: Box<dyn AudioSink>, sample_rate: u32) -> AudioOutput {
+        let resampler = if sample_rate != SAMPLE_RATE {
+            Some(Resampler::new(SAMPLE_RATE, sample_rate))
+        } else {
+            None
+        };
+
+        AudioOutput {
+            sink: sink,
+            resampler: resampler,
+        }
+    }
+
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        match self.resampler {
+            Some(ref mut resampler) => {
+                let resampled = resampler.process(samples);
+                self.sink.write_samples(&resampled)
+            },
+            None => self.sink.write_samples(samples),
+        }
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.sink.finish()
+    }
+}
+
+fn write_wav_header<W: Write>(out: &mut W, sample_rate: u32, data_size: u32) -> io::Result<()> {
+    let block_align = CHANNELS as u16 * BITS_PER_SAMPLE / 8;
+
+    out.write_all(b"RIFF")?;
+    write_u32(out, WAV_HEADER_SIZE - 8 + data_size)?;
+    out.write_all(b"WAVE")?;
+    out.write_all(b"fmt ")?;
+    write_u32(out, 16)?;
+    write_u16(out, 1)?; // PCM
+    write_u16(out, CHANNELS as u16)?;
+    write_u32(out, sample_rate)?;
+    write_u32(out, sample_rate * block_align as u32)?;
+    write_u16(out, block_align)?;
+    write_u16(out, BITS_PER_SAMPLE)?;
+    out.write_all(b"data")?;
+    write_u32(out, data_size)
+}
+
+fn write_pcm<W: Write + ?Sized>(out: &mut W, samples: &[i16]) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(samples.len() * 2);
+
+    for sample in samples {
+        bytes.push(*sample as u8);
+        bytes.push((*sample >> 8) as u8);
+    }
+
+    out.write_all(&bytes)
+}
+
+fn write_u16<W: Write + ?Sized>(out: &mut W, data: u16) -> io::Result<()> {
+    out.write_all(&[data as u8, (data >> 8) as u8])
+}
+
+fn write_u32<W: Write + ?Sized>(out: &mut W, data: u32) -> io::Result<()> {
+    out.write_all(&[data as u8, (data >> 8) as u8, (data >> 16) as u8, (data >> 24) as u8])
+}
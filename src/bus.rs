@@ -0,0 +1,25 @@
+/// What the CPU sees of the rest of the machine. `Memory` is the SNES's; tests and other
+/// systems can supply their own, such as a flat 16 MiB RAM or one that logs every access.
+pub trait Bus {
+    fn read(&mut self, addr: usize) -> u8;
+    fn write(&mut self, addr: usize, data: u8);
+
+    /// A cycle the CPU spends without using the bus
+    fn idle(&mut self) {}
+
+    /// The bank and address execution starts at after a reset. The 65816 reads the vector at
+    /// $00:FFFC.
+    fn reset_vector(&mut self) -> (usize, usize) {
+        (0, read_word(self, 0xFFFC))
+    }
+
+    /// Where BRK jumps to, through the vector at $00:FFE6 in native mode or $00:FFFE in
+    /// emulation mode
+    fn break_vector(&mut self, emulation_mode: bool) -> usize {
+        read_word(self, if emulation_mode { 0xFFFE } else { 0xFFE6 })
+    }
+}
+
+fn read_word<B: Bus + ?Sized>(bus: &mut B, addr: usize) -> usize {
+    bus.read(addr) as usize | ((bus.read(addr + 1) as usize) << 8)
+}
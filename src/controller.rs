@@ -0,0 +1,443 @@
+// Button bits in the layout the auto-joypad registers use, JOYxH:JOYxL
+pub const BUTTON_B: u16 = 0x8000;
+pub const BUTTON_Y: u16 = 0x4000;
+pub const BUTTON_SELECT: u16 = 0x2000;
+pub const BUTTON_START: u16 = 0x1000;
+pub const BUTTON_UP: u16 = 0x0800;
+pub const BUTTON_DOWN: u16 = 0x0400;
+pub const BUTTON_LEFT: u16 = 0x0200;
+pub const BUTTON_RIGHT: u16 = 0x0100;
+pub const BUTTON_A: u16 = 0x0080;
+pub const BUTTON_X: u16 = 0x0040;
+pub const BUTTON_L: u16 = 0x0020;
+pub const BUTTON_R: u16 = 0x0010;
+
+// Mice and light guns reuse the first button word for their own buttons
+pub const MOUSE_LEFT: u16 = 0x0001;
+pub const MOUSE_RIGHT: u16 = 0x0002;
+pub const SCOPE_FIRE: u16 = 0x0001;
+pub const SCOPE_CURSOR: u16 = 0x0002;
+pub const SCOPE_TURBO: u16 = 0x0004;
+pub const SCOPE_PAUSE: u16 = 0x0008;
+pub const JUSTIFIER_TRIGGER: u16 = 0x0001;
+pub const JUSTIFIER_START: u16 = 0x0002;
+
+const BUTTON_NAMES: [(&str, u16); 20] = [
+    ("b", BUTTON_B), ("y", BUTTON_Y), ("select", BUTTON_SELECT), ("start", BUTTON_START),
+    ("up", BUTTON_UP), ("down", BUTTON_DOWN), ("left", BUTTON_LEFT), ("right", BUTTON_RIGHT),
+    ("a", BUTTON_A), ("x", BUTTON_X), ("l", BUTTON_L), ("r", BUTTON_R),
+    ("mouse_left", MOUSE_LEFT), ("mouse_right", MOUSE_RIGHT),
+    ("fire", SCOPE_FIRE), ("cursor", SCOPE_CURSOR), ("turbo", SCOPE_TURBO), ("pause", SCOPE_PAUSE),
+    ("trigger", JUSTIFIER_TRIGGER), ("gun_start", JUSTIFIER_START),
+];
+
+/// Looks up a button bit by its script name, ignoring case
+pub fn button_from_name(name: &str) -> Option<u16> {
+    let name = name.to_lowercase();
+
+    BUTTON_NAMES.iter().find(|&&(button, _)| button == name).map(|&(_, bit)| bit)
+}
+
+/// The script names of every button set in a button word
+pub fn button_names(buttons: u16) -> Vec<&'static str> {
+    BUTTON_NAMES.iter().filter(|&&(_, bit)| buttons & bit != 0).map(|&(name, _)| name).collect()
+}
+
+/// What the host is doing with the device in a port. Pads only look at the first button word,
+/// the multitap at all four, the mouse at the pointer as relative motion, and light guns at the
+/// pointer as a screen position (negative when aimed off screen).
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct PortInput {
+    pub buttons: [u16; 4],
+    pub x: i16,
+    pub y: i16,
+}
+
+/// A device plugged into one of the two controller ports. Both the serial registers at
+/// $4016/$4017 and the auto-joypad read talk to it through the latch and clock lines.
+pub trait Controller {
+    /// Mirrors the latch line driven by bit 0 of $4016
+    fn set_latch(&mut self, latch: bool);
+
+    /// Clocks the device once and returns the levels on its D0 and D1 data lines in bits 0 and 1
+    fn clock(&mut self) -> u8;
+
+    /// Mirrors the port's I/O line, driven by bit 6 ($4201) for port 0 and bit 7 for port 1
+    fn set_iobit(&mut self, level: bool) {}
+
+    fn set_input(&mut self, input: &PortInput) {}
+
+    /// The screen position a light gun sees the beam at, which latches the PPU's H/V counters
+    fn light_position(&self) -> Option<(u16, u16)> {
+        None
+    }
+}
+
+/// Builds a device from its command line name
+pub fn create(name: &str) -> Option<Box<dyn Controller>> {
+    match name {
+        "none" => Some(Box::new(Unplugged)),
+        "pad" => Some(Box::new(Joypad::new())),
+        "multitap" => Some(Box::new(Multitap::new())),
+        "mouse" => Some(Box::new(Mouse::new())),
+        "superscope" => Some(Box::new(SuperScope::new())),
+        "justifier" => Some(Box::new(Justifier::new())),
+        _ => None,
+    }
+}
+
+/// An empty port; both data lines float low
+pub struct Unplugged;
+
+impl Controller for Unplugged {
+    fn set_latch(&mut self, latch: bool) {}
+
+    fn clock(&mut self) -> u8 {
+        0
+    }
+}
+
+/// Shifts out a fixed-length report MSB first while the latch is low, then ones
+struct ShiftRegister {
+    data: u32,
+    length: u8,
+    shifted: u8,
+}
+
+impl ShiftRegister {
+    fn new() -> ShiftRegister {
+        ShiftRegister {
+            data: 0,
+            length: 0,
+            shifted: 0,
+        }
+    }
+
+    fn load(&mut self, data: u32, length: u8) {
+        self.data = data;
+        self.length = length;
+        self.shifted = 0;
+    }
+
+    fn peek(&self) -> u8 {
+        if self.shifted >= self.length {
+            1
+        } else {
+            ((self.data >> (self.length - 1 - self.shifted)) & 1) as u8
+        }
+    }
+
+    fn shift(&mut self) -> u8 {
+        let bit = self.peek();
+
+        if self.shifted < self.length {
+            self.shifted += 1;
+        }
+
+        bit
+    }
+}
+
+/// The standard SNES pad: 12 buttons shifted out B first, then the 4 ID bits, then ones
+pub struct Joypad {
+    buttons: u16,
+    shift: ShiftRegister,
+    latched: bool,
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            buttons: 0,
+            shift: ShiftRegister::new(),
+            latched: false,
+        }
+    }
+}
+
+impl Controller for Joypad {
+    fn set_latch(&mut self, latch: bool) {
+        self.latched = latch;
+
+        if latch {
+            self.shift.load(self.buttons as u32, 16);
+        }
+    }
+
+    fn clock(&mut self) -> u8 {
+        if self.latched {
+            self.shift.load(self.buttons as u32, 16);
+            return self.shift.peek();
+        }
+
+        self.shift.shift()
+    }
+
+    fn set_input(&mut self, input: &PortInput) {
+        self.buttons = input.buttons[0] & 0xFFF0;
+    }
+}
+
+/// Four pads behind one port. The I/O line picks which pair drives D0/D1, and D1 reads
+/// high while latched so games can detect the adapter.
+pub struct Multitap {
+    pads: [Joypad; 4],
+    iobit: bool,
+    latched: bool,
+}
+
+impl Multitap {
+    pub fn new() -> Multitap {
+        Multitap {
+            pads: [Joypad::new(), Joypad::new(), Joypad::new(), Joypad::new()],
+            iobit: true,
+            latched: false,
+        }
+    }
+}
+
+impl Controller for Multitap {
+    fn set_latch(&mut self, latch: bool) {
+        self.latched = latch;
+
+        for pad in self.pads.iter_mut() {
+            pad.set_latch(latch);
+        }
+    }
+
+    fn clock(&mut self) -> u8 {
+        if self.latched {
+            return 0x02;
+        }
+
+        let (first, second) = if self.iobit { (0, 1) } else { (2, 3) };
+
+        self.pads[first].clock() | (self.pads[second].clock() << 1)
+    }
+
+    fn set_iobit(&mut self, level: bool) {
+        self.iobit = level;
+    }
+
+    fn set_input(&mut self, input: &PortInput) {
+        for (pad, buttons) in self.pads.iter_mut().zip(input.buttons.iter()) {
+            pad.buttons = buttons & 0xFFF0;
+        }
+    }
+}
+
+/// The SNES Mouse: a 32 bit report of buttons, sensitivity, signature and sign/magnitude motion.
+/// Clocking it while latched cycles through the three sensitivity settings.
+pub struct Mouse {
+    buttons: u16,
+    dx: i32,
+    dy: i32,
+    sensitivity: u8,
+    shift: ShiftRegister,
+    latched: bool,
+}
+
+impl Mouse {
+    pub fn new() -> Mouse {
+        Mouse {
+            buttons: 0,
+            dx: 0,
+            dy: 0,
+            sensitivity: 0,
+            shift: ShiftRegister::new(),
+            latched: false,
+        }
+    }
+
+    fn report(&self) -> u32 {
+        let scale = |delta: i32| -> u32 {
+            let magnitude = (delta.abs() * (self.sensitivity as i32 + 1)).min(0x7F) as u32;
+            let sign = if delta < 0 { 0x80 } else { 0 };
+
+            sign | magnitude
+        };
+
+        let right = if self.buttons & MOUSE_RIGHT != 0 { 0x80 } else { 0 };
+        let left = if self.buttons & MOUSE_LEFT != 0 { 0x40 } else { 0 };
+        let status = right | left | ((self.sensitivity as u32) << 4) | 0x01;
+
+        (status << 16) | (scale(self.dy) << 8) | scale(self.dx)
+    }
+}
+
+impl Controller for Mouse {
+    fn set_latch(&mut self, latch: bool) {
+        if latch && !self.latched {
+            let report = self.report();
+
+            self.shift.load(report, 32);
+            self.dx = 0;
+            self.dy = 0;
+        }
+
+        self.latched = latch;
+    }
+
+    fn clock(&mut self) -> u8 {
+        if self.latched {
+            self.sensitivity = (self.sensitivity + 1) % 3;
+            return 0;
+        }
+
+        self.shift.shift()
+    }
+
+    fn set_input(&mut self, input: &PortInput) {
+        self.buttons = input.buttons[0];
+        self.dx += input.x as i32;
+        self.dy += input.y as i32;
+    }
+}
+
+/// The Super Scope light gun. Fire only reports on a fresh pull unless the turbo switch is on.
+pub struct SuperScope {
+    buttons: u16,
+    previous: u16,
+    turbo: bool,
+    x: i16,
+    y: i16,
+    shift: ShiftRegister,
+    latched: bool,
+}
+
+impl SuperScope {
+    pub fn new() -> SuperScope {
+        SuperScope {
+            buttons: 0,
+            previous: 0,
+            turbo: false,
+            x: -1,
+            y: -1,
+            shift: ShiftRegister::new(),
+            latched: false,
+        }
+    }
+
+    fn offscreen(&self) -> bool {
+        self.x < 0 || self.y < 0 || self.x >= 256 || self.y >= 240
+    }
+
+    fn report(&mut self) -> u32 {
+        let pressed = self.buttons & !self.previous;
+
+        if pressed & SCOPE_TURBO != 0 {
+            self.turbo = !self.turbo;
+        }
+
+        let fire = self.buttons & SCOPE_FIRE != 0 && (self.turbo || pressed & SCOPE_FIRE != 0);
+        let cursor = self.buttons & SCOPE_CURSOR != 0;
+        let pause = pressed & SCOPE_PAUSE != 0;
+
+        self.previous = self.buttons;
+
+        let mut report = 0x00FF;
+
+        if fire { report |= 0x8000; }
+        if cursor { report |= 0x4000; }
+        if self.turbo { report |= 0x2000; }
+        if pause { report |= 0x1000; }
+        if self.offscreen() { report |= 0x0200; }
+
+        report
+    }
+}
+
+impl Controller for SuperScope {
+    fn set_latch(&mut self, latch: bool) {
+        if latch && !self.latched {
+            let report = self.report();
+            self.shift.load(report, 16);
+        }
+
+        self.latched = latch;
+    }
+
+    fn clock(&mut self) -> u8 {
+        if self.latched {
+            return self.shift.peek();
+        }
+
+        self.shift.shift()
+    }
+
+    fn set_input(&mut self, input: &PortInput) {
+        self.buttons = input.buttons[0];
+        self.x = input.x;
+        self.y = input.y;
+    }
+
+    fn light_position(&self) -> Option<(u16, u16)> {
+        if self.offscreen() {
+            None
+        } else {
+            Some((self.x as u16, self.y as u16))
+        }
+    }
+}
+
+/// Konami's Justifier, with a single gun attached
+pub struct Justifier {
+    buttons: u16,
+    x: i16,
+    y: i16,
+    shift: ShiftRegister,
+    latched: bool,
+}
+
+impl Justifier {
+    pub fn new() -> Justifier {
+        Justifier {
+            buttons: 0,
+            x: -1,
+            y: -1,
+            shift: ShiftRegister::new(),
+            latched: false,
+        }
+    }
+
+    // 12 zero bits, the 0xE and 0x55 signatures, then triggers, starts and the active gun
+    fn report(&self) -> u32 {
+        let trigger = if self.buttons & JUSTIFIER_TRIGGER != 0 { 0x80 } else { 0 };
+        let start = if self.buttons & JUSTIFIER_START != 0 { 0x20 } else { 0 };
+
+        (0xE << 16) | (0x55 << 8) | trigger | start
+    }
+}
+
+impl Controller for Justifier {
+    fn set_latch(&mut self, latch: bool) {
+        if latch && !self.latched {
+            let report = self.report();
+            self.shift.load(report, 32);
+        }
+
+        self.latched = latch;
+    }
+
+    fn clock(&mut self) -> u8 {
+        if self.latched {
+            return self.shift.peek();
+        }
+
+        self.shift.shift()
+    }
+
+    fn set_input(&mut self, input: &PortInput) {
+        self.buttons = input.buttons[0];
+        self.x = input.x;
+        self.y = input.y;
+    }
+
+    fn light_position(&self) -> Option<(u16, u16)> {
+        if self.x < 0 || self.y < 0 || self.x >= 256 || self.y >= 240 {
+            None
+        } else {
+            Some((self.x as u16, self.y as u16))
+        }
+    }
+}
@@ -1,1267 +1,1035 @@
-use memory::Memory;
+use bus::Bus;
+use error::Error;
 use modes::*;
 use modes::InstructionType::*;
+use state::{SectionReader, SectionWriter};
 use std::fmt;
 
 const IS_BYTE: bool = true;
 
+// Base cycle counts per opcode, assuming 8-bit registers and no page/bank penalties
+const CYCLES: [u8; 256] = [
+    7, 6, 7, 4, 5, 3, 5, 6, 3, 2, 2, 4, 6, 4, 6, 5, // 0x00
+    2, 5, 5, 7, 5, 4, 6, 6, 2, 4, 2, 2, 6, 4, 7, 5, // 0x10
+    6, 6, 8, 4, 3, 3, 5, 6, 4, 2, 2, 5, 4, 4, 6, 5, // 0x20
+    2, 5, 5, 7, 4, 4, 6, 6, 2, 4, 2, 2, 4, 4, 7, 5, // 0x30
+    6, 6, 2, 4, 7, 3, 5, 6, 3, 2, 2, 3, 3, 4, 6, 5, // 0x40
+    2, 5, 5, 7, 7, 4, 6, 6, 2, 4, 3, 2, 4, 4, 7, 5, // 0x50
+    6, 6, 6, 4, 3, 3, 5, 6, 4, 2, 2, 6, 5, 4, 6, 5, // 0x60
+    2, 5, 5, 7, 4, 4, 6, 6, 2, 4, 4, 2, 6, 4, 7, 5, // 0x70
+    3, 6, 4, 4, 3, 3, 3, 6, 2, 2, 2, 3, 4, 4, 4, 5, // 0x80
+    2, 6, 5, 7, 4, 4, 4, 6, 2, 5, 2, 2, 4, 5, 5, 5, // 0x90
+    2, 6, 2, 4, 3, 3, 3, 6, 2, 2, 2, 4, 4, 4, 4, 5, // 0xA0
+    2, 5, 5, 7, 4, 4, 4, 6, 2, 4, 2, 2, 4, 4, 4, 5, // 0xB0
+    2, 6, 3, 4, 3, 3, 5, 6, 2, 2, 2, 3, 4, 4, 6, 5, // 0xC0
+    2, 5, 5, 7, 6, 4, 6, 6, 2, 4, 3, 3, 6, 4, 7, 5, // 0xD0
+    2, 6, 3, 4, 3, 3, 5, 6, 2, 2, 2, 3, 4, 4, 6, 5, // 0xE0
+    2, 5, 5, 7, 5, 4, 6, 6, 2, 4, 4, 2, 8, 4, 7, 5, // 0xF0
+];
+
 macro_rules! decode_op_and_execute {
     ($op:expr, $this:ident, $mem:ident) => (
         match $op {
             0x00 => {
-                print!("opcode {:x} brk ", $op);
                 let mode = StackPush;
                 $this.brk(&mode, $mem);
             },
             0x01 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.ora(&mode, $mem);
             },
             0x02 => {
-                unreachable!("COP is not used by the SNES");
+                // COP is for coprocessors the SNES doesn't have
+                $this.unimplemented("cop");
             },
             0x03 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = StackRelative;
                 $this.ora(&mode, $mem);
             },
             0x04 => {
-                print!("opcode {:x} tsb ", $op);
                 let mode = DirectPage;
                 $this.tsb(&mode, $mem);
             },
             0x05 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = DirectPage;
                 $this.ora(&mode, $mem);
             },
             0x06 => {
-                print!("opcode {:x} asl ", $op);
                 let mode = DirectPage;
                 $this.asl(&mode, $mem);
             },
             0x07 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.ora(&mode, $mem);
             },
             0x08 => {
-                print!("opcode {:x} php ", $op);
                 let mode = StackPush;
                 $this.php(&mode, $mem);
             },
             0x09 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = Immediate;
                 $this.ora(&mode, $mem);
             },
             0x0A => {
-                print!("opcode {:x} asl ", $op);
                 let mode = Accumulator;
                 $this.asl(&mode, $mem);
             },
             0x0B => {
-                print!("opcode {:x} phd ", $op);
                 let mode = StackPush;
                 $this.phd(&mode, $mem);
             },
             0x0C => {
-                print!("opcode {:x} tsb ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.tsb(&mode, $mem);
             },
             0x0D => {
-                print!("opcode {:x} ora ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.ora(&mode, $mem);
             },
             0x0E => {
-                print!("opcode {:x} asl ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.asl(&mode, $mem);
             },
             0x0F => {
-                print!("opcode {:x} ora ", $op);
                 let mode = AbsoluteLong { instruction_type : LocatingData };
                 $this.ora(&mode, $mem);
             },
             0x10 => {
-                print!("opcode {:x} bpl ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bpl(&mode, $mem);
             },
             0x11 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.ora(&mode, $mem);
             },
             0x12 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = DirectPageIndirect;
                 $this.ora(&mode, $mem);
             },
             0x13 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.ora(&mode, $mem);
             },
             0x14 => {
-                print!("opcode {:x} trb ", $op);
                 let mode = DirectPage;
                 $this.trb(&mode, $mem);
             },
             0x15 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = DirectPageIndexedX;
                 $this.ora(&mode, $mem);
             },
             0x16 => {
-                print!("opcode {:x} asl ", $op);
                 let mode = DirectPageIndexedX;
                 $this.asl(&mode, $mem);
             },
             0x17 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.ora(&mode, $mem);
             },
             0x18 => {
-                print!("opcode {:x} clc ", $op);
                 $this.clc();
             },
             0x19 => {
-                print!("opcode {:x} ora ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.ora(&mode, $mem);
             },
             0x1A => {
-                print!("opcode {:x} inc ", $op);
                 let mode = Accumulator;
                 $this.inc(&mode, $mem);
             },
             0x1B => {
-                print!("opcode {:x} tcs ", $op);
                 $this.tcs();
             },
             0x1C => {
-                print!("opcode {:x} trb ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.trb(&mode, $mem);
             },
             0x1D => {
-                print!("opcode {:x} ora ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.ora(&mode, $mem);
             },
             0x1E => {
-                print!("opcode {:x} asl ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.asl(&mode, $mem);
             },
             0x1F => {
-                print!("opcode {:x} ora ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.ora(&mode, $mem);
             },
             0x20 => {
-                print!("opcode {:x} jsr ", $op);
                 let mode = Absolute { instruction_type: ControlTransfer };
                 $this.jsr(&mode, $mem, false);
             },
             0x21 => {
-                print!("opcode {:x} and ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.and(&mode, $mem);
             },
             0x22 => {
-                print!("opcode {:x} jsr ", $op);
                 let mode = AbsoluteLong { instruction_type: ControlTransfer };
                 $this.jsr(&mode, $mem, true);
             },
             0x23 => {
-                print!("opcode {:x} and ", $op);
                 let mode = StackRelative;
                 $this.and(&mode, $mem);
             },
             0x24 => {
-                print!("opcode {:x} bit ", $op);
                 let mode = DirectPage;
                 $this.bit(&mode, $mem);
             },
             0x25 => {
-                print!("opcode {:x} and ", $op);
                 let mode = DirectPage;
                 $this.and(&mode, $mem);
             },
             0x26 => {
-                print!("opcode {:x} rol ", $op);
                 let mode = DirectPage;
                 $this.rol(&mode, $mem);
             },
             0x27 => {
-                print!("opcode {:x} and ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.and(&mode, $mem);
             },
             0x28 => {
-                print!("opcode {:x} plp ", $op);
                 let mode = StackPull;
                 $this.plp(&mode, $mem);
             },
             0x29 => {
-                print!("opcode {:x} and ", $op);
                 let mode = Immediate;
                 $this.and(&mode, $mem);
             },
             0x2A => {
-                print!("opcode {:x} rol ", $op);
                 let mode = Accumulator;
                 $this.rol(&mode, $mem);
             },
             0x2B => {
-                print!("opcode {:x} pld ", $op);
                 let mode = StackPull;
                 $this.pld(&mode, $mem);
             },
             0x2C => {
-                print!("opcode {:x} bit ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.bit(&mode, $mem);
             },
             0x2D => {
-                print!("opcode {:x} and ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.and(&mode, $mem);
             },
             0x2E => {
-                print!("opcode {:x} rol ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.rol(&mode, $mem);
             },
             0x2F => {
-                print!("opcode {:x} and ", $op);
                 let mode = AbsoluteLong { instruction_type: LocatingData };
                 $this.and(&mode, $mem);
             },
             0x30 => {
-                print!("opcode {:x} bmi ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bmi(&mode, $mem);
             },
             0x31 => {
-                print!("opcode {:x} and ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.and(&mode, $mem);
             },
             0x32 => {
-                print!("opcode {:x} and ", $op);
                 let mode = DirectPageIndirect;
                 $this.and(&mode, $mem);
             },
             0x33 => {
-                print!("opcode {:x} and ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.and(&mode, $mem);
             },
             0x34 => {
-                print!("opcode {:x} bit ", $op);
                 let mode = DirectPageIndexedX;
                 $this.bit(&mode, $mem);
             },
             0x35 => {
-                print!("opcode {:x} and ", $op);
                 let mode = DirectPageIndexedX;
                 $this.and(&mode, $mem);
             },
             0x36 => {
-                print!("opcode {:x} rol ", $op);
                 let mode = DirectPageIndexedX;
                 $this.rol(&mode, $mem);
             },
             0x37 => {
-                print!("opcode {:x} and ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.and(&mode, $mem);
             },
             0x38 => {
-                print!("opcode {:x} sec ", $op);
                 $this.sec();
             },
             0x39 => {
-                print!("opcode {:x} and ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.and(&mode, $mem);
             },
             0x3A => {
-                print!("opcode {:x} dec ", $op);
                 let mode = Accumulator;
                 $this.dec(&mode, $mem);
             },
             0x3B => {
-                print!("opcode {:x} tsc ", $op);
                 $this.tsc();
             },
             0x3C => {
-                print!("opcode {:x} bit ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.bit(&mode, $mem);
             },
             0x3D => {
-                print!("opcode {:x} and ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.and(&mode, $mem);
             },
             0x3E => {
-                print!("opcode {:x} rol ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.rol(&mode, $mem);
             },
             0x3F => {
-                print!("opcode {:x} and ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.and(&mode, $mem);
             },
             0x40 => {
-                print!("opcode {:x} rti ", $op);
                 let mode = StackRTI;
                 $this.rti(&mode, $mem);
             },
             0x41 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.eor(&mode, $mem);
             },
             0x42 => {
-                print!("opcode {:x} wdm ", $op);
                 $this.wdm();
             },
             0x43 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = StackRelative;
                 $this.eor(&mode, $mem);
             },
             0x44 => {
-                print!("opcode {:x} mvp ", $op);
                 let mode = BlockMove;
                 $this.mvp(&mode, $mem);
             },
             0x45 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = DirectPage;
                 $this.eor(&mode, $mem);
             },
             0x46 => {
-                print!("opcode {:x} lsr ", $op);
                 let mode = DirectPage;
                 $this.lsr(&mode, $mem);
             },
             0x47 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.eor(&mode, $mem);
             },
             0x48 => {
-                print!("opcode {:x} pha ", $op);
                 let mode = StackPush;
                 $this.pha(&mode, $mem);
             },
             0x49 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = Immediate;
                 $this.eor(&mode, $mem);
             },
             0x4A => {
-                print!("opcode {:x} lsr ", $op);
                 let mode = Accumulator;
                 $this.lsr(&mode, $mem);
             },
             0x4B => {
-                print!("opcode {:x} phk ", $op);
                 let mode = StackPush;
                 $this.phk(&mode, $mem);
             },
             0x4C => {
-                print!("opcode {:x} jmp ", $op);
                 let mode = Absolute { instruction_type: ControlTransfer };
                 $this.jmp(&mode, $mem);
             },
             0x4D => {
-                print!("opcode {:x} eor ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.eor(&mode, $mem);
             },
             0x4E => {
-                print!("opcode {:x} lsr ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.lsr(&mode, $mem);
             },
             0x4F => {
-                print!("opcode {:x} eor ", $op);
                 let mode = AbsoluteLong { instruction_type: LocatingData };
                 $this.eor(&mode, $mem);
             },
             0x50 => {
-                print!("opcode {:x} bvc ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bvc(&mode, $mem);
             },
             0x51 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.eor(&mode, $mem);
             },
             0x52 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = DirectPageIndirect;
                 $this.eor(&mode, $mem);
             },
             0x53 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.eor(&mode, $mem);
             },
             0x54 => {
-                print!("opcode {:x} mvn ", $op);
                 let mode = BlockMove;
                 $this.mvn(&mode, $mem);
             },
             0x55 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = DirectPageIndexedX;
                 $this.eor(&mode, $mem);
             },
             0x56 => {
-                print!("opcode {:x} lsr ", $op);
                 let mode = DirectPageIndexedX;
                 $this.lsr(&mode, $mem);
             },
             0x57 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.eor(&mode, $mem);
             },
             0x58 => {
-                print!("opcode {:x} cli ", $op);
                 $this.cli();
             },
             0x59 => {
-                print!("opcode {:x} eor ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.eor(&mode, $mem);
             },
             0x5A => {
-                print!("opcode {:x} phy ", $op);
                 let mode = StackPush;
                 $this.phy(&mode, $mem);
             },
             0x5B => {
-                print!("opcode {:x} tcd ", $op);
                 $this.tcd();
             },
             0x5C => {
-                print!("opcode {:x} jmp ", $op);
                 let mode = AbsoluteLong { instruction_type: ControlTransfer };
                 $this.jmp(&mode, $mem);
             },
             0x5D => {
-                print!("opcode {:x} eor ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.eor(&mode, $mem);
             },
             0x5E => {
-                print!("opcode {:x} lsr ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.lsr(&mode, $mem);
             },
             0x5F => {
-                print!("opcode {:x} eor ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.eor(&mode, $mem);
             },
             0x60 => {
-                print!("opcode {:x} rts ", $op);
                 let mode = StackRTS;
                 $this.rts(&mode, $mem);
             },
             0x61 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.adc(&mode, $mem);
             },
             0x62 => {
-                print!("opcode {:x} per ", $op);
                 let mode = StackProgramCounterRelative;
                 $this.per(&mode, $mem);
             },
             0x63 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = StackRelative;
                 $this.adc(&mode, $mem);
             },
             0x64 => {
-                print!("opcode {:x} stz ", $op);
                 let mode = DirectPage;
                 $this.stz(&mode, $mem);
             },
             0x65 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = DirectPage;
                 $this.adc(&mode, $mem);
             },
             0x66 => {
-                print!("opcode {:x} ror ", $op);
                 let mode = DirectPage;
                 $this.ror(&mode, $mem);
             },
             0x67 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.adc(&mode, $mem);
             },
             0x68 => {
-                print!("opcode {:x} pla ", $op);
                 let mode = StackPull;
                 $this.pla(&mode, $mem);
             },
             0x69 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = Immediate;
                 $this.adc(&mode, $mem);
             },
             0x6A => {
-                print!("opcode {:x} ror ", $op);
                 let mode = Accumulator;
                 $this.ror(&mode, $mem);
             },
             0x6B => {
-                print!("opcode {:x} rtl ", $op);
                 let mode = StackRTL;
                 $this.rtl(&mode, $mem);
             },
             0x6C => {
-                print!("opcode {:x} jmp ", $op);
                 let mode = AbsoluteIndirect;
                 $this.jmp(&mode, $mem);
             },
             0x6D => {
-                print!("opcode {:x} adc ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.adc(&mode, $mem);
             },
             0x6E => {
-                print!("opcode {:x} ror ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.ror(&mode, $mem);
             },
             0x6F => {
-                print!("opcode {:x} adc ", $op);
                 let mode = AbsoluteLong { instruction_type: LocatingData };
                 $this.adc(&mode, $mem);
             },
             0x70 => {
-                print!("opcode {:x} bvs ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bvs(&mode, $mem);
             },
             0x71 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.adc(&mode, $mem);
             },
             0x72 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = DirectPageIndirect;
                 $this.adc(&mode, $mem);
             },
             0x73 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.adc(&mode, $mem);
             },
             0x74 => {
-                print!("opcode {:x} stz ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.stz(&mode, $mem);
             },
             0x75 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = DirectPageIndexedX;
                 $this.adc(&mode, $mem);
             },
             0x76 => {
-                print!("opcode {:x} ror ", $op);
                 let mode = DirectPageIndexedX;
                 $this.ror(&mode, $mem);
             },
             0x77 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.adc(&mode, $mem);
             },
             0x78 => {
-                print!("opcode {:x} sei ", $op);
                 $this.sei();
             },
             0x79 => {
-                print!("opcode {:x} adc ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.adc(&mode, $mem);
             },
             0x7A => {
-                print!("opcode {:x} ply ", $op);
                 let mode = StackPull;
                 $this.ply(&mode, $mem);
             },
             0x7B => {
-                print!("opcode {:x} tdc ", $op);
                 $this.tdc();
             },
             0x7C => {
-                print!("opcode {:x} jmp ", $op);
                 let mode = AbsoluteIndexedIndirect;
                 $this.jmp(&mode, $mem);
             },
             0x7D => {
-                print!("opcode {:x} adc ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.adc(&mode, $mem);
             },
             0x7E => {
-                print!("opcode {:x} ror ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.ror(&mode, $mem);
             },
             0x7F => {
-                print!("opcode {:x} adc ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.adc(&mode, $mem);
             },
             0x80 => {
-                print!("opcode {:x} bra ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bra(&mode, $mem);
             },
             0x81 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.sta(&mode, $mem);
             },
             0x82 => {
-                print!("opcode {:x} brl ", $op);
                 let mode = ProgramCounterRelativeLong;
                 $this.brl(&mode, $mem);
             },
             0x83 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = StackRelative;
                 $this.sta(&mode, $mem);
             },
             0x84 => {
-                print!("opcode {:x} sty ", $op);
                 let mode = DirectPage;
                 $this.sty(&mode, $mem);
             },
             0x85 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = DirectPage;
                 $this.sta(&mode, $mem);
             },
             0x86 => {
-                print!("opcode {:x} stx ", $op);
                 let mode = DirectPage;
                 $this.stx(&mode, $mem);
             },
             0x87 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.sta(&mode, $mem);
             },
             0x88 => {
-                print!("opcode {:x} dey ", $op);
                 $this.dey();
             },
             0x89 => {
-                print!("opcode {:x} bit ", $op);
                 let mode = Immediate;
                 $this.bit(&mode, $mem);
             },
             0x8A => {
-                print!("opcode {:x} txa ", $op);
                 $this.txa();
             },
             0x8B => {
-                print!("opcode {:x} phb ", $op);
                 let mode = StackPush;
                 $this.phb(&mode, $mem);
             },
             0x8C => {
-                print!("opcode {:x} sty ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.sty(&mode, $mem);
             },
             0x8D => {
-                print!("opcode {:x} sta ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.sta(&mode, $mem);
             },
             0x8E => {
-                print!("opcode {:x} stx ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.stx(&mode, $mem);
             },
             0x8F => {
-                print!("opcode {:x} sta ", $op);
                 let mode = AbsoluteLong { instruction_type: LocatingData };
                 $this.sta(&mode, $mem);
             },
             0x90 => {
-                print!("opcode {:x} bcc ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bcc(&mode, $mem);
             },
             0x91 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.sta(&mode, $mem);
             },
             0x92 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = DirectPageIndirect;
                 $this.sta(&mode, $mem);
             },
             0x93 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.sta(&mode, $mem);
             },
             0x94 => {
-                print!("opcode {:x} sty ", $op);
                 let mode = DirectPageIndexedX;
                 $this.sty(&mode, $mem);
             },
             0x95 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = DirectPageIndexedX;
                 $this.sta(&mode, $mem);
             },
             0x96 => {
-                print!("opcode {:x} stx ", $op);
                 let mode = DirectPageIndexedY;
                 $this.stx(&mode, $mem);
             },
             0x97 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.sta(&mode, $mem);
             },
             0x98 => {
-                print!("opcode {:x} tya ", $op);
                 $this.tya();
             },
             0x99 => {
-                print!("opcode {:x} sta ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.sta(&mode, $mem);
             },
             0x9A => {
-                print!("opcode {:x} txs ", $op);
                 $this.txs();
             },
             0x9B => {
-                print!("opcode {:x} txy ", $op);
                 $this.txy();
             },
             0x9C => {
-                print!("opcode {:x} stz ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.stz(&mode, $mem);
             },
             0x9D => {
-                print!("opcode {:x} sta ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.sta(&mode, $mem);
             },
             0x9E => {
-                print!("opcode {:x} stz ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.stz(&mode, $mem);
             },
             0x9F => {
-                print!("opcode {:x} sta ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.sta(&mode, $mem);
             },
             0xA0 => {
-                print!("opcode {:x} ldy ", $op);
                 let mode = Immediate;
                 $this.ldy(&mode, $mem);
             },
             0xA1 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.lda(&mode, $mem);
             },
             0xA2 => {
-                print!("opcode {:x} ldx ", $op);
                 let mode = Immediate;
                 $this.ldx(&mode, $mem);
             },
             0xA3 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = StackRelative;
                 $this.lda(&mode, $mem);
             },
             0xA4 => {
-                print!("opcode {:x} ldy ", $op);
                 let mode = DirectPage;
                 $this.ldy(&mode, $mem);
             },
             0xA5 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = DirectPage;
                 $this.lda(&mode, $mem);
             },
             0xA6 => {
-                print!("opcode {:x} ldx ", $op);
                 let mode = DirectPage;
                 $this.ldx(&mode, $mem);
             },
             0xA7 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.lda(&mode, $mem);
             },
             0xA8 => {
-                print!("opcode {:x} tay ", $op);
                 $this.tay();
             },
             0xA9 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = Immediate;
                 $this.lda(&mode, $mem);
             },
             0xAA => {
-                print!("opcode {:x} tax ", $op);
                 $this.tax();
             },
             0xAB => {
-                print!("opcode {:x} plb ", $op);
                 let mode = StackPull;
                 $this.plb(&mode, $mem);
             },
             0xAC => {
-                print!("opcode {:x} ldy ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.ldy(&mode, $mem);
             },
             0xAD => {
-                print!("opcode {:x} lda ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.lda(&mode, $mem);
             },
             0xAE => {
-                print!("opcode {:x} ldx ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.ldx(&mode, $mem);
             },
             0xAF => {
-                print!("opcode {:x} lda ", $op);
                 let mode = AbsoluteLong { instruction_type: LocatingData };
                 $this.lda(&mode, $mem);
             },
             0xB0 => {
-                print!("opcode {:x} bcs ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bcs(&mode, $mem);
             },
             0xB1 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.lda(&mode, $mem);
             },
             0xB2 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = DirectPageIndirect;
                 $this.lda(&mode, $mem);
             },
             0xB3 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.lda(&mode, $mem);
             },
             0xB4 => {
-                print!("opcode {:x} ldy ", $op);
                 let mode = DirectPageIndexedX;
                 $this.ldy(&mode, $mem);
             },
             0xB5 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = DirectPageIndexedX;
                 $this.lda(&mode, $mem);
             },
             0xB6 => {
-                print!("opcode {:x} ldx ", $op);
                 let mode = DirectPageIndexedY;
                 $this.ldx(&mode, $mem);
             },
             0xB7 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.lda(&mode, $mem);
             },
             0xB8 => {
-                print!("opcode {:x} clv ", $op);
                 $this.clv();
             },
             0xB9 => {
-                print!("opcode {:x} lda ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.lda(&mode, $mem);
             },
             0xBA => {
-                print!("opcode {:x} tsx ", $op);
                 $this.tsx();
             },
             0xBB => {
-                print!("opcode {:x} tyx ", $op);
                 $this.tyx();
             },
             0xBC => {
-                print!("opcode {:x} ldy ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.ldy(&mode, $mem);
             },
             0xBD => {
-                print!("opcode {:x} lda ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.lda(&mode, $mem);
             },
             0xBE => {
-                print!("opcode {:x} ldx ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.ldx(&mode, $mem);
             },
             0xBF => {
-                print!("opcode {:x} lda ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.lda(&mode, $mem);
             },
             0xC0 => {
-                print!("opcode {:x} cpy ", $op);
                 let mode = Immediate;
                 $this.cpy(&mode, $mem);
             },
             0xC1 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.cmp(&mode, $mem);
             },
             0xC2 => {
-                print!("opcode {:x} rep ", $op);
                 let mode = Immediate;
                 $this.rep(&mode, $mem);
             },
             0xC3 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = StackRelative;
                 $this.cmp(&mode, $mem);
             },
             0xC4 => {
-                print!("opcode {:x} cpy ", $op);
                 let mode = DirectPage;
                 $this.cpy(&mode, $mem);
             },
             0xC5 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = DirectPage;
                 $this.cmp(&mode, $mem);
             },
             0xC6 => {
-                print!("opcode {:x} dec ", $op);
                 let mode = DirectPage;
                 $this.dec(&mode, $mem);
             },
             0xC7 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.cmp(&mode, $mem);
             },
             0xC8 => {
-                print!("opcode {:x} iny ", $op);
                 $this.iny();
             },
             0xC9 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = Immediate;
                 $this.cmp(&mode, $mem);
             },
             0xCA => {
-                print!("opcode {:x} dex ", $op);
                 $this.dex();
             },
             0xCB => {
-                print!("opcode {:x} wai ", $op);
                 $this.wai();
             },
             0xCC => {
-                print!("opcode {:x} cpy ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.cpy(&mode, $mem);
             },
             0xCD => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.cmp(&mode, $mem);
             },
             0xCE => {
-                print!("opcode {:x} dec ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.dec(&mode, $mem);
             },
             0xCF => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = AbsoluteLong { instruction_type: LocatingData };
                 $this.cmp(&mode, $mem);
             },
             0xD0 => {
-                print!("opcode {:x} bne ", $op);
                 let mode = ProgramCounterRelative;
                 $this.bne(&mode, $mem);
             },
             0xD1 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.cmp(&mode, $mem);
             },
             0xD2 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = DirectPageIndirect;
                 $this.cmp(&mode, $mem);
             },
             0xD3 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.cmp(&mode, $mem);
             },
             0xD4 => {
-                print!("opcode {:x} pei ", $op);
                 let mode = StackDirectPageIndirect;
                 $this.pei(&mode, $mem);
             },
             0xD5 => {
-                    print!("opcode {:x} cmp ", $op);
                 let mode = DirectPageIndexedX;
                 $this.cmp(&mode, $mem)
             },
             0xD6 => {
-                print!("opcode {:x} dec ", $op);
                 let mode = DirectPageIndexedX;
                 $this.dec(&mode, $mem);
             },
             0xD7 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.cmp(&mode, $mem);
             },
             0xD8 => {
-                print!("opcode {:x} cld ", $op);
                 $this.cld();
             },
             0xD9 => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.cmp(&mode, $mem);
             },
             0xDA => {
-                print!("opcode {:x} phx ", $op);
                 let mode = StackPush;
                 $this.phx(&mode, $mem);
             },
             0xDB => {
-                print!("opcode {:x} stp ", $op);
                 $this.stp();
             },
             0xDC => {
-                print!("opcode {:x} jmp ", $op);
                 let mode = AbsoluteIndirectLong;
                 $this.jmp(&mode, $mem);
             },
             0xDD => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.cmp(&mode, $mem);
             },
             0xDE => {
-                print!("opcode {:x} dec ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.dec(&mode, $mem);
             },
             0xDF => {
-                print!("opcode {:x} cmp ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.cmp(&mode, $mem);
             },
             0xE0 => {
-                print!("opcode {:x} cpx ", $op);
                 let mode = Immediate;
                 $this.cpx(&mode, $mem);
             },
             0xE1 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPageIndexedIndirectX;
                 $this.sbc(&mode, $mem);
             },
             0xE2 => {
-                print!("opcode {:x} sep ", $op);
                 let mode = Immediate;
                 $this.sep(&mode, $mem);
             },
             0xE3 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPage;
                 $this.sbc(&mode, $mem);
             },
             0xE4 => {
-                print!("opcode {:x} cpx ", $op);
                 let mode = DirectPage;
                 $this.cpx(&mode, $mem);
             },
             0xE5 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPage;
                 $this.sbc(&mode, $mem);
             },
             0xE6 => {
-                print!("opcode {:x} inc ", $op);
                 let mode = DirectPage;
                 $this.inc(&mode, $mem);
             },
             0xE7 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPageIndirectLong;
                 $this.sbc(&mode, $mem);
             },
             0xE8 => {
-                print!("opcode {:x} inx ", $op);
                 $this.inx();
             },
             0xE9 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = Immediate;
                 $this.sbc(&mode, $mem);
             },
             0xEA => {
-                print!("opcode {:x} nop ", $op);
                 $this.nop();
             },
             0xEB => {
-                print!("opcode {:x} xba ", $op);
                 $this.xba();
             },
             0xEC => {
-                print!("opcode {:x} cpx ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.cpx(&mode, $mem);
             },
             0xED => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.sbc(&mode, $mem);
             },
             0xEE => {
-                print!("opcode {:x} inc ", $op);
                 let mode = Absolute { instruction_type: LocatingData };
                 $this.inc(&mode, $mem);
             },
             0xEF => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = AbsoluteLong { instruction_type: LocatingData };
                 $this.sbc(&mode, $mem);
             },
             0xF0 => {
-                print!("opcode {:x} beq ", $op);
                 let mode = ProgramCounterRelative;
                 $this.beq(&mode, $mem);
             },
             0xF1 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPageIndirectIndexedY;
                 $this.sbc(&mode, $mem);
             },
             0xF2 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPageIndirect;
                 $this.sbc(&mode, $mem);
             },
             0xF3 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = StackRelativeIndirectIndexedY;
                 $this.sbc(&mode, $mem);
             },
             0xF4 => {
-                print!("opcode {:x} pea ", $op);
                 let mode = StackAbsolute;
                 $this.pea(&mode, $mem);
             },
             0xF5 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPageIndexedX;
                 $this.sbc(&mode, $mem);
             },
             0xF6 => {
-                print!("opcode {:x} inc ", $op);
                 let mode = DirectPageIndexedX;
                 $this.inc(&mode, $mem);
             },
             0xF7 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = DirectPageIndirectLongIndexedY;
                 $this.sbc(&mode, $mem);
             },
             0xF8 => {
-                print!("opcode {:x} sed ", $op);
                 $this.sed();
             },
             0xF9 => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = AbsoluteIndexedY;
                 $this.sbc(&mode, $mem);
             },
             0xFA => {
-                print!("opcode {:x} plx ", $op);
                 let mode = StackPull;
                 $this.plx(&mode, $mem);
             },
             0xFB => {
-                print!("opcode {:x} xce ", $op);
                 $this.xce();
             },
             0xFC => {
-                print!("opcode {:x} jsr ", $op);
                 let mode = AbsoluteIndexedIndirect;
                 $this.jsr(&mode, $mem, false);
             },
             0xFD => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.sbc(&mode, $mem);
             },
             0xFE => {
-                print!("opcode {:x} inc ", $op);
                 let mode = AbsoluteIndexedX;
                 $this.inc(&mode, $mem);
             },
             0xFF => {
-                print!("opcode {:x} sbc ", $op);
                 let mode = AbsoluteLongIndexedX;
                 $this.sbc(&mode, $mem);
             },
-            _ => panic!("{:x} is not an opcode", $op),
         }
     );
 }
 
+#[derive(Clone)]
 pub struct CPU {
     pub accumulator:                    u16,
     pub index_x:                        u16,
@@ -1273,54 +1041,136 @@ pub struct CPU {
     pub processor_status:   ProcessorStatus,
     pub program_counter:              usize,
     pub emulation_mode:                bool,
+    stopped:                           bool, // halted by STP until the next reset
+    fault:                 Option<&'static str>, // what the current instruction needed that isn't emulated
 }
 
 impl CPU {
-	pub fn new(memory: &Memory) -> CPU {
-        use memory::RomType::*;
-
-        let pc = memory.rom.reset_vector;
-        let pb = match memory.rom.rom_type {
-            LoROM | FastLoROM => 0x80,
-            HiROM | FastHiROM => 0xC0,
-            _ => unreachable!(),
-        };
-
-        CPU {
+	pub fn new<B: Bus>(memory: &mut B) -> CPU {
+        let mut cpu = CPU {
             accumulator:                         0,
             index_x:                             0,
             index_y:                             0,
             stack_pointer:                       0,
             data_bank:                           0,
             direct_page:                         0,
-            program_bank:                       pb,
+            program_bank:                        0,
             processor_status:   Default::default(),
-            program_counter:                    pc,
+            program_counter:                     0,
             emulation_mode:                   true,
-        }
+            stopped:                         false,
+            fault:                            None,
+        };
+
+        cpu.reset(memory);
+        cpu
 	}
 
-    pub fn run(&mut self, memory: &mut Memory) {
-        self.run_instruction(memory);
-        //println!("{:?}\n{:?}", self, memory);
+    /// Puts the CPU back in emulation mode and jumps through the reset vector
+    pub fn reset<B: Bus>(&mut self, memory: &mut B) {
+        let (pb, pc) = memory.reset_vector();
+
+        self.accumulator = 0;
+        self.index_x = 0;
+        self.index_y = 0;
+        self.stack_pointer = 0;
+        self.data_bank = 0;
+        self.direct_page = 0;
+        self.program_bank = pb;
+        self.processor_status = Default::default();
+        self.program_counter = pc;
+        self.emulation_mode = true;
+        self.stopped = false;
+    }
+
+    pub fn save_state(&self, out: &mut SectionWriter) {
+        out.write_u16(self.accumulator);
+        out.write_u16(self.index_x);
+        out.write_u16(self.index_y);
+        out.write_u16(self.stack_pointer as u16);
+        out.write_u8(self.data_bank as u8);
+        out.write_u16(self.direct_page as u16);
+        out.write_u8(self.program_bank as u8);
+        out.write_u8(self.processor_status.as_byte());
+        out.write_u16(self.program_counter as u16);
+        out.write_bool(self.emulation_mode);
+        out.write_bool(self.stopped);
+    }
+
+    pub fn load_state(&mut self, input: &mut SectionReader) -> Result<(), String> {
+        self.accumulator = input.read_u16()?;
+        self.index_x = input.read_u16()?;
+        self.index_y = input.read_u16()?;
+        self.stack_pointer = input.read_u16()? as usize;
+        self.data_bank = input.read_u8()? as usize;
+        self.direct_page = input.read_u16()? as usize;
+        self.program_bank = input.read_u8()? as usize;
+        self.processor_status = ProcessorStatus::from_byte(input.read_u8()?);
+        self.program_counter = input.read_u16()? as usize;
+        self.emulation_mode = input.read_bool()?;
+        self.stopped = input.version() >= 2 && input.read_bool()?;
+
+        Ok(())
+    }
+
+    /// Whether STP has halted the CPU
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Runs a single instruction and returns the number of CPU cycles it took. An instruction
+    /// that can't be emulated is abandoned with the program counter left pointing at it.
+    pub fn run<B: Bus>(&mut self, memory: &mut B) -> Result<u32, Error> {
+        let addr = (self.program_bank << 16) | self.program_counter;
+
+        if self.stopped {
+            return Err(Error::CpuStopped { addr: addr });
+        }
+
+        let cycles = self.run_instruction(memory);
+
+        if let Some(what) = self.fault.take() {
+            self.program_bank = addr >> 16;
+            self.program_counter = addr & 0xFFFF;
+            return Err(Error::Unimplemented { what: what, addr: addr });
+        }
+
+        Ok(cycles)
     }
 
-    fn run_instruction(&mut self, memory: &mut Memory) {
+    fn run_instruction<B: Bus>(&mut self, memory: &mut B) -> u32 {
         let addr = (self.program_bank << 16) | self.program_counter;
+        let memory = &mut CountingBus { bus: memory, accesses: 0 };
 
-        let opcode = memory.get_byte(addr);
+        let opcode = memory.read(addr);
 
         self.program_counter = self.program_counter.wrapping_add(1);
         decode_op_and_execute!(opcode, self, memory);
-        println!("at address {:x}", addr);
+
+        // Whatever the cycle count has beyond the accesses made went on internal operations. The
+        // table is for 8-bit registers, so a 16-bit operand can make more accesses than it counts
+        let cycles = CYCLES[opcode as usize] as u32;
+
+        for _ in memory.accesses..cycles {
+            memory.idle();
+        }
+
+        cycles.max(memory.accesses)
     }
 
-    fn brk<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    // Stands in for the parts of the instruction set that aren't written yet
+    pub(crate) fn unimplemented(&mut self, what: &'static str) {
+        if self.fault.is_none() {
+            self.fault = Some(what);
+        }
+    }
+
+    fn brk<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::IndexRegisterSize; // This is the break flag in emulation mode
         use self::StatusFlags::{IRQDisable, Decimal};
 
         let pc = (self.program_counter.wrapping_add(1)) as u32;
-        let interrupt_vector = memory.get_interrupt_vector(self.emulation_mode);
+        let interrupt_vector = memory.break_vector(self.emulation_mode);
 
         if self.emulation_mode {
             self.processor_status.set_flag(IndexRegisterSize, true);
@@ -1335,17 +1185,17 @@ impl CPU {
             mode.store(self, memory, IS_BYTE, pb);
             mode.store(self, memory, !IS_BYTE, pc);
             mode.store(self, memory, IS_BYTE, ps);
-
-            self.program_bank = 0;
         }
 
+        // The vector is in bank 0 in either mode, even when emulation mode runs from a mirror
+        self.program_bank = 0;
         self.processor_status.set_flag(IRQDisable, true);
         self.processor_status.set_flag(Decimal, false); // Cleared "after" the break, but when is "after"?
 
         self.program_counter = interrupt_vector;
     }
 
-    fn ora<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn ora<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{Negative, Zero, AccumulatorRegisterSize};
 
         let emu = self.processor_status.get_flag(AccumulatorRegisterSize);
@@ -1358,27 +1208,27 @@ impl CPU {
         self.processor_status.set_flag(Zero, self.accumulator == 0);
     }
 
-    fn tsb<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("tsb unimplemented")
+    fn tsb<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("tsb")
     }
 
-    fn asl<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("asl unimplemented")
+    fn asl<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("asl")
     }
 
-    fn php<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn php<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let data = self.processor_status.as_byte() as u32;
 
         mode.store(self, memory, IS_BYTE, data);
     }
 
-    fn phd<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn phd<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let data = self.direct_page as u32;
 
         mode.store(self, memory, !IS_BYTE, data);
     }
 
-    fn bpl<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn bpl<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::Negative;
         
         if !self.processor_status.get_flag(Negative) {
@@ -1394,8 +1244,8 @@ impl CPU {
         self.program_counter = self.program_counter.wrapping_add(1);
     }
 
-    fn trb<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("trb unimplemented")
+    fn trb<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("trb")
     }
 
     fn clc(&mut self) {
@@ -1404,7 +1254,7 @@ impl CPU {
         self.processor_status.set_flag(Carry, false);
     }
 
-    fn inc<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn inc<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{AccumulatorRegisterSize, Negative, Zero};
 
         let emu = self.processor_status.get_flag(AccumulatorRegisterSize);
@@ -1422,10 +1272,10 @@ impl CPU {
     }
 
     fn tcs(&mut self) {
-        panic!("tcs unimplemented")
+        self.unimplemented("tcs")
     }
 
-    fn jsr<T: Instruction>(&mut self, mode: &T, memory: &mut Memory, is_long: bool) {
+    fn jsr<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B, is_long: bool) {
 
         // PC starts just past opcode at this point, but before we push it onto the stack
         // it must be pointing at the last byte of the operand, which is either two or three
@@ -1456,23 +1306,23 @@ impl CPU {
     //    println!("jsr addr {:x}{:x}", bank, addr);
     }
 
-    fn and<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("and unimplemented")
+    fn and<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("and")
     }
 
-    fn bit<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("bit unimplemented")
+    fn bit<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("bit")
     }
 
-    fn rol<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("rol unimplemented")
+    fn rol<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("rol")
     }
 
-    fn plp<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("plp unimplemented")
+    fn plp<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("plp")
     }
 
-    fn pld<T: Instruction>(&mut self, mode: &T, memory: &Memory) {
+    fn pld<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{Zero, Negative};
 
         self.direct_page = mode.load(self, memory, !IS_BYTE) as usize;
@@ -1483,43 +1333,43 @@ impl CPU {
         self.processor_status.set_flag(Zero, self.direct_page == 0);
     }
 
-    fn bmi<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("bmi unimplemented")
+    fn bmi<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("bmi")
     }
 
     fn sec(&mut self) {
-        panic!("sec unimplemented")
+        self.unimplemented("sec")
     }
 
-    fn dec<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("dec unimplemented")
+    fn dec<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("dec")
     }
 
     fn tsc(&mut self) {
-        panic!("tsc unimplemented")
+        self.unimplemented("tsc")
     }
 
-    fn rti<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("rti unimplemented")
+    fn rti<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("rti")
     }
 
-    fn eor<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("eor unimplemented")
+    fn eor<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("eor")
     }
 
     fn wdm(&mut self) {
-        panic!("wdm unimplemented")
+        self.unimplemented("wdm")
     }
 
-    fn mvp<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("mvp unimplemented")
+    fn mvp<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("mvp")
     }
 
-    fn lsr<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("lsr unimplemented")
+    fn lsr<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("lsr")
     }
 
-    fn pha<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn pha<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::AccumulatorRegisterSize;
 
         let data = self.accumulator as u32;
@@ -1528,31 +1378,31 @@ impl CPU {
         mode.store(self, memory, emu, data);
     }
 
-    fn phk<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn phk<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let data = self.program_bank as u32;
         mode.store(self, memory, IS_BYTE, data);
     }
 
-    fn jmp<T: Instruction>(&mut self, mode: &T, memory: &Memory) {
+    fn jmp<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let jump_addr = mode.load(self, memory, !IS_BYTE) as usize;
         self.program_counter = jump_addr & 0x00FFFF;
         self.program_bank = (jump_addr & 0xFF0000) >> 16;
     //    println!("jump_addr {:x}", jump_addr);
     }
 
-    fn bvc<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("bvc unimplemented")
+    fn bvc<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("bvc")
     }
 
-    fn mvn<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("mvn unimplemented")
+    fn mvn<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("mvn")
     }
 
     fn cli(&mut self) {
-        panic!("cli unimplemented")
+        self.unimplemented("cli")
     }
 
-    fn phy<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn phy<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::IndexRegisterSize;
 
         let data = self.index_y as u32;
@@ -1570,7 +1420,7 @@ impl CPU {
         self.processor_status.set_flag(Zero, self.accumulator == 0);
     }
 
-    fn rts<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn rts<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let addr = mode.load(self, memory, !IS_BYTE) as usize;
 
         self.program_counter = addr.wrapping_add(1);
@@ -1578,7 +1428,7 @@ impl CPU {
         //println!("rts addr {:x}", addr + 1);
     }
 
-    fn adc<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn adc<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{AccumulatorRegisterSize, Negative, Overflow, Zero, Carry};
 
         let emu = self.processor_status.get_flag(AccumulatorRegisterSize);
@@ -1615,11 +1465,11 @@ impl CPU {
         self.processor_status.set_flag(Carry, unsigned_overflow_check);
     }
 
-    fn per<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("per unimplemented")
+    fn per<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("per")
     }
 
-    fn stz<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn stz<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::AccumulatorRegisterSize;
 
         let emu = self.processor_status.get_flag(AccumulatorRegisterSize);
@@ -1627,20 +1477,20 @@ impl CPU {
         mode.store(self, memory, emu, 0);
     }
 
-    fn ror<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("ror unimplemented")
+    fn ror<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("ror")
     }
 
-    fn pla<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("pla unimplemented")
+    fn pla<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("pla")
     }
 
-    fn rtl<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("rtl unimplemented")
+    fn rtl<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("rtl")
     }
 
-    fn bvs<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("bvs unimplemented")
+    fn bvs<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("bvs")
     }
 
     fn sei(&mut self) {
@@ -1649,8 +1499,8 @@ impl CPU {
         self.processor_status.set_flag(IRQDisable, true);
     }
 
-    fn ply<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("ply unimplemented")
+    fn ply<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("ply")
     }
 
     fn tdc(&mut self) {
@@ -1661,11 +1511,11 @@ impl CPU {
         self.processor_status.set_flag(Zero, self.accumulator == 0);
     }
 
-    fn bra<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("bra unimplemented")
+    fn bra<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("bra")
     }
 
-    fn sta<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn sta<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::AccumulatorRegisterSize;
 
         let data = self.accumulator as u32;
@@ -1674,11 +1524,11 @@ impl CPU {
         mode.store(self, memory, emu, data);
     }
 
-    fn brl<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("brl unimplemented")
+    fn brl<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("brl")
     }
 
-    fn sty<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn sty<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::IndexRegisterSize;
         
         let data = self.index_y as u32;
@@ -1687,7 +1537,7 @@ impl CPU {
         mode.store(self, memory, emu, data);
     }
 
-    fn stx<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn stx<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::IndexRegisterSize;
 
         let data = self.index_x as u32;
@@ -1697,24 +1547,24 @@ impl CPU {
     }
 
     fn dey(&mut self) {
-        panic!("dey unimplemented")
+        self.unimplemented("dey")
     }
 
     fn txa(&mut self) {
-        panic!("txa unimplemented")
+        self.unimplemented("txa")
     }
 
-    fn phb<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn phb<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let data = self.data_bank as u32;
         mode.store(self, memory, IS_BYTE, data);
     }
 
-    fn bcc<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("bcc unimplemented")
+    fn bcc<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("bcc")
     }
 
     fn tya(&mut self) {
-        panic!("tya unimplemented")
+        self.unimplemented("tya")
     }
 
     fn txs(&mut self) {
@@ -1728,87 +1578,97 @@ impl CPU {
     }
 
     fn txy(&mut self) {
-        panic!("txy unimplemented")
+        self.unimplemented("txy")
     }
 
-    fn ldy<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn ldy<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{Negative, Zero, IndexRegisterSize};
         
         let emu = self.processor_status.get_flag(IndexRegisterSize);
         let data = mode.load(self, memory, emu);
-
+        let sign = if emu { 0x80 } else { 0x8000 };
         self.index_y = data as u16;
 
-        self.processor_status.set_flag(Negative, data.rotate_left(1) & 1 == 1);
+        self.processor_status.set_flag(Negative, data & sign != 0);
         self.processor_status.set_flag(Zero, self.index_y == 0);
     }
 
-    fn lda<T: Instruction>(&mut self, mode: &T, memory: &Memory) {
+    fn lda<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{AccumulatorRegisterSize, Negative, Zero};
 
         let emu = self.processor_status.get_flag(AccumulatorRegisterSize);
         let data = mode.load(self, memory, emu);
-        self.accumulator = data as u16;
-        
-        self.processor_status.set_flag(Negative, data.rotate_left(1) & 1 == 1);
-        self.processor_status.set_flag(Zero, self.accumulator == 0);
+        let sign = if emu { 0x80 } else { 0x8000 };
+
+        // An 8-bit load leaves the high byte (B) alone
+        if emu {
+            self.accumulator = (self.accumulator & 0xFF00) | (data as u16 & 0x00FF);
+        } else {
+            self.accumulator = data as u16;
+        }
+
+        self.processor_status.set_flag(Negative, data & sign != 0);
+        self.processor_status.set_flag(Zero, data == 0);
     }
 
-    fn ldx<T: Instruction>(&mut self, mode: &T, memory: &Memory) {
+    fn ldx<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{IndexRegisterSize, Zero, Negative};
 
         let emu = self.processor_status.get_flag(IndexRegisterSize);
         let data = mode.load(self, memory, emu);
+        let sign = if emu { 0x80 } else { 0x8000 };
         self.index_x = data as u16;
 
-        self.processor_status.set_flag(Negative, data.rotate_left(1) & 1 == 1);
+        self.processor_status.set_flag(Negative, data & sign != 0);
         self.processor_status.set_flag(Zero, self.index_x == 0);
     }
 
     fn tay(&mut self) {
-        panic!("tay unimplemented")
+        self.unimplemented("tay")
     }
 
     fn tax(&mut self) {
         use self::StatusFlags::{Negative, Zero, IndexRegisterSize};
 
+        let emu = self.processor_status.get_flag(IndexRegisterSize);
+        let sign = if emu { 0x80 } else { 0x8000 };
         self.index_x = self.accumulator;
 
-        if self.processor_status.get_flag(IndexRegisterSize) {
+        if emu {
             self.index_x &= 0x00FF;
         }
 
-        self.processor_status.set_flag(Negative, self.index_x.rotate_left(1) & 1 == 1);
+        self.processor_status.set_flag(Negative, self.index_x & sign != 0);
         self.processor_status.set_flag(Zero, self.index_x == 0);
     }
 
-    fn plb<T: Instruction>(&mut self, mode: &T, memory: &Memory) {
+    fn plb<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{Negative, Zero};
 
         let data = mode.load(self, memory, IS_BYTE);
         self.data_bank = data as usize;
 
-        self.processor_status.set_flag(Negative, data.rotate_left(1) & 1 == 1);
+        self.processor_status.set_flag(Negative, data & 0x80 != 0);
         self.processor_status.set_flag(Zero, self.data_bank == 0);
     }
 
-    fn bcs<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("bcs unimplemented")
+    fn bcs<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("bcs")
     }
 
     fn clv(&mut self) {
-        panic!("clv unimplemented")
+        self.unimplemented("clv")
     }
 
     fn tsx(&mut self) {
-        panic!("tsx unimplemented")
+        self.unimplemented("tsx")
     }
 
     fn tyx(&mut self) {
-        panic!("tyx unimplemented")
+        self.unimplemented("tyx")
     }
 
-    fn cpy<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn cpy<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{Negative, Zero, Carry, IndexRegisterSize};
 
         let emu = self.processor_status.get_flag(IndexRegisterSize);
@@ -1821,7 +1681,7 @@ impl CPU {
         self.processor_status.set_flag(Carry, self.index_y >= data);
     }
 
-    fn cmp<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn cmp<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{Negative, Zero, Carry, AccumulatorRegisterSize};
 
         let emu = self.processor_status.get_flag(AccumulatorRegisterSize);
@@ -1842,7 +1702,7 @@ impl CPU {
         self.processor_status.set_flag(Carry, carry);
     }
 
-    fn rep<T: Instruction>(&mut self, mode: &T, memory: &Memory) {
+    fn rep<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let val = mode.load(self, memory, IS_BYTE);
 
         self.processor_status.set_by_byte(self.emulation_mode, val as u8, false);
@@ -1867,10 +1727,10 @@ impl CPU {
     }
 
     fn wai(&mut self) {
-        panic!("wai unimplemented")
+        self.unimplemented("wai")
     }
 
-    fn bne<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn bne<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::Zero;
 
         if !self.processor_status.get_flag(Zero) {
@@ -1886,15 +1746,15 @@ impl CPU {
         self.program_counter = self.program_counter.wrapping_add(1);
     }
 
-    fn pei<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("pei unimplemented")
+    fn pei<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("pei")
     }
 
     fn cld(&mut self) {
-        panic!("cld unimplemented")
+        self.unimplemented("cld")
     }
 
-    fn phx<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn phx<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::IndexRegisterSize;
 
         let data = self.index_x as u32;
@@ -1904,10 +1764,10 @@ impl CPU {
     }
 
     fn stp(&mut self) {
-        panic!("stp unimplemented")
+        self.stopped = true;
     }
 
-    fn cpx<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
+    fn cpx<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         use self::StatusFlags::{Negative, Zero, Carry, IndexRegisterSize};
 
         let emu = self.processor_status.get_flag(IndexRegisterSize);
@@ -1920,16 +1780,16 @@ impl CPU {
         self.processor_status.set_flag(Carry, self.index_x >= data);
     }
 
-    fn sbc<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("sbc unimplemented")
+    fn sbc<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("sbc")
     }
 
     fn inx(&mut self) {
-        panic!("inx unimplemented")
+        self.unimplemented("inx")
     }
 
     fn nop(&mut self) {
-        panic!("nop unimplemented")
+        self.unimplemented("nop")
     }
 
     fn xba(&mut self) {
@@ -1947,20 +1807,20 @@ impl CPU {
         self.processor_status.set_flag(Zero, high_to_low == 0);
     }
 
-    fn beq<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("beq unimplemented")
+    fn beq<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("beq")
     }
 
-    fn pea<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("pea unimplemented")
+    fn pea<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("pea")
     }
 
     fn sed(&mut self) {
-        panic!("sed unimplemented")
+        self.unimplemented("sed")
     }
 
-    fn plx<T: Instruction>(&mut self, mode: &T, memory: &mut Memory) {
-        panic!("plx unimplemented")
+    fn plx<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
+        self.unimplemented("plx")
     }
 
     fn xce(&mut self) {
@@ -1971,13 +1831,44 @@ impl CPU {
         self.processor_status.set_flag(Carry, emu_bit);
     }
 
-    fn sep<T: Instruction>(&mut self, mode: &T, memory: &Memory) {
+    fn sep<T: Instruction, B: Bus>(&mut self, mode: &T, memory: &mut B) {
         let val = mode.load(self, memory, IS_BYTE);
         
         self.processor_status.set_by_byte(self.emulation_mode, val as u8, true);
     }
 }
 
+// Passes accesses through to the bus and counts them, so the cycles an instruction spends
+// without the bus can be told to it as idle
+struct CountingBus<'a, B: Bus + 'a> {
+    bus: &'a mut B,
+    accesses: u32,
+}
+
+impl<'a, B: Bus> Bus for CountingBus<'a, B> {
+    fn read(&mut self, addr: usize) -> u8 {
+        self.accesses += 1;
+        self.bus.read(addr)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) {
+        self.accesses += 1;
+        self.bus.write(addr, data)
+    }
+
+    fn idle(&mut self) {
+        self.bus.idle()
+    }
+
+    fn reset_vector(&mut self) -> (usize, usize) {
+        self.bus.reset_vector()
+    }
+
+    fn break_vector(&mut self, emulation_mode: bool) -> usize {
+        self.bus.break_vector(emulation_mode)
+    }
+}
+
 impl fmt::Debug for CPU {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "CPU {{
@@ -1998,12 +1889,22 @@ impl fmt::Debug for CPU {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub struct ProcessorStatus {
     status: [bool; 8],
 }
 
 impl ProcessorStatus {
+    pub fn from_byte(byte: u8) -> ProcessorStatus {
+        let mut status = [false; 8];
+
+        for (i, flag) in status.iter_mut().enumerate() {
+            *flag = byte & (1 << i) != 0;
+        }
+
+        ProcessorStatus { status: status }
+    }
+
     pub fn set_flag(&mut self, flag: StatusFlags, val: bool) {
         self.status[flag as usize] = val;
     }
@@ -0,0 +1,19 @@
+/// The CRC-32 used by zip, PNG and the BPS/UPS patch formats
+pub fn crc32(data: &[u8]) -> u32 {
+    update(0, data)
+}
+
+/// Continues a CRC-32 over more data, starting from a previous result
+pub fn update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
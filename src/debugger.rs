@@ -0,0 +1,386 @@
+use std::io::{self, BufRead, Write};
+
+use cpu::{ProcessorStatus, StatusFlags};
+use disasm::{self, Disassembler};
+use error::Error;
+use memory::{BusAccess, WatchHit, WatchKind, Watchpoint};
+use snes::SNES;
+use state::{self, StateSlots};
+use symbols::Symbols;
+use trace;
+
+const HELP: &str = "\
+s, step [N]             run N instructions (default 1)
+n, next                 run one instruction, stepping over JSR and JSL
+c, continue             run until a breakpoint, watchpoint or error
+u, until ADDR           run until the program counter reaches an address
+f, frame                run to the end of the frame
+back [N]                rewind to the start of the frame N frames back (default 1); the
+                        current frame counts if it has started. Needs --rewind
+b, break ADDR           stop before running the instruction at an address
+w, watch [r|w|rw] ADDR [END]
+                        stop after an access to an address or range; rw if not given
+d, delete N             remove a breakpoint or watchpoint by its number in the list
+l, list                 list breakpoints and watchpoints
+r, regs                 show the registers
+set REG VALUE           set a, x, y, s, d, db, pb, pc or p in hex, or e to 0 or 1
+x ADDR [COUNT]          dump COUNT bytes (default 64) of memory; I/O registers aren't read
+dis [ADDR [COUNT]]      disassemble COUNT instructions (default 10) from an address or the PC
+save N, load N          save the machine to or restore it from numbered slot N (0-9)
+q, quit                 leave the debugger
+Addresses are hex with an optional colon after the bank, as in 80:8000, or with symbols loaded,
+a label name such as Reset or Reset+4. Breakpoints also stop in the other half's mirror of their
+bank, $00 for $80 and so on, since symbol files and the reset vector don't always agree on which
+one code runs from. An empty line repeats the last command.";
+
+enum Stop {
+    Steps,
+    Breakpoint(usize),
+    Watchpoint(WatchHit),
+    Reached,
+    FrameEnd,
+    Error(Error),
+}
+
+enum Stopping {
+    Steps(u64),
+    Address(usize),
+    FrameEnd,
+    Never,
+}
+
+/// An interactive debugger for the console. Errors such as unimplemented instructions, STP and
+/// bus errors under the `Break` policy stop it like a breakpoint does, and since the CPU leaves
+/// the program counter on an instruction it couldn't run, `set pc` can skip past it.
+pub struct Debugger {
+    breakpoints: Vec<usize>,
+    watchpoints: Vec<Watchpoint>,
+    symbols: Option<Symbols>,
+    slots: Option<StateSlots>,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            symbols: None,
+            slots: None,
+            last_command: String::new(),
+        }
+    }
+
+    /// Shows labels in disassembly and accepts their names wherever an address is asked for
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = Some(symbols);
+    }
+
+    /// Where `save` and `load` keep their states
+    pub fn set_state_slots(&mut self, slots: StateSlots) {
+        self.slots = Some(slots);
+    }
+
+    /// Reads commands until `quit` or the end of the input
+    pub fn run<R: BufRead, W: Write>(&mut self, snes: &mut SNES, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", trace::describe(snes.cpu(), snes.memory(), self.symbols.as_ref()))?;
+        write!(output, "> ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            let line = if line.trim().is_empty() { self.last_command.clone() } else { line };
+
+            match self.command(snes, &line, &mut output) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {},
+                Err(message) => writeln!(output, "{}", message)?,
+            }
+
+            self.last_command = line;
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    // Returns true to quit. Errors are the user's mistakes, reported before the next prompt.
+    fn command<W: Write>(&mut self, snes: &mut SNES, line: &str, output: &mut W) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let argument = |index: usize| words.get(index).cloned();
+        let io_error = |err: io::Error| err.to_string();
+
+        match words.first().cloned().unwrap_or("") {
+            "" => {},
+            "s" | "step" => {
+                let count = match argument(1) {
+                    Some(count) => count.parse::<u64>().map_err(|_| format!("Bad count {}", count))?,
+                    None => 1,
+                };
+
+                self.resume(snes, Stopping::Steps(count), output).map_err(io_error)?;
+            },
+            "n" | "next" => {
+                let cpu = snes.cpu();
+                let addr = (cpu.program_bank << 16) | cpu.program_counter;
+                let instruction = disasm::disassemble(snes.memory(), addr, true, true);
+
+                let stopping = match instruction.opcode {
+                    0x20 | 0x22 | 0xFC => Stopping::Address((addr & 0xFF0000) | ((addr + instruction.length) & 0xFFFF)),
+                    _ => Stopping::Steps(1),
+                };
+
+                self.resume(snes, stopping, output).map_err(io_error)?;
+            },
+            "c" | "continue" => self.resume(snes, Stopping::Never, output).map_err(io_error)?,
+            "u" | "until" => {
+                let addr = self.parse_address(argument(1))?;
+                self.resume(snes, Stopping::Address(addr), output).map_err(io_error)?;
+            },
+            "back" => {
+                if snes.rewind_frames() == 0 {
+                    return Err("Nothing to rewind; it needs --rewind SECONDS and a frame run".to_string());
+                }
+
+                let count = parse_count(argument(1), 1)?;
+                let rewound = snes.rewind(count).map_err(|err| err.to_string())?;
+
+                writeln!(output, "Back {} frames to the start of frame {}", rewound, snes.frame()).map_err(io_error)?;
+                writeln!(output, "{}", trace::describe(snes.cpu(), snes.memory(), self.symbols.as_ref())).map_err(io_error)?;
+            },
+            "f" | "frame" => self.resume(snes, Stopping::FrameEnd, output).map_err(io_error)?,
+            "b" | "break" => {
+                let addr = self.parse_address(argument(1))?;
+                self.breakpoints.push(addr);
+                self.list(output).map_err(io_error)?;
+            },
+            "w" | "watch" => {
+                let (kind, rest) = match argument(1) {
+                    Some("r") => (WatchKind::Read, 2),
+                    Some("w") => (WatchKind::Write, 2),
+                    Some("rw") => (WatchKind::Access, 2),
+                    _ => (WatchKind::Access, 1),
+                };
+
+                let start = self.parse_address(argument(rest))?;
+                let end = match argument(rest + 1) {
+                    Some(_) => self.parse_address(argument(rest + 1))?,
+                    None => start,
+                };
+
+                self.watchpoints.push(Watchpoint { start: start, end: end.max(start), kind: kind });
+                snes.memory_mut().set_watchpoints(self.watchpoints.clone());
+                self.list(output).map_err(io_error)?;
+            },
+            "d" | "delete" => {
+                let number = argument(1).and_then(|number| number.parse::<usize>().ok())
+                    .filter(|&number| number >= 1 && number <= self.breakpoints.len() + self.watchpoints.len())
+                    .ok_or("delete needs the number of a breakpoint or watchpoint from list")?;
+
+                if number <= self.breakpoints.len() {
+                    self.breakpoints.remove(number - 1);
+                } else {
+                    self.watchpoints.remove(number - 1 - self.breakpoints.len());
+                    snes.memory_mut().set_watchpoints(self.watchpoints.clone());
+                }
+
+                self.list(output).map_err(io_error)?;
+            },
+            "l" | "list" => self.list(output).map_err(io_error)?,
+            "r" | "regs" => {
+                let (h, v) = snes.beam_position();
+                writeln!(output, "{}", trace::describe(snes.cpu(), snes.memory(), self.symbols.as_ref())).map_err(io_error)?;
+                writeln!(output, "frame {} H:{} V:{}", snes.frame(), h, v).map_err(io_error)?;
+            },
+            "set" => {
+                let register = argument(1).ok_or("set needs a register")?;
+                let value = argument(2).ok_or("set needs a value")?;
+                let value = usize::from_str_radix(value, 16).map_err(|_| format!("Bad value {}", value))?;
+                set_register(snes, register, value)?;
+                writeln!(output, "{}", trace::describe(snes.cpu(), snes.memory(), self.symbols.as_ref())).map_err(io_error)?;
+            },
+            "x" => {
+                let start = self.parse_address(argument(1))?;
+                let count = parse_count(argument(2), 64)?;
+
+                for row in (0..count).step_by(16) {
+                    let bytes: Vec<String> = (row..count.min(row + 16))
+                        .map(|offset| format!("{:02x}", snes.memory().peek_byte((start + offset) & 0xFFFFFF)))
+                        .collect();
+                    let addr = (start + row) & 0xFFFFFF;
+
+                    writeln!(output, "{:02x}:{:04x}  {}", addr >> 16, addr & 0xFFFF, bytes.join(" ")).map_err(io_error)?;
+                }
+            },
+            "dis" => {
+                let cpu = snes.cpu();
+                let start = match argument(1) {
+                    Some(_) => self.parse_address(argument(1))?,
+                    None => (cpu.program_bank << 16) | cpu.program_counter,
+                };
+                let count = parse_count(argument(2), 10)?;
+                let m8 = cpu.processor_status.get_flag(StatusFlags::AccumulatorRegisterSize);
+                let x8 = cpu.processor_status.get_flag(StatusFlags::IndexRegisterSize);
+
+                for instruction in Disassembler::new(snes.memory(), start, m8, x8, cpu.emulation_mode).take(count) {
+                    if let Some(label) = self.symbols.as_ref().and_then(|symbols| symbols.lookup(instruction.addr)) {
+                        writeln!(output, "{}:", label).map_err(io_error)?;
+                    }
+
+                    let marker = if self.breakpoints.contains(&instruction.addr) { "*" } else { " " };
+                    writeln!(output, "{}{:02x}:{:04x}  {}", marker, instruction.addr >> 16, instruction.addr & 0xFFFF,
+                             instruction.text(self.symbols.as_ref())).map_err(io_error)?;
+                }
+            },
+            "save" | "load" => {
+                let slots = self.slots.as_ref().ok_or("No save slots are set up")?;
+                let slot = argument(1).ok_or(format!("{} needs a slot number", words[0]))?;
+                let slot = state::parse_slot(slot).ok_or(format!("Bad save slot {}", slot))?;
+
+                if words[0] == "save" {
+                    slots.save(slot, &snes.save_state())
+                        .map_err(|err| format!("Could not write {}: {}", slots.path(slot).display(), err))?;
+                    writeln!(output, "Saved slot {}", slot).map_err(io_error)?;
+                } else {
+                    snes.load_state(&slots.load(slot)?).map_err(|err| err.to_string())?;
+                    writeln!(output, "{}", trace::describe(snes.cpu(), snes.memory(), self.symbols.as_ref()))
+                        .map_err(io_error)?;
+                }
+            },
+            "h" | "help" => writeln!(output, "{}", HELP).map_err(io_error)?,
+            "q" | "quit" => return Ok(true),
+            command => return Err(format!("Unknown command {}, try help", command)),
+        }
+
+        Ok(false)
+    }
+
+    fn resume<W: Write>(&mut self, snes: &mut SNES, stopping: Stopping, output: &mut W) -> io::Result<()> {
+        let stop = self.run_until(snes, stopping);
+
+        match stop {
+            Stop::Steps | Stop::Reached => {},
+            Stop::Breakpoint(addr) => writeln!(output, "Breakpoint at {}", self.location(addr))?,
+            Stop::Watchpoint(hit) => match hit.access {
+                BusAccess::Read => writeln!(output, "Watchpoint: read from {}", self.location(hit.addr))?,
+                BusAccess::Write(data) => writeln!(output, "Watchpoint: wrote {:02x} to {}", data, self.location(hit.addr))?,
+            },
+            Stop::FrameEnd => writeln!(output, "Frame {} finished", snes.frame())?,
+            Stop::Error(error) => writeln!(output, "Stopped: {}", error)?,
+        }
+
+        writeln!(output, "{}", trace::describe(snes.cpu(), snes.memory(), self.symbols.as_ref()))
+    }
+
+    fn run_until(&mut self, snes: &mut SNES, stopping: Stopping) -> Stop {
+        let mut steps = 0;
+
+        // The first instruction always runs, so continuing from a breakpoint doesn't stop on it again
+        loop {
+            let frame_done = match snes.step_instruction() {
+                Ok(frame_done) => frame_done,
+                Err(error) => return Stop::Error(error),
+            };
+
+            steps += 1;
+
+            if let Some(hit) = snes.memory_mut().take_watch_hit() {
+                return Stop::Watchpoint(hit);
+            }
+
+            let cpu = snes.cpu();
+            let pc = (cpu.program_bank << 16) | cpu.program_counter;
+
+            match stopping {
+                Stopping::Steps(count) if steps >= count => return Stop::Steps,
+                Stopping::Address(addr) if same_location(pc, addr) => return Stop::Reached,
+                Stopping::FrameEnd if frame_done => return Stop::FrameEnd,
+                _ => {},
+            }
+
+            if self.breakpoints.iter().any(|&addr| same_location(pc, addr)) {
+                return Stop::Breakpoint(pc);
+            }
+        }
+    }
+
+    fn list<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for (number, addr) in self.breakpoints.iter().enumerate() {
+            writeln!(output, "{}: break {}", number + 1, self.location(*addr))?;
+        }
+
+        for (number, watchpoint) in self.watchpoints.iter().enumerate() {
+            let kind = match watchpoint.kind {
+                WatchKind::Read => "r",
+                WatchKind::Write => "w",
+                WatchKind::Access => "rw",
+            };
+
+            writeln!(output, "{}: watch {} {:06x}-{:06x}", self.breakpoints.len() + number + 1, kind,
+                     watchpoint.start, watchpoint.end)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_address(&self, text: Option<&str>) -> Result<usize, String> {
+        let text = text.ok_or("Missing address")?;
+
+        if let Some(addr) = self.symbols.as_ref().and_then(|symbols| symbols.address(text)) {
+            return Ok(addr);
+        }
+
+        usize::from_str_radix(&text.replace(':', ""), 16).ok()
+            .filter(|&addr| addr <= 0xFFFFFF)
+            .ok_or(format!("Bad address {}", text))
+    }
+
+    /// An address as bb:pppp, followed by the nearest label if there is one
+    fn location(&self, addr: usize) -> String {
+        match self.symbols.as_ref().and_then(|symbols| symbols.describe(addr)) {
+            Some(label) => format!("{:02x}:{:04x} ({})", addr >> 16, addr & 0xFFFF, label),
+            None => format!("{:02x}:{:04x}", addr >> 16, addr & 0xFFFF),
+        }
+    }
+}
+
+// Banks $80-$FD mirror $00-$7D; $7E and $7F are work RAM, which $FE and $FF don't mirror
+fn same_location(pc: usize, addr: usize) -> bool {
+    pc == addr || (pc ^ 0x800000 == addr && (pc >> 16) & 0x7F < 0x7E)
+}
+
+fn parse_count(text: Option<&str>, default: usize) -> Result<usize, String> {
+    match text {
+        Some(text) => text.parse::<usize>().map_err(|_| format!("Bad count {}", text)),
+        None => Ok(default),
+    }
+}
+
+fn set_register(snes: &mut SNES, register: &str, value: usize) -> Result<(), String> {
+    let cpu = snes.cpu_mut();
+
+    match register {
+        "a" => cpu.accumulator = value as u16,
+        "x" => cpu.index_x = value as u16,
+        "y" => cpu.index_y = value as u16,
+        "s" => cpu.stack_pointer = value & 0xFFFF,
+        "d" => cpu.direct_page = value & 0xFFFF,
+        "db" => cpu.data_bank = value & 0xFF,
+        "pb" => cpu.program_bank = value & 0xFF,
+        "pc" if value > 0xFFFFFF => return Err(format!("Bad address {:x}", value)),
+        "pc" => {
+            // A full bank:address moves the program bank too
+            if value > 0xFFFF {
+                cpu.program_bank = value >> 16;
+            }
+
+            cpu.program_counter = value & 0xFFFF;
+        },
+        "p" => cpu.processor_status = ProcessorStatus::from_byte(value as u8),
+        "e" => cpu.emulation_mode = value != 0,
+        _ => return Err(format!("Unknown register {}", register)),
+    }
+
+    Ok(())
+}
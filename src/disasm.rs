@@ -0,0 +1,330 @@
+use std::fmt;
+
+use memory::Memory;
+use symbols::Symbols;
+
+/// How an instruction's operand bytes are laid out and written. Named after the addressing
+/// modes in `modes`, with immediates split by which register size sets their width.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    ImmediateM, // 8 or 16 bits depending on the M flag
+    ImmediateX, // 8 or 16 bits depending on the X flag
+    Immediate8, // REP, SEP and the signature bytes of BRK, COP and WDM
+    Absolute,
+    AbsoluteLong,
+    AbsoluteIndexedX,
+    AbsoluteIndexedY,
+    AbsoluteLongIndexedX,
+    AbsoluteIndirect,
+    AbsoluteIndirectLong,
+    AbsoluteIndexedIndirect,
+    DirectPage,
+    DirectPageIndexedX,
+    DirectPageIndexedY,
+    DirectPageIndirect,
+    DirectPageIndirectLong,
+    DirectPageIndexedIndirectX,
+    DirectPageIndirectIndexedY,
+    DirectPageIndirectLongIndexedY,
+    StackRelative,
+    StackRelativeIndirectIndexedY,
+    StackAbsolute, // PEA
+    StackDirectPageIndirect, // PEI
+    StackProgramCounterRelative, // PER
+    ProgramCounterRelative,
+    ProgramCounterRelativeLong,
+    BlockMove,
+}
+
+use self::Mode::*;
+
+const OPCODES: [(&str, Mode); 256] = [
+    // 0x00
+    ("brk", Immediate8), ("ora", DirectPageIndexedIndirectX), ("cop", Immediate8), ("ora", StackRelative),
+    ("tsb", DirectPage), ("ora", DirectPage), ("asl", DirectPage), ("ora", DirectPageIndirectLong),
+    ("php", Implied), ("ora", ImmediateM), ("asl", Accumulator), ("phd", Implied),
+    ("tsb", Absolute), ("ora", Absolute), ("asl", Absolute), ("ora", AbsoluteLong),
+    // 0x10
+    ("bpl", ProgramCounterRelative), ("ora", DirectPageIndirectIndexedY), ("ora", DirectPageIndirect), ("ora", StackRelativeIndirectIndexedY),
+    ("trb", DirectPage), ("ora", DirectPageIndexedX), ("asl", DirectPageIndexedX), ("ora", DirectPageIndirectLongIndexedY),
+    ("clc", Implied), ("ora", AbsoluteIndexedY), ("inc", Accumulator), ("tcs", Implied),
+    ("trb", Absolute), ("ora", AbsoluteIndexedX), ("asl", AbsoluteIndexedX), ("ora", AbsoluteLongIndexedX),
+    // 0x20
+    ("jsr", Absolute), ("and", DirectPageIndexedIndirectX), ("jsl", AbsoluteLong), ("and", StackRelative),
+    ("bit", DirectPage), ("and", DirectPage), ("rol", DirectPage), ("and", DirectPageIndirectLong),
+    ("plp", Implied), ("and", ImmediateM), ("rol", Accumulator), ("pld", Implied),
+    ("bit", Absolute), ("and", Absolute), ("rol", Absolute), ("and", AbsoluteLong),
+    // 0x30
+    ("bmi", ProgramCounterRelative), ("and", DirectPageIndirectIndexedY), ("and", DirectPageIndirect), ("and", StackRelativeIndirectIndexedY),
+    ("bit", DirectPageIndexedX), ("and", DirectPageIndexedX), ("rol", DirectPageIndexedX), ("and", DirectPageIndirectLongIndexedY),
+    ("sec", Implied), ("and", AbsoluteIndexedY), ("dec", Accumulator), ("tsc", Implied),
+    ("bit", AbsoluteIndexedX), ("and", AbsoluteIndexedX), ("rol", AbsoluteIndexedX), ("and", AbsoluteLongIndexedX),
+    // 0x40
+    ("rti", Implied), ("eor", DirectPageIndexedIndirectX), ("wdm", Immediate8), ("eor", StackRelative),
+    ("mvp", BlockMove), ("eor", DirectPage), ("lsr", DirectPage), ("eor", DirectPageIndirectLong),
+    ("pha", Implied), ("eor", ImmediateM), ("lsr", Accumulator), ("phk", Implied),
+    ("jmp", Absolute), ("eor", Absolute), ("lsr", Absolute), ("eor", AbsoluteLong),
+    // 0x50
+    ("bvc", ProgramCounterRelative), ("eor", DirectPageIndirectIndexedY), ("eor", DirectPageIndirect), ("eor", StackRelativeIndirectIndexedY),
+    ("mvn", BlockMove), ("eor", DirectPageIndexedX), ("lsr", DirectPageIndexedX), ("eor", DirectPageIndirectLongIndexedY),
+    ("cli", Implied), ("eor", AbsoluteIndexedY), ("phy", Implied), ("tcd", Implied),
+    ("jml", AbsoluteLong), ("eor", AbsoluteIndexedX), ("lsr", AbsoluteIndexedX), ("eor", AbsoluteLongIndexedX),
+    // 0x60
+    ("rts", Implied), ("adc", DirectPageIndexedIndirectX), ("per", StackProgramCounterRelative), ("adc", StackRelative),
+    ("stz", DirectPage), ("adc", DirectPage), ("ror", DirectPage), ("adc", DirectPageIndirectLong),
+    ("pla", Implied), ("adc", ImmediateM), ("ror", Accumulator), ("rtl", Implied),
+    ("jmp", AbsoluteIndirect), ("adc", Absolute), ("ror", Absolute), ("adc", AbsoluteLong),
+    // 0x70
+    ("bvs", ProgramCounterRelative), ("adc", DirectPageIndirectIndexedY), ("adc", DirectPageIndirect), ("adc", StackRelativeIndirectIndexedY),
+    ("stz", DirectPageIndexedX), ("adc", DirectPageIndexedX), ("ror", DirectPageIndexedX), ("adc", DirectPageIndirectLongIndexedY),
+    ("sei", Implied), ("adc", AbsoluteIndexedY), ("ply", Implied), ("tdc", Implied),
+    ("jmp", AbsoluteIndexedIndirect), ("adc", AbsoluteIndexedX), ("ror", AbsoluteIndexedX), ("adc", AbsoluteLongIndexedX),
+    // 0x80
+    ("bra", ProgramCounterRelative), ("sta", DirectPageIndexedIndirectX), ("brl", ProgramCounterRelativeLong), ("sta", StackRelative),
+    ("sty", DirectPage), ("sta", DirectPage), ("stx", DirectPage), ("sta", DirectPageIndirectLong),
+    ("dey", Implied), ("bit", ImmediateM), ("txa", Implied), ("phb", Implied),
+    ("sty", Absolute), ("sta", Absolute), ("stx", Absolute), ("sta", AbsoluteLong),
+    // 0x90
+    ("bcc", ProgramCounterRelative), ("sta", DirectPageIndirectIndexedY), ("sta", DirectPageIndirect), ("sta", StackRelativeIndirectIndexedY),
+    ("sty", DirectPageIndexedX), ("sta", DirectPageIndexedX), ("stx", DirectPageIndexedY), ("sta", DirectPageIndirectLongIndexedY),
+    ("tya", Implied), ("sta", AbsoluteIndexedY), ("txs", Implied), ("txy", Implied),
+    ("stz", Absolute), ("sta", AbsoluteIndexedX), ("stz", AbsoluteIndexedX), ("sta", AbsoluteLongIndexedX),
+    // 0xA0
+    ("ldy", ImmediateX), ("lda", DirectPageIndexedIndirectX), ("ldx", ImmediateX), ("lda", StackRelative),
+    ("ldy", DirectPage), ("lda", DirectPage), ("ldx", DirectPage), ("lda", DirectPageIndirectLong),
+    ("tay", Implied), ("lda", ImmediateM), ("tax", Implied), ("plb", Implied),
+    ("ldy", Absolute), ("lda", Absolute), ("ldx", Absolute), ("lda", AbsoluteLong),
+    // 0xB0
+    ("bcs", ProgramCounterRelative), ("lda", DirectPageIndirectIndexedY), ("lda", DirectPageIndirect), ("lda", StackRelativeIndirectIndexedY),
+    ("ldy", DirectPageIndexedX), ("lda", DirectPageIndexedX), ("ldx", DirectPageIndexedY), ("lda", DirectPageIndirectLongIndexedY),
+    ("clv", Implied), ("lda", AbsoluteIndexedY), ("tsx", Implied), ("tyx", Implied),
+    ("ldy", AbsoluteIndexedX), ("lda", AbsoluteIndexedX), ("ldx", AbsoluteIndexedY), ("lda", AbsoluteLongIndexedX),
+    // 0xC0
+    ("cpy", ImmediateX), ("cmp", DirectPageIndexedIndirectX), ("rep", Immediate8), ("cmp", StackRelative),
+    ("cpy", DirectPage), ("cmp", DirectPage), ("dec", DirectPage), ("cmp", DirectPageIndirectLong),
+    ("iny", Implied), ("cmp", ImmediateM), ("dex", Implied), ("wai", Implied),
+    ("cpy", Absolute), ("cmp", Absolute), ("dec", Absolute), ("cmp", AbsoluteLong),
+    // 0xD0
+    ("bne", ProgramCounterRelative), ("cmp", DirectPageIndirectIndexedY), ("cmp", DirectPageIndirect), ("cmp", StackRelativeIndirectIndexedY),
+    ("pei", StackDirectPageIndirect), ("cmp", DirectPageIndexedX), ("dec", DirectPageIndexedX), ("cmp", DirectPageIndirectLongIndexedY),
+    ("cld", Implied), ("cmp", AbsoluteIndexedY), ("phx", Implied), ("stp", Implied),
+    ("jml", AbsoluteIndirectLong), ("cmp", AbsoluteIndexedX), ("dec", AbsoluteIndexedX), ("cmp", AbsoluteLongIndexedX),
+    // 0xE0
+    ("cpx", ImmediateX), ("sbc", DirectPageIndexedIndirectX), ("sep", Immediate8), ("sbc", StackRelative),
+    ("cpx", DirectPage), ("sbc", DirectPage), ("inc", DirectPage), ("sbc", DirectPageIndirectLong),
+    ("inx", Implied), ("sbc", ImmediateM), ("nop", Implied), ("xba", Implied),
+    ("cpx", Absolute), ("sbc", Absolute), ("inc", Absolute), ("sbc", AbsoluteLong),
+    // 0xF0
+    ("beq", ProgramCounterRelative), ("sbc", DirectPageIndirectIndexedY), ("sbc", DirectPageIndirect), ("sbc", StackRelativeIndirectIndexedY),
+    ("pea", StackAbsolute), ("sbc", DirectPageIndexedX), ("inc", DirectPageIndexedX), ("sbc", DirectPageIndirectLongIndexedY),
+    ("sed", Implied), ("sbc", AbsoluteIndexedY), ("plx", Implied), ("xce", Implied),
+    ("jsr", AbsoluteIndexedIndirect), ("sbc", AbsoluteIndexedX), ("inc", AbsoluteIndexedX), ("sbc", AbsoluteLongIndexedX),
+];
+
+/// One decoded instruction
+pub struct Instruction {
+    pub addr: usize,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: Mode,
+    pub operand: u32, // little endian operand bytes, without the opcode
+    pub length: usize, // in bytes, including the opcode
+}
+
+impl Instruction {
+    /// The opcode and operand as they sit in memory
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode];
+        bytes.extend((0..self.length - 1).map(|index| (self.operand >> (index * 8)) as u8));
+
+        bytes
+    }
+
+    /// Where a branch goes, or where a PER pushes
+    pub fn branch_target(&self) -> Option<usize> {
+        let next = self.addr.wrapping_add(self.length) as u16;
+
+        let target = match self.mode {
+            ProgramCounterRelative => next.wrapping_add(self.operand as u8 as i8 as u16),
+            ProgramCounterRelativeLong | StackProgramCounterRelative => next.wrapping_add(self.operand as u16),
+            _ => return None,
+        };
+
+        Some((self.addr & 0xFF0000) | target as usize)
+    }
+}
+
+impl Instruction {
+    /// The instruction as assembly, with any address that has a symbol written as its name
+    pub fn text(&self, symbols: Option<&Symbols>) -> String {
+        let operand = self.operand as usize;
+        let bank = self.addr & 0xFF0000;
+        let label = |addr: usize| symbols.and_then(|symbols| symbols.lookup(addr)).map(String::from);
+
+        // Sixteen bit operands are looked up in the instruction's own bank, or bank zero for
+        // the indirect jumps that read their pointer from there
+        let absolute = |bank: usize| label(bank | operand).unwrap_or_else(|| format!("${:04x}", operand));
+        let long = || label(operand).unwrap_or_else(|| format!("${:06x}", operand));
+
+        let text = match self.mode {
+            Implied => return self.mnemonic.to_string(),
+            Accumulator => "a".to_string(),
+            ImmediateM | ImmediateX | Immediate8 => format!("#${:0width$x}", operand, width = (self.length - 1) * 2),
+            Absolute | StackAbsolute => absolute(bank),
+            AbsoluteLong => long(),
+            AbsoluteIndexedX => format!("{},x", absolute(bank)),
+            AbsoluteIndexedY => format!("{},y", absolute(bank)),
+            AbsoluteLongIndexedX => format!("{},x", long()),
+            AbsoluteIndirect => format!("({})", absolute(0)),
+            AbsoluteIndirectLong => format!("[{}]", absolute(0)),
+            AbsoluteIndexedIndirect => format!("({},x)", absolute(bank)),
+            DirectPage => format!("${:02x}", operand),
+            DirectPageIndexedX => format!("${:02x},x", operand),
+            DirectPageIndexedY => format!("${:02x},y", operand),
+            DirectPageIndirect | StackDirectPageIndirect => format!("(${:02x})", operand),
+            DirectPageIndirectLong => format!("[${:02x}]", operand),
+            DirectPageIndexedIndirectX => format!("(${:02x},x)", operand),
+            DirectPageIndirectIndexedY => format!("(${:02x}),y", operand),
+            DirectPageIndirectLongIndexedY => format!("[${:02x}],y", operand),
+            StackRelative => format!("${:02x},s", operand),
+            StackRelativeIndirectIndexedY => format!("(${:02x},s),y", operand),
+            ProgramCounterRelative | ProgramCounterRelativeLong | StackProgramCounterRelative => {
+                let target = self.branch_target().unwrap_or(0);
+                label(target).unwrap_or_else(|| format!("${:04x}", target & 0xFFFF))
+            },
+            // The operand bytes are the destination bank then the source bank
+            BlockMove => format!("${:02x},${:02x}", operand >> 8, operand & 0xFF),
+        };
+
+        format!("{} {}", self.mnemonic, text)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text(None))
+    }
+}
+
+/// How many operand bytes follow an opcode in this mode, given the register sizes
+fn operand_length(mode: Mode, m8: bool, x8: bool) -> usize {
+    match mode {
+        Implied | Accumulator => 0,
+        ImmediateM => if m8 { 1 } else { 2 },
+        ImmediateX => if x8 { 1 } else { 2 },
+        Immediate8 | DirectPage | DirectPageIndexedX | DirectPageIndexedY | DirectPageIndirect |
+        DirectPageIndirectLong | DirectPageIndexedIndirectX | DirectPageIndirectIndexedY |
+        DirectPageIndirectLongIndexedY | StackRelative | StackRelativeIndirectIndexedY |
+        StackDirectPageIndirect | ProgramCounterRelative => 1,
+        Absolute | AbsoluteIndexedX | AbsoluteIndexedY | AbsoluteIndirect | AbsoluteIndirectLong |
+        AbsoluteIndexedIndirect | StackAbsolute | StackProgramCounterRelative | ProgramCounterRelativeLong |
+        BlockMove => 2,
+        AbsoluteLong | AbsoluteLongIndexedX => 3,
+    }
+}
+
+/// Decodes the instruction at `addr`. `m8` and `x8` say whether the accumulator and index
+/// registers are 8 bits wide, which sets the size of immediate operands. Memory is only
+/// peeked, so I/O registers are left alone.
+pub fn disassemble(memory: &Memory, addr: usize, m8: bool, x8: bool) -> Instruction {
+    let opcode = memory.peek_byte(addr);
+    let (mnemonic, mode) = OPCODES[opcode as usize];
+    let length = 1 + operand_length(mode, m8, x8);
+
+    // The program counter wraps within its bank
+    let operand = (1..length).rev().fold(0u32, |operand, offset| {
+        let byte_addr = (addr & 0xFF0000) | (addr.wrapping_add(offset) & 0xFFFF);
+        (operand << 8) | memory.peek_byte(byte_addr) as u32
+    });
+
+    Instruction {
+        addr: addr,
+        opcode: opcode,
+        mnemonic: mnemonic,
+        mode: mode,
+        operand: operand,
+        length: length,
+    }
+}
+
+/// Decodes a run of instructions in address order. REP and SEP change the register sizes used
+/// for the instructions after them, and setting or clearing carry with CLC, SEC, REP or SEP
+/// followed by XCE switches between native and emulation mode. Flags changed any other way,
+/// such as by PLP or RTI, or code reached with different flags, can't be seen, so a listing may
+/// be off until the next REP or SEP.
+pub struct Disassembler<'a> {
+    memory: &'a Memory,
+    addr: usize,
+    m8: bool,
+    x8: bool,
+    emulation: bool,
+    carry: Option<bool>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(memory: &'a Memory, addr: usize, m8: bool, x8: bool, emulation: bool) -> Disassembler<'a> {
+        Disassembler {
+            memory: memory,
+            addr: addr,
+            m8: m8 || emulation,
+            x8: x8 || emulation,
+            emulation: emulation,
+            carry: None,
+        }
+    }
+
+    fn follow_flags(&mut self, instruction: &Instruction) {
+        match instruction.opcode {
+            0x18 => self.carry = Some(false), // CLC
+            0x38 => self.carry = Some(true), // SEC
+            0xC2 => {
+                // REP, which can't widen the registers in emulation mode
+                if instruction.operand & 0x01 != 0 {
+                    self.carry = Some(false);
+                }
+
+                if !self.emulation {
+                    self.m8 &= instruction.operand & 0x20 == 0;
+                    self.x8 &= instruction.operand & 0x10 == 0;
+                }
+            },
+            0xE2 => {
+                // SEP
+                if instruction.operand & 0x01 != 0 {
+                    self.carry = Some(true);
+                }
+
+                self.m8 |= instruction.operand & 0x20 != 0;
+                self.x8 |= instruction.operand & 0x10 != 0;
+            },
+            0xFB => {
+                // XCE swaps carry and the emulation flag; entering emulation mode forces 8 bits
+                if let Some(carry) = self.carry {
+                    self.carry = Some(self.emulation);
+                    self.emulation = carry;
+
+                    if carry {
+                        self.m8 = true;
+                        self.x8 = true;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+impl<'a> Iterator for Disassembler<'a> {
+    type Item = Instruction;
+
+    fn next(&mut self) -> Option<Instruction> {
+        let instruction = disassemble(self.memory, self.addr, self.m8, self.x8);
+
+        self.follow_flags(&instruction);
+        self.addr = (self.addr & 0xFF0000) | (self.addr.wrapping_add(instruction.length) & 0xFFFF);
+
+        Some(instruction)
+    }
+}
@@ -0,0 +1,54 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use memory::{BusError, RomType};
+
+/// Everything that can stop a cartridge from loading or the console from running
+#[derive(Debug)]
+pub enum Error {
+    /// The image is too small to hold a header where its mapper puts one
+    TruncatedRom(usize),
+    /// Neither the LoROM nor the HiROM header location holds a title
+    NoHeader,
+    UnsupportedMapper(RomType),
+    /// The CPU reached an instruction or addressing mode that isn't emulated yet
+    Unimplemented { what: &'static str, addr: usize },
+    /// STP halted the CPU, which only a reset brings back. `addr` is the instruction after it.
+    CpuStopped { addr: usize },
+    /// An unmapped access or ROM write while the bus error policy is `Break`
+    Bus(BusError),
+    /// A save state that's corrupt or from a newer version
+    BadState(String),
+    /// Writing one of the outputs, such as the trace, audio, frames, a movie or the hash log
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::TruncatedRom(size) => write!(f, "The ROM is truncated ({} bytes)", size),
+            Error::NoHeader => write!(f, "The ROM has no cartridge header"),
+            Error::UnsupportedMapper(rom_type) => write!(f, "{:?} cartridges aren't supported", rom_type),
+            Error::Unimplemented { what, addr } => write!(f, "{} isn't implemented (at {:06x})", what, addr),
+            Error::CpuStopped { addr } => write!(f, "The CPU is halted by STP (PC {:06x})", addr),
+            Error::Bus(ref error) => write!(f, "Bus error: {}", error),
+            Error::BadState(ref message) => write!(f, "{}", message),
+            Error::Io(ref error) => write!(f, "Could not write output: {}", error),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<BusError> for Error {
+    fn from(error: BusError) -> Error {
+        Error::Bus(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
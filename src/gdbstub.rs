@@ -0,0 +1,446 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str;
+
+use cpu::ProcessorStatus;
+use error::Error;
+use memory::{BusAccess, WatchKind, Watchpoint};
+use snes::SNES;
+
+// GDB has no 65816 architecture of its own, so the register layout is described to it here.
+// The program counter is the full 24-bit address, program bank included.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rustsnes.w65c816">
+    <reg name="a" bitsize="16" regnum="0"/>
+    <reg name="x" bitsize="16"/>
+    <reg name="y" bitsize="16"/>
+    <reg name="s" bitsize="16" type="data_ptr"/>
+    <reg name="d" bitsize="16"/>
+    <reg name="db" bitsize="8"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+    <reg name="p" bitsize="8"/>
+    <reg name="e" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+// Register widths in bytes, in the order of TARGET_XML
+const REGISTER_SIZES: [usize; 9] = [2, 2, 2, 2, 2, 1, 4, 1, 1];
+
+// How many instructions run between checks for an interrupt from the debugger
+const INTERRUPT_CHECK_INTERVAL: u64 = 4096;
+
+// The largest packet taken from or sent to the debugger, as told to it in qSupported
+const PACKET_SIZE: usize = 0x1000;
+
+const ADDRESS_MASK: usize = 0xFFFFFF;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 10;
+
+/// Serves the GDB remote serial protocol to one debugger over TCP, so GDB, LLDB or an IDE can
+/// set breakpoints and watchpoints, step, and read and write registers and RAM.
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: Vec<usize>,
+    watchpoints: Vec<Watchpoint>,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    /// Waits on a port on the loopback interface for a debugger to connect
+    pub fn listen(port: u16) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        Ok(GdbStub {
+            stream: stream,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            no_ack: false,
+        })
+    }
+
+    /// Answers packets until the debugger detaches, kills the session or disconnects
+    pub fn run(&mut self, snes: &mut SNES) -> io::Result<()> {
+        loop {
+            let packet = match self.read_packet()? {
+                Some(packet) => packet,
+                None => return Ok(()),
+            };
+
+            let reply = match packet.as_bytes().first() {
+                Some(&b'?') => stop_reply(SIGTRAP),
+                Some(&b'g') => read_registers(snes),
+                Some(&b'G') => write_registers(snes, &packet[1..]),
+                Some(&b'p') => read_register(snes, &packet[1..]),
+                Some(&b'P') => write_register(snes, &packet[1..]),
+                Some(&b'm') => read_memory(snes, &packet[1..]),
+                Some(&b'M') => write_memory(snes, &packet[1..]),
+                Some(&b'c') => self.resume(snes, false)?,
+                Some(&b's') => self.resume(snes, true)?,
+                Some(&b'Z') => self.set_breakpoint(snes, &packet[1..], true),
+                Some(&b'z') => self.set_breakpoint(snes, &packet[1..], false),
+                Some(&b'H') => "OK".to_string(),
+                Some(&b'D') => {
+                    self.write_packet("OK")?;
+                    return Ok(());
+                },
+                Some(&b'k') => return Ok(()),
+                _ => self.query(&packet),
+            };
+
+            self.write_packet(&reply)?;
+        }
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE)
+        } else if packet == "QStartNoAckMode" {
+            // Takes effect after this reply is acknowledged
+            self.no_ack = true;
+            "OK".to_string()
+        } else if let Some(arguments) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            read_target_xml(arguments)
+        } else {
+            match packet {
+                "qAttached" => "1".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                _ => String::new(), // not supported
+            }
+        }
+    }
+
+    fn resume(&mut self, snes: &mut SNES, single_step: bool) -> io::Result<String> {
+        let mut steps = 0;
+
+        loop {
+            match snes.step_instruction() {
+                Ok(_) => {},
+                Err(Error::Bus(_)) => return Ok(stop_reply(SIGBUS)),
+                Err(_) => return Ok(stop_reply(SIGILL)),
+            }
+
+            if let Some(hit) = snes.memory_mut().take_watch_hit() {
+                let access_watch = self.watchpoints.iter()
+                    .any(|watchpoint| watchpoint.kind == WatchKind::Access && hit.addr >= watchpoint.start && hit.addr <= watchpoint.end);
+
+                let kind = match hit.access {
+                    _ if access_watch => "awatch",
+                    BusAccess::Read => "rwatch",
+                    BusAccess::Write(_) => "watch",
+                };
+
+                return Ok(format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.addr));
+            }
+
+            let cpu = snes.cpu();
+
+            if single_step || self.breakpoints.contains(&((cpu.program_bank << 16) | cpu.program_counter)) {
+                return Ok(stop_reply(SIGTRAP));
+            }
+
+            steps += 1;
+
+            if steps % INTERRUPT_CHECK_INTERVAL == 0 && self.interrupted()? {
+                return Ok(stop_reply(SIGINT));
+            }
+        }
+    }
+
+    // Z0/z0 are execution breakpoints; Z2, Z3 and Z4 are write, read and access watchpoints
+    fn set_breakpoint(&mut self, snes: &mut SNES, arguments: &str, insert: bool) -> String {
+        let fields: Vec<&str> = arguments.split(',').collect();
+
+        let (kind, addr, length) = match (fields.first(), fields.get(1).and_then(|addr| parse_hex(addr)), fields.get(2)) {
+            (Some(&kind), Some(addr), Some(length)) => (kind, addr & ADDRESS_MASK, parse_hex(length).unwrap_or(1).max(1)),
+            _ => return "E01".to_string(),
+        };
+
+        let watch_kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.push(addr);
+                } else if let Some(index) = self.breakpoints.iter().position(|&breakpoint| breakpoint == addr) {
+                    self.breakpoints.remove(index);
+                }
+
+                return "OK".to_string();
+            },
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+
+        let end = addr.saturating_add(length - 1).min(ADDRESS_MASK);
+        let watchpoint = Watchpoint { start: addr, end: end, kind: watch_kind };
+
+        if insert {
+            self.watchpoints.push(watchpoint);
+        } else if let Some(index) = self.watchpoints.iter().position(|&existing| existing == watchpoint) {
+            self.watchpoints.remove(index);
+        }
+
+        snes.memory_mut().set_watchpoints(self.watchpoints.clone());
+        "OK".to_string()
+    }
+
+    // GDB interrupts a running target by sending a lone 0x03 outside any packet
+    fn interrupted(&mut self) -> io::Result<bool> {
+        let mut byte = [0];
+
+        self.stream.set_nonblocking(true)?;
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(1) => Ok(byte[0] == 0x03),
+            Ok(_) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The debugger disconnected")),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    // Packets are $data#checksum, acknowledged with + or - unless no-ack mode is on. Returns
+    // None once the connection closes. Packets longer than PACKET_SIZE are answered with an
+    // error without being kept.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                Some(b'$') => {},
+                Some(_) => continue, // acks and stray interrupts
+                None => return Ok(None),
+            }
+
+            let mut data = Vec::new();
+            let mut oversized = false;
+
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) if data.len() < PACKET_SIZE => data.push(byte),
+                    Some(_) => oversized = true,
+                    None => return Ok(None),
+                }
+            }
+
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected = u8::from_str_radix(&String::from_utf8_lossy(&checksum), 16).ok();
+            let actual = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+            if oversized {
+                if !self.no_ack {
+                    self.stream.write_all(b"+")?;
+                }
+
+                self.write_packet("E01")?;
+                continue;
+            }
+
+            if !self.no_ack {
+                self.stream.write_all(if expected == Some(actual) { b"+" } else { b"-" })?;
+            }
+
+            if self.no_ack || expected == Some(actual) {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+
+        for &byte in data.as_bytes() {
+            match byte {
+                b'#' | b'$' | b'}' | b'*' => packet.extend_from_slice(&[b'}', byte ^ 0x20]),
+                _ => packet.push(byte),
+            }
+        }
+
+        let checksum = packet[1..].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+
+        loop {
+            self.stream.write_all(&packet)?;
+
+            if self.no_ack || self.read_byte()? != Some(b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+// Just hex digits; from_str_radix would take a sign too
+fn parse_hex(text: &str) -> Option<usize> {
+    if !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn register_values(snes: &SNES) -> [usize; 9] {
+    let cpu = snes.cpu();
+
+    [
+        cpu.accumulator as usize, cpu.index_x as usize, cpu.index_y as usize, cpu.stack_pointer,
+        cpu.direct_page, cpu.data_bank, (cpu.program_bank << 16) | cpu.program_counter,
+        cpu.processor_status.as_byte() as usize, cpu.emulation_mode as usize,
+    ]
+}
+
+fn set_register_value(snes: &mut SNES, index: usize, value: usize) {
+    let cpu = snes.cpu_mut();
+
+    match index {
+        0 => cpu.accumulator = value as u16,
+        1 => cpu.index_x = value as u16,
+        2 => cpu.index_y = value as u16,
+        3 => cpu.stack_pointer = value & 0xFFFF,
+        4 => cpu.direct_page = value & 0xFFFF,
+        5 => cpu.data_bank = value & 0xFF,
+        6 => {
+            cpu.program_bank = (value >> 16) & 0xFF;
+            cpu.program_counter = value & 0xFFFF;
+        },
+        7 => cpu.processor_status = ProcessorStatus::from_byte(value as u8),
+        _ => cpu.emulation_mode = value & 1 != 0,
+    }
+}
+
+// Register values go over the wire as little endian hex bytes
+fn encode(value: usize, size: usize) -> String {
+    (0..size).map(|byte| format!("{:02x}", (value >> (byte * 8)) & 0xFF)).collect()
+}
+
+fn decode(hex: &str) -> Option<usize> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len() / 2).rev().try_fold(0, |value, byte| {
+        hex.get(byte * 2..byte * 2 + 2).and_then(parse_hex).map(|data| (value << 8) | data)
+    })
+}
+
+fn read_registers(snes: &SNES) -> String {
+    register_values(snes).iter().zip(REGISTER_SIZES.iter()).map(|(&value, &size)| encode(value, size)).collect()
+}
+
+fn write_registers(snes: &mut SNES, hex: &str) -> String {
+    let mut offset = 0;
+
+    for (index, size) in REGISTER_SIZES.iter().enumerate() {
+        match hex.get(offset..offset + size * 2).and_then(decode) {
+            Some(value) => set_register_value(snes, index, value),
+            None => return "E01".to_string(),
+        }
+
+        offset += size * 2;
+    }
+
+    "OK".to_string()
+}
+
+fn read_register(snes: &SNES, arguments: &str) -> String {
+    match parse_hex(arguments).filter(|&index| index < REGISTER_SIZES.len()) {
+        Some(index) => encode(register_values(snes)[index], REGISTER_SIZES[index]),
+        None => "E01".to_string(),
+    }
+}
+
+fn write_register(snes: &mut SNES, arguments: &str) -> String {
+    let mut parts = arguments.splitn(2, '=');
+    let index = parts.next().and_then(parse_hex).filter(|&index| index < REGISTER_SIZES.len());
+    let value = parts.next().and_then(decode);
+
+    match (index, value) {
+        (Some(index), Some(value)) => {
+            set_register_value(snes, index, value);
+            "OK".to_string()
+        },
+        _ => "E01".to_string(),
+    }
+}
+
+// I/O registers read back as open bus rather than being read, which could change them. Reads
+// are cut short to what fits in a packet, which GDB takes as a partial read.
+fn read_memory(snes: &SNES, arguments: &str) -> String {
+    let mut parts = arguments.splitn(2, ',');
+
+    match (parts.next().and_then(parse_hex), parts.next().and_then(parse_hex)) {
+        (Some(addr), Some(length)) => {
+            let addr = addr & ADDRESS_MASK;
+
+            (0..length.min(PACKET_SIZE / 2))
+                .map(|offset| format!("{:02x}", snes.memory().peek_byte(addr.wrapping_add(offset) & ADDRESS_MASK)))
+                .collect()
+        },
+        _ => "E01".to_string(),
+    }
+}
+
+// Only RAM and SRAM can be written
+fn write_memory(snes: &mut SNES, arguments: &str) -> String {
+    let mut parts = arguments.splitn(2, ':');
+    let mut location = parts.next().unwrap_or("").splitn(2, ',');
+    let addr = location.next().and_then(parse_hex);
+    let data = parts.next().unwrap_or("");
+
+    let addr = match addr {
+        Some(addr) if data.len().is_multiple_of(2) => addr & ADDRESS_MASK,
+        _ => return "E01".to_string(),
+    };
+
+    for (offset, hex) in data.as_bytes().chunks(2).enumerate() {
+        let byte = match str::from_utf8(hex).ok().and_then(parse_hex) {
+            Some(byte) if byte <= 0xFF => byte as u8,
+            _ => return "E01".to_string(),
+        };
+
+        if !snes.memory_mut().poke_byte((addr + offset) & ADDRESS_MASK, byte) {
+            return "E02".to_string();
+        }
+    }
+
+    "OK".to_string()
+}
+
+fn read_target_xml(arguments: &str) -> String {
+    let mut parts = arguments.splitn(2, ',');
+
+    match (parts.next().and_then(parse_hex), parts.next().and_then(parse_hex)) {
+        (Some(offset), Some(length)) => {
+            let start = offset.min(TARGET_XML.len());
+            let end = offset.saturating_add(length).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { "m" } else { "l" };
+
+            format!("{}{}", more, &TARGET_XML[start..end])
+        },
+        _ => "E01".to_string(),
+    }
+}
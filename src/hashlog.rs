@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+const MAGIC: &str = "rustsnes-hashes";
+const VERSION: u32 = 1;
+
+/// Hashes of the whole machine state taken at the start of every `interval`th frame, one line
+/// each, for finding where two runs of the same input stop behaving the same:
+///
+/// ```text
+/// rustsnes-hashes 1
+/// rom-crc32 1a2b3c4d
+/// interval 60
+/// 0 9d3c1f0e6a7b2c45
+/// 60 04e2b8d17f3a9c60
+/// ```
+pub struct HashLog {
+    pub rom_crc32: u32,
+    pub interval: u64,
+    pub hashes: Vec<(u64, u64)>, // frame, hash
+}
+
+/// Where two hash logs first differ
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Comparison {
+    /// Every frame both logs have a hash for matched
+    Match { compared: usize },
+    /// The logs have no frames in common, so nothing could be compared
+    Disjoint,
+    Diverged { frame: u64, first: u64, second: u64 },
+}
+
+impl HashLog {
+    pub fn load(path: &str) -> Result<HashLog, String> {
+        let mut text = String::new();
+
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("Could not read hash log {}: {}", path, err))?;
+
+        HashLog::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn parse(text: &str) -> Result<HashLog, String> {
+        let mut lines = text.lines().enumerate();
+        let mut header = |name: &str| -> Result<String, String> {
+            match lines.next() {
+                Some((_, line)) if line.starts_with(name) => Ok(line[name.len()..].trim().to_string()),
+                _ => Err(format!("Missing {} line", name)),
+            }
+        };
+
+        let version = header(MAGIC)?;
+
+        if version.parse::<u32>().map_err(|_| format!("Bad version {}", version))? > VERSION {
+            return Err(format!("The hash log is from a newer version (format {})", version));
+        }
+
+        let crc = header("rom-crc32")?;
+        let rom_crc32 = u32::from_str_radix(&crc, 16).map_err(|_| format!("Bad CRC32 {}", crc))?;
+        let interval = header("interval")?;
+        let interval = interval.parse::<u64>().map_err(|_| format!("Bad interval {}", interval))?;
+
+        let mut hashes = Vec::new();
+
+        for (number, line) in lines {
+            let mut fields = line.split_whitespace();
+            let entry = match (fields.next(), fields.next()) {
+                (Some(frame), Some(hash)) => frame.parse::<u64>().ok().zip(u64::from_str_radix(hash, 16).ok()),
+                (None, None) => continue,
+                _ => None,
+            };
+
+            hashes.push(entry.ok_or(format!("Line {}: expected a frame and a hash", number + 1))?);
+        }
+
+        Ok(HashLog {
+            rom_crc32: rom_crc32,
+            interval: interval,
+            hashes: hashes,
+        })
+    }
+
+    /// Compares the frames both logs have hashes for, in order, so logs taken at different
+    /// intervals or run for different lengths can still be checked against each other
+    pub fn compare(&self, other: &HashLog) -> Comparison {
+        let mut theirs = other.hashes.iter().peekable();
+        let mut compared = 0;
+
+        for &(frame, hash) in self.hashes.iter() {
+            while theirs.peek().is_some_and(|&&(their_frame, _)| their_frame < frame) {
+                theirs.next();
+            }
+
+            if let Some(&&(their_frame, their_hash)) = theirs.peek() {
+                if their_frame == frame {
+                    if their_hash != hash {
+                        return Comparison::Diverged { frame: frame, first: hash, second: their_hash };
+                    }
+
+                    compared += 1;
+                }
+            }
+        }
+
+        if compared == 0 {
+            Comparison::Disjoint
+        } else {
+            Comparison::Match { compared: compared }
+        }
+    }
+}
+
+/// Writes a `HashLog` as the console runs
+pub struct HashLogger {
+    output: BufWriter<File>,
+    interval: u64,
+}
+
+impl HashLogger {
+    pub fn create(path: &str, rom_crc32: u32, interval: u64) -> io::Result<HashLogger> {
+        let mut output = BufWriter::new(File::create(path)?);
+
+        writeln!(output, "{} {}", MAGIC, VERSION)?;
+        writeln!(output, "rom-crc32 {:08x}", rom_crc32)?;
+        writeln!(output, "interval {}", interval)?;
+
+        Ok(HashLogger {
+            output: output,
+            interval: interval,
+        })
+    }
+
+    pub fn wants(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.interval)
+    }
+
+    pub fn record(&mut self, frame: u64, hash: u64) -> io::Result<()> {
+        writeln!(self.output, "{} {:016x}", frame, hash)
+    }
+}
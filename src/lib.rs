@@ -0,0 +1,41 @@
+#![allow(unused_variables, ellipsis_inclusive_range_patterns)]
+#![allow(clippy::redundant_field_names, clippy::upper_case_acronyms, clippy::unnecessary_cast,
+         clippy::if_same_then_else, clippy::into_iter_on_ref, clippy::precedence, clippy::new_without_default)]
+
+//! A Super Nintendo emulator. `SNES` is the whole console: load a ROM into it, feed it input and
+//! run it a frame or an instruction at a time.
+
+extern crate flate2;
+extern crate serde_json;
+
+mod alu;
+pub mod apu;
+pub mod audio;
+pub mod bus;
+pub mod controller;
+pub mod cpu;
+mod crc32;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod gdbstub;
+pub mod hashlog;
+pub mod loader;
+pub mod memory;
+mod modes;
+pub mod movie;
+pub mod patch;
+pub mod ppu;
+pub mod rewind;
+pub mod screenshot;
+pub mod script;
+pub mod snes;
+pub mod state;
+pub mod symbols;
+pub mod trace;
+pub mod video;
+
+pub use controller::PortInput;
+pub use error::Error;
+pub use memory::{BusError, BusErrorPolicy, Rom, RomType};
+pub use snes::{MachineEvent, Region, SNES};
@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+use crc32;
+use memory::COPIER_HEADER_SIZE;
+use patch::{self, PatchFormat};
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZIP_LOCAL_HEADER: u32 = 0x04034B50;
+const ZIP_CENTRAL_HEADER: u32 = 0x02014B50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x06054B50;
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+
+const ROM_EXTENSIONS: [&str; 5] = ["sfc", "smc", "swc", "fig", "bin"];
+
+pub fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+
+    File::open(path).and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|err| format!("Could not read {}: {}", path, err))?;
+
+    Ok(data)
+}
+
+/// Reads a ROM, unpacking it from a .zip or .gz archive if need be, and applies a patch to it.
+/// Without an explicit patch, an .ips, .ups or .bps file next to the ROM with the same name is
+/// used if there is one. Returns the ROM and the path of the patch applied to it.
+pub fn load_rom(path: &str, patch_path: Option<&str>) -> Result<(Vec<u8>, Option<String>), String> {
+    let data = read_file(path)?;
+
+    let rom = if data.starts_with(GZIP_MAGIC) {
+        gunzip(&data).map_err(|err| format!("Could not decompress {}: {}", path, err))?
+    } else if data.len() >= 4 && read_u32(&data, 0) == ZIP_LOCAL_HEADER {
+        unzip_rom(&data).map_err(|err| format!("Could not unzip {}: {}", path, err))?
+    } else {
+        data
+    };
+
+    let patch_path = match patch_path {
+        Some(patch_path) => Some(patch_path.to_string()),
+        None => find_sibling_patch(path),
+    };
+
+    match patch_path {
+        Some(patch_path) => {
+            let patch = read_file(&patch_path)?;
+            let patched = apply_patch(&rom, &patch).map_err(|err| format!("Could not apply {}: {}", patch_path, err))?;
+
+            Ok((patched, Some(patch_path)))
+        },
+        None => Ok((rom, None)),
+    }
+}
+
+// UPS and BPS patches are made against headerless images, so a copier header is set aside while
+// they're applied and put back afterwards. IPS patches address the file as it is.
+fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let format = PatchFormat::detect(patch);
+    let headered = rom.len() % 1024 == COPIER_HEADER_SIZE;
+
+    if format == Some(PatchFormat::IPS) || !headered {
+        return patch::apply(rom, patch);
+    }
+
+    patch::apply(rom, patch).or_else(|err| {
+        let mut patched = rom[..COPIER_HEADER_SIZE].to_vec();
+        patched.extend(patch::apply(&rom[COPIER_HEADER_SIZE..], patch).map_err(|_| err)?);
+
+        Ok(patched)
+    })
+}
+
+// game.sfc, game.zip and game.sfc.gz all look for game.bps, game.ups and game.ips
+fn find_sibling_patch(path: &str) -> Option<String> {
+    let mut stem = Path::new(path).with_extension("");
+
+    if path.ends_with(".gz") && stem.extension().is_some() {
+        stem = stem.with_extension("");
+    }
+
+    [PatchFormat::BPS, PatchFormat::UPS, PatchFormat::IPS].iter()
+        .map(|format| stem.with_extension(format.extension()))
+        .find(|candidate| candidate.is_file())
+        .map(|candidate| candidate.to_string_lossy().into_owned())
+}
+
+fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+
+    GzDecoder::new(data).read_to_end(&mut rom).map_err(|err| err.to_string())?;
+
+    Ok(rom)
+}
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+// Takes the first file with a ROM extension, or the largest file if none has one
+fn unzip_rom(data: &[u8]) -> Result<Vec<u8>, String> {
+    let entries = read_zip_directory(data)?;
+
+    let has_rom_extension = |entry: &&ZipEntry| {
+        let name = entry.name.to_lowercase();
+        ROM_EXTENSIONS.iter().any(|extension| name.ends_with(&format!(".{}", extension)))
+    };
+
+    let entry = entries.iter().find(has_rom_extension)
+        .or_else(|| entries.iter().max_by_key(|entry| entry.size))
+        .ok_or("The archive is empty")?;
+
+    if entry.header_offset + 30 > data.len() || read_u32(data, entry.header_offset) != ZIP_LOCAL_HEADER {
+        return Err(format!("Corrupt local header for {}", entry.name));
+    }
+
+    let name_length = read_u16(data, entry.header_offset + 26) as usize;
+    let extra_length = read_u16(data, entry.header_offset + 28) as usize;
+    let start = entry.header_offset + 30 + name_length + extra_length;
+    let compressed = data.get(start..start + entry.compressed_size).ok_or("The archive is truncated")?;
+
+    let rom = match entry.method {
+        ZIP_STORED => compressed.to_vec(),
+        ZIP_DEFLATED => {
+            let mut rom = Vec::with_capacity(entry.size);
+            DeflateDecoder::new(compressed).read_to_end(&mut rom).map_err(|err| err.to_string())?;
+            rom
+        },
+        method => return Err(format!("Unsupported compression method {} for {}", method, entry.name)),
+    };
+
+    if crc32::crc32(&rom) != entry.crc {
+        return Err(format!("{} failed its CRC check", entry.name));
+    }
+
+    Ok(rom)
+}
+
+// The central directory at the end of the archive has the sizes even when the local headers
+// defer them to a data descriptor
+fn read_zip_directory(data: &[u8]) -> Result<Vec<ZipEntry>, String> {
+    let end = (0..data.len().saturating_sub(21)).rev()
+        .find(|&offset| read_u32(data, offset) == ZIP_END_OF_DIRECTORY)
+        .ok_or("Missing the end of central directory record")?;
+
+    let count = read_u16(data, end + 10) as usize;
+    let mut offset = read_u32(data, end + 16) as usize;
+    let mut entries = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        if offset + 46 > data.len() || read_u32(data, offset) != ZIP_CENTRAL_HEADER {
+            return Err("Corrupt central directory".to_string());
+        }
+
+        let name_length = read_u16(data, offset + 28) as usize;
+        let extra_length = read_u16(data, offset + 30) as usize;
+        let comment_length = read_u16(data, offset + 32) as usize;
+        let name = data.get(offset + 46..offset + 46 + name_length).ok_or("Corrupt central directory")?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: read_u16(data, offset + 10),
+            crc: read_u32(data, offset + 16),
+            compressed_size: read_u32(data, offset + 20) as usize,
+            size: read_u32(data, offset + 24) as usize,
+            header_offset: read_u32(data, offset + 42) as usize,
+        });
+
+        offset += 46 + name_length + extra_length + comment_length;
+    }
+
+    Ok(entries)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    data[offset] as u16 | (data[offset + 1] as u16) << 8
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    read_u16(data, offset) as u32 | (read_u16(data, offset + 2) as u32) << 16
+}
@@ -1,41 +1,694 @@
-#![allow(dead_code, unused_variables)]
+#![allow(clippy::redundant_field_names)]
+
+extern crate rustsnes;
 
 use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Write};
+use std::process;
+
+use rustsnes::{apu, controller, loader, screenshot, state};
+use rustsnes::audio::{AudioOutput, AudioSink, PcmWriter, WavWriter};
+use rustsnes::cpu::CPU;
+use rustsnes::debugger::Debugger;
+use rustsnes::gdbstub::GdbStub;
+use rustsnes::hashlog::{Comparison, HashLog, HashLogger};
+use rustsnes::disasm::Disassembler;
+use rustsnes::memory::Memory;
+use rustsnes::movie::{Movie, MovieEvent, MoviePlayer, MovieRecorder, StartState};
+use rustsnes::script::InputScript;
+use rustsnes::state::StateSlots;
+use rustsnes::symbols::Symbols;
+use rustsnes::trace::TraceLogger;
+use rustsnes::video::{AviWriter, FrameSelection, PngDumper, Y4mWriter};
+use rustsnes::{BusErrorPolicy, Error, Region, Rom, RomType, SNES};
+
+const USAGE: &str = "usage: rustsnes [OPTIONS] ROM
+       rustsnes --info ROM
+       rustsnes disasm [--mapper MAPPER] [--symbols FILE] ROM [ADDRESS [COUNT]]
+       rustsnes compare-hashes LOG LOG
+
+disasm lists COUNT instructions (default 32) from a bank:address such as 80:8000 or a label,
+starting at the reset vector if none is given. compare-hashes reports the first frame where two
+--hash-log runs differ, exiting with status 1 if they do or have no frames in common.
+
+Cartridge:
+    --info              print the cartridge header and exit
+    --patch FILE        apply an IPS, UPS or BPS patch to the ROM; without this a patch with the
+                        ROM's name next to it is applied. ROMs can be inside .zip or .gz files
+    --region REGION     run as an ntsc or pal console instead of the cartridge's region
+    --mapper MAPPER     map the ROM as lorom, hirom, fastlorom or fasthirom regardless of its header
+    --sram FILE         start with the battery backed RAM loaded from a file
+    --load-state FILE   start from a save state
+    --load-slot N       start from numbered save state slot N (0-9), kept next to the ROM as
+                        ROM.stateN
 
-mod cpu;
-mod apu;
-mod ppu;
-mod memory;
-mod modes;
-mod snes;
+Running:
+    --frames N          stop after N frames
+    --headless          run as fast as possible instead of at the console's frame rate
+    --rewind SECONDS    keep a snapshot of every frame for the last SECONDS, so the debugger's
+                        back command and rewind entries in an input script can step backwards
+    --debug             start in the debugger instead of running; type help at its prompt
+    --gdb PORT          wait for GDB or LLDB to connect on a local port and let it drive the console
+    --trace FILE        log every instruction to a file as it runs
+    --trace-range RANGE only trace instructions in a range of addresses such as 80:8000-80:ffff;
+                        can be given more than once
+    --symbols FILE      show labels from a WLA-DX .sym, ca65 .dbg or VICE label file, or a bass or
+                        asar symbol list in traces and the debugger, which can break on them
+    --hash-log FILE     write a hash of the whole machine state every 60 frames, to compare
+                        runs of the same input with compare-hashes
+    --hash-interval N   hash every N frames instead
+    --bus-errors POLICY what to do on unmapped accesses and ROM writes: ignore (default), log
+                        or break, which stops emulation
+
+Output, written when emulation stops unless noted:
+    --screenshot FILE   save the last frame as a PNG image, or a PPM if FILE ends in .ppm
+    --dump-frames DIR   save every frame as it finishes to DIR/frame-NNNNNN.png, counting from 0
+    --dump-every N      only dump every Nth frame
+    --dump-frame N      only dump frame N
+    --y4m FILE          stream the video to an uncompressed YUV4MPEG2 file; add --wav for sound
+    --avi FILE          stream the video and audio to an uncompressed AVI file
+    --save-state FILE   save the machine state
+    --save-slot N       save the machine state to a numbered slot
+    --wav FILE          write the APU output to a 16-bit stereo WAV file as it plays
+    --pcm FILE          write the APU output as raw 16-bit little endian stereo PCM as it plays,
+                        - for stdout
+    --sample-rate HZ    resample the audio output from 32000 Hz to 44100 or 48000 Hz
+
+Input:
+    --port1 DEVICE      plug a device into a controller port: none, pad, multitap, mouse,
+    --port2 DEVICE      superscope or justifier (default pad)
+    --input FILE        feed the controllers from a text or .json schedule of frame -> buttons
+    --record FILE       record every frame's input to a movie
+    --play FILE         play back a movie, reporting the frame where it desyncs";
+
+struct Options {
+    rom_path: String,
+    info: bool,
+    patch_path: Option<String>,
+    region: Option<Region>,
+    mapper: Option<RomType>,
+    sram_path: Option<String>,
+    load_state_path: Option<String>,
+    load_slot: Option<u32>,
+    frames: Option<u64>,
+    headless: bool,
+    rewind_seconds: Option<u32>,
+    debug: bool,
+    gdb_port: Option<u16>,
+    trace_path: Option<String>,
+    trace_ranges: Vec<(usize, usize)>,
+    symbols_path: Option<String>,
+    hash_log_path: Option<String>,
+    hash_interval: u64,
+    bus_error_policy: BusErrorPolicy,
+    screenshot_path: Option<String>,
+    dump_directory: Option<String>,
+    dump_selection: FrameSelection,
+    y4m_path: Option<String>,
+    avi_path: Option<String>,
+    save_state_path: Option<String>,
+    save_slot: Option<u32>,
+    wav_path: Option<String>,
+    pcm_path: Option<String>,
+    sample_rate: u32,
+    ports: [String; 2],
+    input_path: Option<String>,
+    record_path: Option<String>,
+    play_path: Option<String>,
+}
 
 fn main() {
-    let rom = { 
-        let romname = env::args().nth(1).unwrap();
-        read_bin(romname)
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    if args.first().map(String::as_str) == Some("compare-hashes") {
+        match compare_hashes(&args[1..]) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                process::exit(1);
+            },
+        }
+    }
+
+    if args.first().map(String::as_str) == Some("disasm") {
+        disasm_command(&args[1..]).unwrap_or_else(|err| {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(1);
+        });
+        return;
+    }
+
+    let options = parse_args(args).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(1);
+    });
+
+    let (rom, patch_path) = loader::load_rom(&options.rom_path, options.patch_path.as_deref())
+        .unwrap_or_else(|err| fail(err));
+
+    if let Some(patch_path) = patch_path {
+        eprintln!("Applied patch {}", patch_path);
+    }
+
+    let mut mem = Memory::with_rom_type(rom, options.mapper)
+        .unwrap_or_else(|err| fail(format!("Could not load {}: {}", options.rom_path, err)));
+
+    if options.info {
+        print_info(&mem.rom);
+        return;
+    }
+
+    let region = options.region.unwrap_or_else(|| mem.rom.region());
+    mem.set_region(region);
+    mem.set_bus_error_policy(options.bus_error_policy);
+
+    let mut ports = options.ports.clone();
+    let movie = options.play_path.as_ref().map(|path| Movie::load(path).unwrap_or_else(|err| fail(err)));
+
+    let start = match (movie.as_ref(), options.sram_path.as_ref()) {
+        (Some(movie), _) => {
+            if movie.rom_crc32 != mem.rom.checksum() {
+                eprintln!("Warning: the movie was recorded with a different ROM (CRC32 {:08x})", movie.rom_crc32);
+            }
+
+            ports = movie.ports.clone();
+
+            match movie.start {
+                StartState::Sram(ref sram) => StartState::Sram(sram.clone()),
+                StartState::PowerOn => StartState::PowerOn,
+            }
+        },
+        (None, Some(path)) => StartState::Sram(loader::read_file(path).unwrap_or_else(|err| fail(err))),
+        (None, None) => StartState::PowerOn,
     };
 
-    let mem = memory::Memory::new(rom);
-    let ppu = ppu::PPU::new();
-    let apu = apu::APU::new();
-    let cpu = cpu::CPU::new(&mem);
-    let mut snes = snes::SNES::new(cpu, ppu, apu, mem);
+    if let StartState::Sram(ref sram) = start {
+        mem.load_sram(sram);
+    }
 
-    snes.run();
-}
+    for (port, device) in ports.iter().enumerate() {
+        let controller = controller::create(device).unwrap_or_else(|| fail(format!("Unknown controller {}", device)));
+        mem.plug_controller(port, controller);
+    }
+
+    let rom_crc32 = mem.rom.checksum();
+
+    let mut snes = SNES::new(mem);
+
+    snes.set_frame_limit(options.frames);
+    snes.set_realtime(!options.headless);
+
+    if let Some(ref path) = options.input_path {
+        let script = InputScript::load(path).unwrap_or_else(|err| fail(err));
+
+        if script.rewinds() && options.rewind_seconds.is_none() {
+            fail(format!("{} rewinds, which needs --rewind", path));
+        }
+
+        // A rewind replays frames, which movies and hash logs can't hold as they're written one
+        // frame after another
+        if script.rewinds() && (options.record_path.is_some() || options.hash_log_path.is_some()) {
+            fail(format!("{} rewinds, so it can't be recorded with --record or --hash-log", path));
+        }
+
+        snes.set_input_script(script);
+    }
+
+    if let Some(ref directory) = options.dump_directory {
+        match PngDumper::create(directory, options.dump_selection) {
+            Ok(dumper) => snes.add_frame_sink(Box::new(dumper)),
+            Err(err) => fail(format!("Could not create {}: {}", directory, err)),
+        }
+    }
+
+    if let Some(ref path) = options.y4m_path {
+        match Y4mWriter::create(path, region) {
+            Ok(writer) => snes.add_frame_sink(Box::new(writer)),
+            Err(err) => fail(format!("Could not create {}: {}", path, err)),
+        }
+    }
+
+    if let Some(ref path) = options.avi_path {
+        match AviWriter::create(path, region) {
+            Ok(writer) => snes.add_frame_sink(Box::new(writer)),
+            Err(err) => fail(format!("Could not create {}: {}", path, err)),
+        }
+    }
+
+    if let Some(ref path) = options.hash_log_path {
+        match HashLogger::create(path, rom_crc32, options.hash_interval) {
+            Ok(logger) => snes.set_hash_logger(logger),
+            Err(err) => fail(format!("Could not create {}: {}", path, err)),
+        }
+    }
+
+    if let Some(seconds) = options.rewind_seconds {
+        let frame_rate = snes.memory().region().frame_rate();
+        snes.set_rewind((seconds * frame_rate) as usize);
+    }
+
+    let symbols = options.symbols_path.as_ref().map(|path| Symbols::load(path).unwrap_or_else(|err| fail(err)));
+
+    if let Some(ref path) = options.trace_path {
+        let mut tracer = TraceLogger::create(path).unwrap_or_else(|err| fail(format!("Could not create {}: {}", path, err)));
+
+        for &(start, end) in options.trace_ranges.iter() {
+            tracer.add_range(start, end);
+        }
+
+        if let Some(ref symbols) = symbols {
+            tracer.set_symbols(symbols.clone());
+        }
+
+        snes.set_tracer(tracer);
+    }
+
+    if let Some(ref path) = options.load_state_path {
+        let state = loader::read_file(path).unwrap_or_else(|err| fail(err));
+        snes.load_state(&state).unwrap_or_else(|err| fail(format!("Could not load {}: {}", path, err)));
+    }
+
+    let slots = StateSlots::new(&options.rom_path);
+
+    if let Some(slot) = options.load_slot {
+        let state = slots.load(slot).unwrap_or_else(|err| fail(err));
+        snes.load_state(&state).unwrap_or_else(|err| fail(format!("Could not load slot {}: {}", slot, err)));
+    }
+
+    if let Some(output) = open_audio_output(&options) {
+        snes.set_audio_output(output);
+    }
+
+    if let Some(ref path) = options.record_path {
+        match MovieRecorder::create(path, rom_crc32, &ports, &start) {
+            Ok(recorder) => snes.set_recorder(recorder),
+            Err(err) => fail(format!("Could not create {}: {}", path, err)),
+        }
+    }
+
+    if let Some(movie) = movie {
+        snes.set_player(MoviePlayer::new(movie));
+        snes.set_movie_listener(Box::new(|event| match event {
+            MovieEvent::Finished { frame } => eprintln!("Movie finished at frame {}", frame),
+            MovieEvent::Desynced { frame, hash } => {
+                eprintln!("Movie desynced at frame {}: state hash is {:016x}", frame, hash)
+            },
+        }));
+    }
+
+    let result = if let Some(port) = options.gdb_port {
+        eprintln!("Waiting for a debugger on 127.0.0.1:{}", port);
+        GdbStub::listen(port).and_then(|mut stub| stub.run(&mut snes))
+            .unwrap_or_else(|err| fail(format!("GDB connection failed: {}", err)));
+        Ok(())
+    } else if options.debug {
+        let stdin = io::stdin();
+        let mut debugger = Debugger::new();
+        debugger.set_state_slots(StateSlots::new(&options.rom_path));
 
-fn read_bin(rom_path: String) -> Vec<u8> {
-    let mut rom = Vec::new();
-    let mut file = File::open(&rom_path);
+        if let Some(symbols) = symbols {
+            debugger.set_symbols(symbols);
+        }
 
-    if let Ok(ref mut the_file) = file {
-        let _res = the_file.read_to_end(&mut rom);
+        debugger.run(&mut snes, stdin.lock(), io::stdout())
+            .unwrap_or_else(|err| fail(format!("Debugger I/O failed: {}", err)));
+        Ok(())
     } else {
-        panic!("Could not open the file {}", rom_path);
+        snes.run()
+    };
+
+    match result {
+        Err(Error::Bus(ref error)) => {
+            let cpu = snes.cpu();
+            eprintln!("Stopped on bus error: {} (next instruction at {:02x}:{:04x})",
+                      error, cpu.program_bank, cpu.program_counter);
+        },
+        Err(ref err) => eprintln!("Stopped: {}", err),
+        Ok(()) => {},
+    }
+
+    if let Some(ref path) = options.screenshot_path {
+        screenshot::write_image(path, snes.framebuffer())
+            .unwrap_or_else(|err| fail(format!("Could not write {}: {}", path, err)));
+    }
+
+    if let Some(ref path) = options.save_state_path {
+        File::create(path).and_then(|mut file| file.write_all(&snes.save_state()))
+            .unwrap_or_else(|err| fail(format!("Could not write {}: {}", path, err)));
+    }
+
+    if let Some(slot) = options.save_slot {
+        slots.save(slot, &snes.save_state())
+            .unwrap_or_else(|err| fail(format!("Could not write {}: {}", slots.path(slot).display(), err)));
+    }
+
+    // Dropping the console finishes off the WAV, movie and trace files before exiting
+    if result.is_err() {
+        drop(snes);
+        process::exit(1);
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn print_info(rom: &Rom) {
+    let (checksum, complement) = rom.header_checksum();
+    let checksum_ok = checksum == rom.computed_checksum() && checksum ^ complement == 0xFFFF;
+
+    println!("Title:         {}", rom.title());
+    println!("Mapper:        {:?}", rom.rom_type);
+    println!("ROM size:      {} KiB (header says {} KiB)", rom.size() / 1024, rom.header_rom_size());
+    println!("SRAM size:     {} KiB", rom.sram_size());
+    println!("Region:        {:?} (country code {:02x})", rom.region(), rom.country());
+    println!("Version:       1.{}", rom.version());
+    println!("Checksum:      {:04x}, complement {:04x} ({})", checksum, complement, if checksum_ok { "valid" } else { "invalid" });
+    println!("CRC32:         {:08x}", rom.checksum());
+    println!("Reset vector:  {:04x}", rom.reset_vector);
+    println!("Copier header: {}", if rom.headered() { "yes" } else { "no" });
+    println!("Interleaved:   {}", if rom.interleaved { "yes" } else { "no" });
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut info = false;
+    let mut patch_path = None;
+    let mut region = None;
+    let mut mapper = None;
+    let mut sram_path = None;
+    let mut load_state_path = None;
+    let mut load_slot = None;
+    let mut frames = None;
+    let mut headless = false;
+    let mut rewind_seconds = None;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut trace_path = None;
+    let mut trace_ranges = Vec::new();
+    let mut symbols_path = None;
+    let mut hash_log_path = None;
+    let mut hash_interval = 60;
+    let mut bus_error_policy = BusErrorPolicy::Ignore;
+    let mut screenshot_path = None;
+    let mut dump_directory = None;
+    let mut dump_selection = FrameSelection::Every(1);
+    let mut y4m_path = None;
+    let mut avi_path = None;
+    let mut save_state_path = None;
+    let mut save_slot = None;
+    let mut wav_path = None;
+    let mut pcm_path = None;
+    let mut sample_rate = apu::SAMPLE_RATE;
+    let mut ports = ["pad".to_string(), "pad".to_string()];
+    let mut input_path = None;
+    let mut record_path = None;
+    let mut play_path = None;
+
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--info" => info = true,
+            "--patch" => patch_path = Some(value()?),
+            "--region" => {
+                let name = value()?;
+                region = Some(Region::from_name(&name).ok_or(format!("Unknown region {}", name))?);
+            },
+            "--mapper" => {
+                let name = value()?;
+                mapper = Some(RomType::from_name(&name).ok_or(format!("Unknown mapper {}", name))?);
+            },
+            "--sram" => sram_path = Some(value()?),
+            "--load-state" => load_state_path = Some(value()?),
+            "--load-slot" => {
+                let slot = value()?;
+                load_slot = Some(state::parse_slot(&slot).ok_or(format!("Bad save slot {}", slot))?);
+            },
+            "--frames" => {
+                let count = value()?;
+                frames = Some(count.parse::<u64>().map_err(|_| format!("Bad frame count {}", count))?);
+            },
+            "--headless" => headless = true,
+            "--rewind" => {
+                let seconds = value()?;
+                // NTSC has the higher frame rate, so a length that fits in frames there fits anywhere
+                rewind_seconds = Some(seconds.parse::<u32>().ok()
+                    .filter(|seconds| seconds.checked_mul(Region::NTSC.frame_rate()).is_some())
+                    .ok_or(format!("Bad rewind length {}", seconds))?);
+            },
+            "--debug" => debug = true,
+            "--gdb" => {
+                let port = value()?;
+                gdb_port = Some(port.parse::<u16>().map_err(|_| format!("Bad port {}", port))?);
+            },
+            "--trace" => trace_path = Some(value()?),
+            "--trace-range" => {
+                let range = value()?;
+                trace_ranges.push(parse_range(&range).ok_or(format!("Bad address range {}", range))?);
+            },
+            "--symbols" => symbols_path = Some(value()?),
+            "--hash-log" => hash_log_path = Some(value()?),
+            "--hash-interval" => {
+                let interval = value()?;
+                hash_interval = interval.parse::<u64>().ok().filter(|&interval| interval > 0)
+                    .ok_or(format!("Bad hash interval {}", interval))?;
+            },
+            "--bus-errors" => {
+                let policy = value()?;
+                bus_error_policy = BusErrorPolicy::from_name(&policy).ok_or(format!("Unknown bus error policy {}", policy))?;
+            },
+            "--screenshot" => screenshot_path = Some(value()?),
+            "--dump-frames" => dump_directory = Some(value()?),
+            "--dump-every" | "--dump-frame" => {
+                let number = value()?;
+                let number = number.parse::<u64>().map_err(|_| format!("Bad frame number {}", number))?;
+
+                dump_selection = if arg == "--dump-frame" {
+                    FrameSelection::Only(number)
+                } else if number > 0 {
+                    FrameSelection::Every(number)
+                } else {
+                    return Err("--dump-every needs a number above 0".to_string());
+                };
+            },
+            "--y4m" => y4m_path = Some(value()?),
+            "--avi" => avi_path = Some(value()?),
+            "--save-state" => save_state_path = Some(value()?),
+            "--save-slot" => {
+                let slot = value()?;
+                save_slot = Some(state::parse_slot(&slot).ok_or(format!("Bad save slot {}", slot))?);
+            },
+            "--wav" => wav_path = Some(value()?),
+            "--pcm" => pcm_path = Some(value()?),
+            "--sample-rate" => {
+                let rate = value()?;
+
+                sample_rate = match rate.as_str() {
+                    "32000" => 32000,
+                    "44100" => 44100,
+                    "48000" => 48000,
+                    _ => return Err(format!("Unsupported sample rate {}", rate)),
+                };
+            },
+            "--port1" | "--port2" => {
+                let device = value()?;
+
+                if controller::create(&device).is_none() {
+                    return Err(format!("Unknown controller {}", device));
+                }
+
+                ports[if arg == "--port1" { 0 } else { 1 }] = device;
+            },
+            "--input" => input_path = Some(value()?),
+            "--record" => record_path = Some(value()?),
+            "--play" => play_path = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if rom_path.is_some() => return Err(format!("Only one ROM can be given, not {}", arg)),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    if wav_path.is_some() && pcm_path.is_some() {
+        return Err("Only one of --wav and --pcm can be given".to_string());
+    }
+
+    if debug && gdb_port.is_some() {
+        return Err("Only one of --debug and --gdb can be given".to_string());
+    }
+
+    if load_state_path.is_some() && load_slot.is_some() {
+        return Err("Only one of --load-state and --load-slot can be given".to_string());
+    }
+
+    if record_path.is_some() && play_path.is_some() {
+        return Err("Only one of --record and --play can be given".to_string());
     }
 
-    rom
+    // Movies start from power-on or SRAM, so one started from a save state couldn't play back
+    if (load_state_path.is_some() || load_slot.is_some()) && (record_path.is_some() || play_path.is_some()) {
+        return Err("--load-state and --load-slot can't be used with --record or --play".to_string());
+    }
+
+    Ok(Options {
+        rom_path: rom_path.ok_or("No ROM given")?,
+        info: info,
+        patch_path: patch_path,
+        region: region,
+        mapper: mapper,
+        sram_path: sram_path,
+        load_state_path: load_state_path,
+        load_slot: load_slot,
+        frames: frames,
+        headless: headless,
+        rewind_seconds: rewind_seconds,
+        debug: debug,
+        gdb_port: gdb_port,
+        trace_path: trace_path,
+        trace_ranges: trace_ranges,
+        symbols_path: symbols_path,
+        hash_log_path: hash_log_path,
+        hash_interval: hash_interval,
+        bus_error_policy: bus_error_policy,
+        screenshot_path: screenshot_path,
+        dump_directory: dump_directory,
+        dump_selection: dump_selection,
+        y4m_path: y4m_path,
+        avi_path: avi_path,
+        save_state_path: save_state_path,
+        save_slot: save_slot,
+        wav_path: wav_path,
+        pcm_path: pcm_path,
+        sample_rate: sample_rate,
+        ports: ports,
+        input_path: input_path,
+        record_path: record_path,
+        play_path: play_path,
+    })
 }
 
+// 24-bit addresses are hex, with an optional colon after the bank: 808000 or 80:8000
+fn parse_address(text: &str) -> Option<usize> {
+    usize::from_str_radix(&text.replace(':', ""), 16).ok().filter(|&addr| addr <= 0xFFFFFF)
+}
+
+// 808000-80ffff or 80:8000-80:ffff
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let mut parts = range.splitn(2, '-');
+    let start = parse_address(parts.next()?)?;
+    let end = parse_address(parts.next()?)?;
+
+    if start <= end { Some((start, end)) } else { None }
+}
+
+// Lists code straight from the cartridge, starting in emulation mode as the CPU does at reset
+fn disasm_command(args: &[String]) -> Result<(), String> {
+    let mut mapper = None;
+    let mut symbols = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mapper" => {
+                let name = args.next().ok_or("--mapper needs a value")?;
+                mapper = Some(RomType::from_name(name).ok_or(format!("Unknown mapper {}", name))?);
+            },
+            "--symbols" => {
+                let path = args.next().ok_or("--symbols needs a value")?;
+                symbols = Some(Symbols::load(path)?);
+            },
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let rom_path = positional.first().ok_or("No ROM given")?;
+    let (rom, _) = loader::load_rom(rom_path, None)?;
+    let mut mem = Memory::with_rom_type(rom, mapper).map_err(|err| format!("Could not load {}: {}", rom_path, err))?;
+
+    let start = match positional.get(1) {
+        Some(address) => symbols.as_ref().and_then(|symbols| symbols.address(address))
+            .or_else(|| parse_address(address))
+            .ok_or(format!("Bad address {}", address))?,
+        None => {
+            let cpu = CPU::new(&mut mem);
+            (cpu.program_bank << 16) | cpu.program_counter
+        },
+    };
+
+    let count = match positional.get(2) {
+        Some(count) => count.parse::<usize>().map_err(|_| format!("Bad instruction count {}", count))?,
+        None => 32,
+    };
+
+    for instruction in Disassembler::new(&mem, start, true, true, true).take(count) {
+        let bytes: Vec<String> = instruction.bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        if let Some(label) = symbols.as_ref().and_then(|symbols| symbols.lookup(instruction.addr)) {
+            println!("{}:", label);
+        }
+
+        println!("{:02x}:{:04x}  {:<12} {}", instruction.addr >> 16, instruction.addr & 0xFFFF, bytes.join(" "),
+                 instruction.text(symbols.as_ref()));
+    }
+
+    Ok(())
+}
+
+// Returns whether the runs matched on at least one frame
+fn compare_hashes(args: &[String]) -> Result<bool, String> {
+    if args.len() != 2 {
+        return Err("compare-hashes needs two hash logs".to_string());
+    }
+
+    let first = HashLog::load(&args[0])?;
+    let second = HashLog::load(&args[1])?;
+
+    if first.rom_crc32 != second.rom_crc32 {
+        eprintln!("Warning: the runs used different ROMs (CRC32 {:08x} and {:08x})", first.rom_crc32, second.rom_crc32);
+    }
+
+    match first.compare(&second) {
+        Comparison::Match { compared } => {
+            println!("The runs match over {} hashes", compared);
+            Ok(true)
+        },
+        Comparison::Diverged { frame, first, second } => {
+            println!("The runs diverge at frame {}: {:016x} vs {:016x}", frame, first, second);
+            Ok(false)
+        },
+        Comparison::Disjoint => {
+            println!("The logs have no frames in common, so nothing was compared");
+            Ok(false)
+        },
+    }
+}
+
+fn open_audio_output(options: &Options) -> Option<AudioOutput> {
+    let sink: Box<dyn AudioSink> = if let Some(ref path) = options.wav_path {
+        match WavWriter::create(path, options.sample_rate) {
+            Ok(writer) => Box::new(writer),
+            Err(err) => fail(format!("Could not create {}: {}", path, err)),
+        }
+    } else if let Some(ref path) = options.pcm_path {
+        match PcmWriter::create(path) {
+            Ok(writer) => Box::new(writer),
+            Err(err) => fail(format!("Could not create {}: {}", path, err)),
+        }
+    } else {
+        return None;
+    };
+
+    Some(AudioOutput::new(sink, options.sample_rate))
+}
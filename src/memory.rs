@@ -1,21 +1,59 @@
 use std::fmt;
 
+use alu::ALU;
+use bus::Bus;
+use controller::{Controller, Joypad};
+use crc32;
+use error::Error;
+use snes::Region;
+use state::{SectionReader, SectionWriter};
+
 const LOROM_ROM_NAME_START: usize = 0x7FC0;
 const LOROM_ROM_MAKEUP_BYTE: usize = 0x7FD5;
+const LOROM_VAL: u8 = 0x20;
 const LOROM_FASTROM_VAL: u8 = 0x30;
 const EXLOROM_VAL: u8 = 0x32;
 
 const HIROM_ROM_NAME_START: usize = 0xFFC0;
 const HIROM_ROM_MAKEUP_BYTE: usize = 0xFFD5;
+const HIROM_VAL: u8 = 0x21;
 const HIROM_FASTROM_VAL: u8 = 0x31;
 const EXHIROM_VAL: u8 = 0x35;
+const EXHIROM_ROM_NAME_START: usize = 0x40FFC0;
+const HEADER_AREA_SIZE: usize = 0x40; // the header proper and the interrupt vectors after it
+const MIN_HEADER_SCORE: u32 = 2; // as much as a title alone, which is all some homebrew has
+
+// Offsets into the cartridge header from the start of the title
+const HEADER_ROM_SIZE: usize = 0x17;
+const HEADER_SRAM_SIZE: usize = 0x18;
+const HEADER_COUNTRY: usize = 0x19;
+const HEADER_VERSION: usize = 0x1B;
+const HEADER_COMPLEMENT: usize = 0x1C;
+const HEADER_CHECKSUM: usize = 0x1E;
+const HEADER_MAKEUP: usize = 0x15;
+const HEADER_RESET_VECTOR: usize = 0x3C; // the emulation mode one the console starts from
 
-const HEADERED_OFFSET: usize = 512;
+/// Copiers put a header of their own in front of the ROM
+pub const COPIER_HEADER_SIZE: usize = 512;
+const INTERLEAVE_BLOCK_SIZE: usize = 0x8000;
 
 const RAM_SIZE: usize = 128 * 1024;
-const VRAM_SIZE: usize = (64 * 1024) + 512 + 32 + (256 * 15); // main VRAM + sprite RAM + palette RAM
-const ARAM_SIZE: usize =  64 * 1024;
 const SRAM_SIZE: usize = 512 * 1024;
+const CGRAM_SIZE: usize = 512; // 256 colors of two bytes
+
+const PAGE_SHIFT: usize = 12; // 4 KiB pages
+const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+const PAGE_MASK: usize = PAGE_SIZE - 1;
+const PAGE_COUNT: usize = 0x1000000 >> PAGE_SHIFT;
+const ADDRESS_MASK: usize = 0xFFFFFF; // addresses past bank $FF wrap around to bank $00
+
+const NMITIMEN_AUTO_JOYPAD: u8 = 0x01;
+const AUTO_JOYPAD_CYCLES: u32 = 4224; // the auto-joypad read keeps $4212 busy for about three scanlines
+const WRIO_PORT1_IOBIT: u8 = 0x40;
+const WRIO_PORT2_IOBIT: u8 = 0x80; // also enables latching the H/V counters from port 2
+const STAT78_COUNTERS_LATCHED: u8 = 0x40;
+const STAT78_PAL: u8 = 0x10;
+const PPU2_VERSION: u8 = 0x03;
 
 pub const LOROM_NATIVE_MODE_VECTORS: [usize; 6] = [
     0x7FE4, // COP, not used for SNES
@@ -62,591 +100,701 @@ pub enum Vectors {
     IRQ = 5,
 }
 
+/// Something other than ROM, RAM or the system registers answering on the bus, such as a
+/// coprocessor or a cartridge mapper's registers
+pub trait BusDevice {
+    fn read(&mut self, addr: usize) -> u8;
+    fn write(&mut self, addr: usize, data: u8);
+}
+
+// What answers for each page of the 24-bit address space
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Page {
+    Unmapped,
+    Rom(usize), // offset of the page's first byte in the ROM image
+    Wram(usize),
+    Sram(usize),
+    Io,
+    Device(usize), // index into Memory::devices
+}
+
+/// What happens when the CPU touches an address nothing answers on, or writes to ROM.
+/// The hardware ignores these, so `Ignore` is what games expect.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BusErrorPolicy {
+    Ignore,
+    Log,
+    Break,
+}
+
+impl BusErrorPolicy {
+    pub fn from_name(name: &str) -> Option<BusErrorPolicy> {
+        match name {
+            "ignore" => Some(BusErrorPolicy::Ignore),
+            "log" => Some(BusErrorPolicy::Log),
+            "break" => Some(BusErrorPolicy::Break),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BusAccess {
+    Read,
+    Write(u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BusError {
+    pub addr: usize,
+    pub access: BusAccess,
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.access {
+            BusAccess::Read => write!(f, "read from unmapped address {:06x}", self.addr),
+            BusAccess::Write(data) => write!(f, "write of {:02x} to unmapped or read only address {:06x}", data, self.addr),
+        }
+    }
+}
+
+/// Which accesses to a range of addresses a debugger wants to stop on
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+/// A debugger watchpoint over the 24-bit addresses `start` to `end` inclusive. Addresses are
+/// matched as the CPU puts them on the bus, so each mirror of a location needs its own.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: usize, access: BusAccess) -> bool {
+        let kind_matches = matches!((self.kind, access),
+            (WatchKind::Access, _) | (WatchKind::Read, BusAccess::Read) | (WatchKind::Write, BusAccess::Write(_)));
+
+        kind_matches && addr >= self.start && addr <= self.end
+    }
+}
+
+/// The access that tripped a watchpoint
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WatchHit {
+    pub addr: usize,
+    pub access: BusAccess,
+}
+
 pub struct Memory {
 	ram: Box<[u8]>,
     pub rom: Rom,
-    sram: [u8; SRAM_SIZE],
+    pages: Box<[Page]>,
+    devices: Vec<Box<dyn BusDevice>>,
+    sram: Box<[u8]>, // on the heap, since 512 KiB inline overflows the 2 MiB stack of a test thread
+    cgram: [u8; CGRAM_SIZE],
+    cgram_addr: usize, // in bytes, so the low bit says which half of a color is next
+    cgram_latch: u8, // a color's low byte, held until its high byte is written
     bregs: [u8; 68], // address bus B registers
     wramregs: [u8; 4], // wram registers
     jpregs: [u8; 2], // old style joypad registers
     cpuregs: [u8; 32], // internal CPU registers; cannot write to 0x420E or 0x420F
     dmaregs: [u8; 88], // DMA registers
+    alu: ALU,
+    ports: [Box<dyn Controller>; 2],
+    joypad_busy_cycles: u32,
+    vblank: bool,
+    hblank: bool,
+    beam_h: u16,
+    beam_v: u16,
+    ophct: u16, // latched H/V counters and their read flip-flops
+    opvct: u16,
+    ophct_high: bool,
+    opvct_high: bool,
+    counters_latched: bool,
+    open_bus: u8, // the last value driven onto the data bus, read back from unmapped addresses
+    region: Region,
+    bus_error_policy: BusErrorPolicy,
+    bus_error: Option<BusError>,
+    watchpoints: Vec<Watchpoint>,
+    watch_hit: Option<WatchHit>,
 }
 
 impl Memory {
-    pub fn new(rom: Vec<u8>) -> Memory {
-        Memory {
+    pub fn new(rom: Vec<u8>) -> Result<Memory, Error> {
+        Memory::with_rom_type(rom, None)
+    }
+
+    /// Loads a cartridge, overriding the mapping its header asks for when `rom_type` is given
+    pub fn with_rom_type(rom: Vec<u8>, rom_type: Option<RomType>) -> Result<Memory, Error> {
+        let rom = Rom::new(rom, rom_type)?;
+        let pages = build_memory_map(&rom);
+        let region = rom.region();
+
+        Ok(Memory {
             ram: vec![0; RAM_SIZE].into_boxed_slice(),
-            rom: Rom::new(rom),
-            sram: [0; SRAM_SIZE],
+            rom: rom,
+            pages: pages,
+            devices: Vec::new(),
+            sram: vec![0; SRAM_SIZE].into_boxed_slice(),
+            cgram: [0; CGRAM_SIZE],
+            cgram_addr: 0,
+            cgram_latch: 0,
             bregs: [0; 68],
             wramregs: [0; 4],
             jpregs: [0; 2],
             cpuregs: [0; 32],
             dmaregs: [0; 88],
+            alu: ALU::new(),
+            ports: [Box::new(Joypad::new()), Box::new(Joypad::new())],
+            joypad_busy_cycles: 0,
+            vblank: false,
+            hblank: false,
+            beam_h: 0,
+            beam_v: 0,
+            ophct: 0,
+            opvct: 0,
+            ophct_high: false,
+            opvct_high: false,
+            counters_latched: false,
+            open_bus: 0,
+            region: region,
+            bus_error_policy: BusErrorPolicy::Ignore,
+            bus_error: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+        })
+    }
+
+    /// Returns the I/O registers to their power-on state, as the reset line does; RAM is untouched
+    pub fn reset(&mut self) {
+        self.bregs = [0; 68];
+        self.wramregs = [0; 4];
+        self.jpregs = [0; 2];
+        self.cpuregs = [0; 32];
+        self.dmaregs = [0; 88];
+        self.alu = ALU::new();
+        self.joypad_busy_cycles = 0;
+        self.vblank = false;
+        self.hblank = false;
+        self.counters_latched = false;
+        self.ophct_high = false;
+        self.opvct_high = false;
+    }
+
+    /// Clears WRAM and CGRAM along with the registers. SRAM is battery backed and survives.
+    pub fn power_cycle(&mut self) {
+        self.reset();
+
+        for byte in self.ram.iter_mut() {
+            *byte = 0;
         }
+
+        self.cgram = [0; CGRAM_SIZE];
+        self.cgram_addr = 0;
     }
 
-    pub fn get_byte(&self, addr: usize) -> u8 {
-        use self::RomType::*;
+    pub fn sram(&self) -> &[u8] {
+        &self.sram
+    }
 
-        let header_offset = if self.rom.headered { HEADERED_OFFSET } else { 0 };
-        let addr_offset = addr + header_offset;
-        let bank = (addr & 0xFF0000) >> 16;
-        let bank_header = (addr_offset & 0xFF0000) >> 16;
-        let offset = addr & 0xFFFF;
-        let offset_header = addr_offset & 0xFFFF;
+    /// Loads a battery save; shorter saves leave the rest of SRAM cleared
+    pub fn load_sram(&mut self, data: &[u8]) {
+        let length = data.len().min(SRAM_SIZE);
 
-        /*
-        println!("get addr {:x} h_offset: {:x} addr_offset: {:x} bank {:x} \
-                  bank_header {:x} offset {:x} offset_header {:x}",
-                 addr, header_offset, addr_offset, bank, bank_header, offset, offset_header);
-                 */
+        self.sram.fill(0);
+        self.sram[..length].copy_from_slice(&data[..length]);
+    }
 
-        match self.rom.rom_type {
-            LoROM | FastLoROM => {
-                match bank {
-                    0x00 ... 0x3F | 0x80 ... 0xBF => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                self.ram[offset]
-                            },
-                            0x2000 ... 0x20FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x2100 ... 0x21FF => {
-                                panic!("Unimplemented: PPU1, APU, hardware registers {:x}", addr)
-                            },
-                            0x2200 ... 0x2FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x3000 ... 0x3FFF => {
-                                panic!("Unimplemented: DSP, SuperFX, hardware registers {:x}", addr)
-                            },
-                            0x4000 ... 0x40FF => {
-                                panic!("Unimplemented: Old style joypad registers {:x}", addr)
-                            },
-                            0x4100 ... 0x41FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x4200 ... 0x420D | 0x4210 ... 0x421F => {
-                                let adjusted_offset = offset - 0x4200;
-
-                                self.cpuregs[adjusted_offset]
-                            },
-                            0x4300 ... 0x430A |
-                            0x4310 ... 0x431A |
-                            0x4320 ... 0x432A |
-                            0x4330 ... 0x433A |
-                            0x4340 ... 0x434A |
-                            0x4350 ... 0x435A |
-                            0x4360 ... 0x436A |
-                            0x4370 ... 0x437A => {
-                                let adjusted_offset = offset - 0x4300;
-
-                                self.dmaregs[adjusted_offset]
-                            },
-                            0x4500 ... 0x5FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x6000 ... 0x7FFF => {
-                                panic!("Reserved memory {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                let tmp_bank = if bank_header >= 0x80 {
-                                    bank_header - 0x80
-                                } else {
-                                    bank_header
-                                };
-
-                                self.rom.data[(tmp_bank << 16) | offset_header - 0x8000]
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr_offset)
-                        }
-                    },
-                    0x40 ... 0x6F | 0xC0 ... 0xEF => {
-                        match offset {
-                            0x0000 ... 0x7FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                let tmp_bank = if bank_header >= 0xC0 {
-                                    bank_header - 0x80
-                                } else {
-                                    bank_header
-                                };
-
-                                self.rom.data[(tmp_bank * 0x8000) + (offset_header - 0x8000)]
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x70 ... 0x7D | 0xF0 ... 0xFD => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("SRAM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7E => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                panic!("Unimplemented: LowRAM (WRAM) {:x}", addr)
-                            },
-                            0x2000 ... 0x7FFF => {
-                                panic!("Unimplemented: HighRAM (WRAM) {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Unimplemented: Extended RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7F => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("Unimplemented: Extended RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0xFE ... 0xFF => {
-                        match offset {
-                            0x0000 ... 0x7FFF => {
-                                panic!("SRAM {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                self.rom.data[(bank_header * 0x8000) + (offset_header - 0x8000)]
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    _ => unreachable!("Invalid address {:x}", addr)
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// The palette: 256 colors of 15-bit BGR, low byte first
+    pub fn cgram(&self) -> &[u8] {
+        &self.cgram
+    }
+
+    /// INIDISP, with force blank in bit 7 and the brightness in the low four bits
+    pub fn inidisp(&self) -> u8 {
+        self.bregs[0]
+    }
+
+    pub fn save_state(&self, out: &mut SectionWriter) {
+        out.write_bytes(&self.ram);
+        out.write_bytes(&self.sram);
+        out.write_bytes(&self.bregs);
+        out.write_bytes(&self.wramregs);
+        out.write_bytes(&self.jpregs);
+        out.write_bytes(&self.cpuregs);
+        out.write_bytes(&self.dmaregs);
+        self.alu.save_state(out);
+        out.write_u32(self.joypad_busy_cycles);
+        out.write_bool(self.vblank);
+        out.write_bool(self.hblank);
+        out.write_u16(self.beam_h);
+        out.write_u16(self.beam_v);
+        out.write_u16(self.ophct);
+        out.write_u16(self.opvct);
+        out.write_bool(self.ophct_high);
+        out.write_bool(self.opvct_high);
+        out.write_bool(self.counters_latched);
+        out.write_u8(self.open_bus);
+        out.write_bytes(&self.cgram);
+        out.write_u16(self.cgram_addr as u16);
+        out.write_u8(self.cgram_latch);
+    }
+
+    /// Everything is read before anything changes, so a bad section leaves memory as it was
+    pub fn load_state(&mut self, input: &mut SectionReader) -> Result<(), String> {
+        let mut ram = vec![0; self.ram.len()].into_boxed_slice();
+        let mut sram = vec![0; self.sram.len()].into_boxed_slice();
+        let (mut bregs, mut wramregs, mut jpregs) = (self.bregs, self.wramregs, self.jpregs);
+        let (mut cpuregs, mut dmaregs) = (self.cpuregs, self.dmaregs);
+        let mut alu = self.alu.clone();
+        let mut cgram = self.cgram;
+        let (mut cgram_addr, mut cgram_latch) = (self.cgram_addr, self.cgram_latch);
+
+        input.read_into(&mut ram)?;
+        input.read_into(&mut sram)?;
+        input.read_into(&mut bregs)?;
+        input.read_into(&mut wramregs)?;
+        input.read_into(&mut jpregs)?;
+        input.read_into(&mut cpuregs)?;
+        input.read_into(&mut dmaregs)?;
+        alu.load_state(input)?;
+        let joypad_busy_cycles = input.read_u32()?;
+        let vblank = input.read_bool()?;
+        let hblank = input.read_bool()?;
+        let beam_h = input.read_u16()?;
+        let beam_v = input.read_u16()?;
+        let ophct = input.read_u16()?;
+        let opvct = input.read_u16()?;
+        let ophct_high = input.read_bool()?;
+        let opvct_high = input.read_bool()?;
+        let counters_latched = input.read_bool()?;
+        let open_bus = input.read_u8()?;
+
+        if input.version() >= 3 {
+            input.read_into(&mut cgram)?;
+            cgram_addr = input.read_u16()? as usize % CGRAM_SIZE;
+            cgram_latch = input.read_u8()?;
+        }
+
+        self.ram = ram;
+        self.sram = sram;
+        self.bregs = bregs;
+        self.wramregs = wramregs;
+        self.jpregs = jpregs;
+        self.cpuregs = cpuregs;
+        self.dmaregs = dmaregs;
+        self.alu = alu;
+        self.joypad_busy_cycles = joypad_busy_cycles;
+        self.vblank = vblank;
+        self.hblank = hblank;
+        self.beam_h = beam_h;
+        self.beam_v = beam_v;
+        self.ophct = ophct;
+        self.opvct = opvct;
+        self.ophct_high = ophct_high;
+        self.opvct_high = opvct_high;
+        self.counters_latched = counters_latched;
+        self.open_bus = open_bus;
+        self.cgram = cgram;
+        self.cgram_addr = cgram_addr;
+        self.cgram_latch = cgram_latch;
+
+        Ok(())
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn set_bus_error_policy(&mut self, policy: BusErrorPolicy) {
+        self.bus_error_policy = policy;
+    }
+
+    /// The bus error that asked to break, if one has happened since the last call
+    pub fn take_bus_error(&mut self) -> Option<BusError> {
+        self.bus_error.take()
+    }
+
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.watchpoints = watchpoints;
+    }
+
+    /// The first access to trip a watchpoint since the last call
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn check_watchpoints(&mut self, addr: usize, access: BusAccess) {
+        if self.watch_hit.is_none() && self.watchpoints.iter().any(|watchpoint| watchpoint.matches(addr, access)) {
+            self.watch_hit = Some(WatchHit { addr: addr, access: access });
+        }
+    }
+
+    // Unmapped reads float and return whatever was last on the bus; writes go nowhere
+    fn bus_error(&mut self, addr: usize, access: BusAccess) -> u8 {
+        let error = BusError { addr: addr, access: access };
+
+        match self.bus_error_policy {
+            BusErrorPolicy::Ignore => {},
+            BusErrorPolicy::Log => eprintln!("Bus error: {}", error),
+            BusErrorPolicy::Break => {
+                if self.bus_error.is_none() {
+                    self.bus_error = Some(error);
                 }
             },
-            HiROM | FastHiROM => {
-                match bank {
-                    0x00 ... 0x1F | 0x80 ... 0x9F => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                self.ram[offset]
-                            },
-                            0x2000 ... 0x20FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x2100 ... 0x21FF => {
-                                let adjusted_offset = offset - 0x2100;
-
-                                self.bregs[adjusted_offset]
-                            },
-                            0x2200 ... 0x2FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x3000 ... 0x3FFF => {
-                                panic!("Unimplemented: DSP, SuperFX, hardware registers {:x}", addr)
-                            },
-                            0x4000 ... 0x40FF => {
-                                panic!("Unimplemented: Old style joypad registers {:x}", addr)
-                            },
-                            0x4100 ... 0x41FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x4200 ... 0x44FF => {
-                                panic!("Unimplemented: DMA, PPU2, hardware registers {:x}", addr)
-                            },
-                            0x4500 ... 0x5FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x6000 ... 0x7FFF => {
-                                panic!("Reserved {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                let tmp_bank = if bank_header >= 0x80 {
-                                    bank_header - 0x80
-                                } else {
-                                    bank_header
-                                };
-
-                                self.rom.data[(tmp_bank << 16) + offset_header]
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x20 ... 0x3F | 0xA0 ... 0xBF => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                panic!("Unimplemented: LowRAM, shadowed from bank 0x7E {:x}", addr)
-                            },
-                            0x2000 ... 0x20FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x2100 ... 0x21FF => {
-                                panic!("Unimplemented: PPU1, APU, hardware registers {:x}", addr)
-                            },
-                            0x2200 ... 0x2FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x3000 ... 0x3FFF => {
-                                panic!("Unimplemented: DSP, SuperFX, hardware registers {:x}", addr)
-                            },
-                            0x4000 ... 0x40FF => {
-                                panic!("Unimplemented: Old style joypad registers {:x}", addr)
-                            },
-                            0x4100 ... 0x41FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x4200 ... 0x44FF => {
-                                panic!("Unimplemented: DMA, PPU2, hardware registers {:x}", addr)
-                            },
-                            0x4500 ... 0x5FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x6000 ... 0x7FFF => {
-                                panic!("SRAM {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                let tmp_bank = if bank_header >= 0xA0 {
-                                    bank_header - 0x80
-                                } else {
-                                    bank_header
-                                };
-
-                                self.rom.data[(tmp_bank << 16) + offset_header]
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x40 ... 0x7D | 0xC0 ... 0xFD => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                let tmp_bank = if bank_header >= 0xC0 {
-                                    bank_header - 0xC0
-                                } else {
-                                    bank_header - 0x40
-                                };
-
-                                self.rom.data[(tmp_bank << 16) + offset_header]
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7E => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                panic!("Unimplemented: LowRAM (WRAM) {:x}", addr)
-                            },
-                            0x2000 ... 0x7FFF => {
-                                panic!("Unimplemented: HighRAM (WRAM) {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Unimplemented: Expanded RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7F => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("Unimplemented: Expanded RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0xFE ... 0xFF => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                let tmp_bank = bank_header - 0xC0;
-                                self.rom.data[(tmp_bank << 16) + offset_header]
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    _ => unreachable!("Invalid address {:x}", addr)
-                }
+        }
+
+        self.open_bus
+    }
+
+    /// Plugs a device into controller port 0 or 1, replacing whatever was there
+    pub fn plug_controller(&mut self, port: usize, controller: Box<dyn Controller>) {
+        self.ports[port] = controller;
+    }
+
+    pub fn controller_mut(&mut self, port: usize) -> &mut dyn Controller {
+        &mut *self.ports[port]
+    }
+
+    pub fn set_beam_position(&mut self, h: u16, v: u16) {
+        self.beam_h = h;
+        self.beam_v = v;
+    }
+
+    /// Where a light gun in either port is aimed, if anywhere on screen
+    pub fn light_gun_target(&self) -> Option<(u16, u16)> {
+        self.ports[1].light_position().or_else(|| self.ports[0].light_position())
+    }
+
+    /// Latches the H/V counters for $213C/$213D, as a light gun pulling port 2's I/O line low does
+    pub fn latch_counters(&mut self, h: u16, v: u16) {
+        if self.cpuregs[1] & WRIO_PORT2_IOBIT == 0 {
+            return;
+        }
+
+        self.ophct = h;
+        self.opvct = v;
+        self.counters_latched = true;
+    }
+
+    pub fn set_hblank(&mut self, hblank: bool) {
+        self.hblank = hblank;
+    }
+
+    pub fn start_vblank(&mut self) {
+        self.vblank = true;
+
+        if self.cpuregs[0] & NMITIMEN_AUTO_JOYPAD != 0 {
+            self.auto_joypad_read();
+        }
+    }
+
+    pub fn end_vblank(&mut self) {
+        self.vblank = false;
+    }
+
+    /// Steps the multiplier and divider through one instruction's worth of CPU cycles
+    pub fn run_alu(&mut self, cpu_cycles: u32) {
+        self.alu.run(cpu_cycles);
+    }
+
+    pub fn advance_joypad(&mut self, master_cycles: u32) {
+        self.joypad_busy_cycles = self.joypad_busy_cycles.saturating_sub(master_cycles);
+    }
+
+    // Clocks 16 bits out of both ports into JOY1-JOY4; D0 feeds JOY1/JOY2 and D1 feeds JOY3/JOY4
+    fn auto_joypad_read(&mut self) {
+        self.joypad_busy_cycles = AUTO_JOYPAD_CYCLES;
+
+        for port in 0..2 {
+            let controller = &mut self.ports[port];
+            let mut data1: u16 = 0;
+            let mut data2: u16 = 0;
+
+            controller.set_latch(true);
+            controller.set_latch(false);
+
+            for _ in 0..16 {
+                let bits = controller.clock();
+
+                data1 = (data1 << 1) | (bits & 1) as u16;
+                data2 = (data2 << 1) | ((bits >> 1) & 1) as u16;
+            }
+
+            self.cpuregs[0x18 + port * 2] = data1 as u8;
+            self.cpuregs[0x19 + port * 2] = (data1 >> 8) as u8;
+            self.cpuregs[0x1C + port * 2] = data2 as u8;
+            self.cpuregs[0x1D + port * 2] = (data2 >> 8) as u8;
+        }
+    }
+
+    // $4016 reads port 0 and $4017 port 1; bits 2-4 of $4017 are tied high and the
+    // rest of both registers is open bus
+    fn read_joypad(&mut self, port: usize) -> u8 {
+        let data = self.ports[port].clock() & 0x03;
+
+        if port == 1 {
+            (self.open_bus & 0xE0) | 0x1C | data
+        } else {
+            (self.open_bus & 0xFC) | data
+        }
+    }
+
+    fn write_joypad_latch(&mut self, data: u8) {
+        self.jpregs[0] = data;
+
+        for controller in self.ports.iter_mut() {
+            controller.set_latch(data & 1 != 0);
+        }
+    }
+
+    fn read_counter_register(&mut self, offset: usize) -> u8 {
+        match offset {
+            0x2137 => {
+                let (h, v) = (self.beam_h, self.beam_v);
+                self.latch_counters(h, v);
+
+                // Nothing drives the bus for this read, so it's whatever the CPU last saw
+                self.open_bus
             },
-            ExLoROM => {
-                unimplemented!()
+            0x213C => {
+                self.ophct_high = !self.ophct_high;
+
+                if self.ophct_high { self.ophct as u8 } else { (self.ophct >> 8) as u8 & 0x01 }
             },
-            ExHiROM => {
-                unimplemented!()
+            0x213D => {
+                self.opvct_high = !self.opvct_high;
+
+                if self.opvct_high { self.opvct as u8 } else { (self.opvct >> 8) as u8 & 0x01 }
+            },
+            0x213F => {
+                let latched = if self.counters_latched { STAT78_COUNTERS_LATCHED } else { 0 };
+
+                self.ophct_high = false;
+                self.opvct_high = false;
+                self.counters_latched = false;
+
+                let pal = if self.region == Region::PAL { STAT78_PAL } else { 0 };
+
+                latched | pal | PPU2_VERSION
+            },
+            _ => unreachable!("Not a counter register {:x}", offset)
+        }
+    }
+
+    fn write_wrio(&mut self, data: u8) {
+        let previous = self.cpuregs[1];
+        self.cpuregs[1] = data;
+
+        self.ports[0].set_iobit(data & WRIO_PORT1_IOBIT != 0);
+        self.ports[1].set_iobit(data & WRIO_PORT2_IOBIT != 0);
+
+        // Dropping the port 2 I/O line latches the counters just like a light gun does
+        if previous & WRIO_PORT2_IOBIT != 0 && data & WRIO_PORT2_IOBIT == 0 {
+            self.ophct = self.beam_h;
+            self.opvct = self.beam_v;
+            self.counters_latched = true;
+        }
+    }
+
+    fn read_cpu_register(&mut self, offset: usize) -> u8 {
+        match offset {
+            0x4212 => {
+                let vblank = if self.vblank { 0x80 } else { 0 };
+                let hblank = if self.hblank { 0x40 } else { 0 };
+                let busy = if self.joypad_busy_cycles > 0 { 0x01 } else { 0 };
+
+                vblank | hblank | busy
             },
+            0x4213 => self.cpuregs[1], // RDIO reads back the I/O lines WRIO drives
+            0x4214 ... 0x4217 => self.alu.read(offset),
+            _ => self.cpuregs[offset - 0x4200],
         }
     }
 
+    fn write_cpu_register(&mut self, offset: usize, data: u8) {
+        match offset {
+            0x4201 => self.write_wrio(data),
+            0x4202 ... 0x4206 => self.alu.write(offset, data),
+            0x4210 ... 0x421F => {}, // read only
+            _ => self.cpuregs[offset - 0x4200] = data,
+        }
+    }
+
+    pub fn get_byte(&mut self, addr: usize) -> u8 {
+        let addr = addr & ADDRESS_MASK;
+        let data = self.read_mapped(addr);
+        self.open_bus = data;
+
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, BusAccess::Read);
+        }
+
+        data
+    }
+
     pub fn set_byte(&mut self, addr: usize, data: u8) {
-        use self::RomType::*;
+        let addr = addr & ADDRESS_MASK;
+        self.open_bus = data;
+        self.write_mapped(addr, data);
+
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, BusAccess::Write(data));
+        }
+    }
+
+    /// Reads memory without side effects, for debuggers and the disassembler. I/O registers
+    /// and devices aren't touched; they read back as open bus.
+    pub fn peek_byte(&self, addr: usize) -> u8 {
+        let addr = addr & ADDRESS_MASK;
+        let offset = addr & PAGE_MASK;
+
+        match self.pages[addr >> PAGE_SHIFT] {
+            Page::Rom(base) => self.rom.data.get(base + offset).cloned().unwrap_or(self.open_bus),
+            Page::Wram(base) => self.ram[base + offset],
+            Page::Sram(base) => self.sram[base + offset],
+            Page::Io | Page::Device(_) | Page::Unmapped => self.open_bus,
+        }
+    }
+
+    /// Writes RAM or SRAM without side effects, for debuggers. Returns false for anything else.
+    pub fn poke_byte(&mut self, addr: usize, data: u8) -> bool {
+        let addr = addr & ADDRESS_MASK;
+        let offset = addr & PAGE_MASK;
+
+        match self.pages[addr >> PAGE_SHIFT] {
+            Page::Wram(base) => self.ram[base + offset] = data,
+            Page::Sram(base) => self.sram[base + offset] = data,
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn read_mapped(&mut self, addr: usize) -> u8 {
+        let offset = addr & PAGE_MASK;
+
+        match self.pages[addr >> PAGE_SHIFT] {
+            Page::Rom(base) => self.rom.data.get(base + offset).cloned().unwrap_or(self.open_bus),
+            Page::Wram(base) => self.ram[base + offset],
+            Page::Sram(base) => self.sram[base + offset],
+            Page::Io => self.read_io(addr),
+            Page::Device(index) => self.devices[index].read(addr),
+            Page::Unmapped => self.bus_error(addr, BusAccess::Read),
+        }
+    }
+
+    fn write_mapped(&mut self, addr: usize, data: u8) {
+        let offset = addr & PAGE_MASK;
 
-        let header_offset = if self.rom.headered { HEADERED_OFFSET } else { 0 };
-        let addr_offset = addr + header_offset;
-        let bank = (addr & 0xFF0000) >> 16;
-        let bank_header = (addr_offset & 0xFF0000) >> 16;
+        match self.pages[addr >> PAGE_SHIFT] {
+            Page::Wram(base) => self.ram[base + offset] = data,
+            Page::Sram(base) => self.sram[base + offset] = data,
+            Page::Io => self.write_io(addr, data),
+            Page::Device(index) => self.devices[index].write(addr, data),
+            Page::Rom(_) | Page::Unmapped => {
+                self.bus_error(addr, BusAccess::Write(data));
+            },
+        }
+    }
+
+    // The system registers at $2000-$5FFF of banks $00-$3F and $80-$BF
+    fn read_io(&mut self, addr: usize) -> u8 {
         let offset = addr & 0xFFFF;
-        let offset_header = addr_offset & 0xFFFF;
 
-        /*
-        println!("set addr {:x} h_offset: {:x} addr_offset: {:x} bank {:x} \
-                  bank_header {:x} offset {:x} offset_header {:x} data {:x}",
-                 addr, header_offset, addr_offset, bank, bank_header, offset, offset_header, data);
-                 */
+        match offset {
+            0x2137 | 0x213C | 0x213D | 0x213F => {
+                self.read_counter_register(offset)
+            },
+            0x2100 ... 0x2143 => {
+                self.bregs[offset - 0x2100]
+            },
+            0x2180 ... 0x2183 => {
+                self.wramregs[offset - 0x2180]
+            },
+            0x4016 ... 0x4017 => {
+                self.read_joypad(offset - 0x4016)
+            },
+            0x4200 ... 0x420D | 0x4210 ... 0x421F => {
+                self.read_cpu_register(offset)
+            },
+            0x4300 ... 0x437F if offset & 0x0F <= 0x0A => {
+                self.dmaregs[dma_register_index(offset)]
+            },
+            _ => self.bus_error(addr, BusAccess::Read),
+        }
+    }
 
-        match self.rom.rom_type {
-            LoROM | FastLoROM => {
-                match bank {
-                    0x00 ... 0x3F | 0x80 ... 0xBF => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                self.ram[offset] = data;
-                            },
-                            0x2000 ... 0x20FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x2100 ... 0x21FF => {
-                                panic!("Unimplemented: PPU1, APU, hardware registers {:x}", addr)
-                            },
-                            0x2200 ... 0x2FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x3000 ... 0x3FFF => {
-                                panic!("Unimplemented: DSP, SuperFX, hardware registers {:x}", addr)
-                            },
-                            0x4000 ... 0x40FF => {
-                                panic!("Unimplemented: Old style joypad registers {:x}", addr)
-                            },
-                            0x4100 ... 0x41FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x4200 ... 0x420D | 0x4210 ... 0x421F => {
-                                let adjusted_offset = offset - 0x4200;
-
-                                self.cpuregs[adjusted_offset] = data;
-                            },
-                            0x4300 ... 0x430A |
-                            0x4310 ... 0x431A |
-                            0x4320 ... 0x432A |
-                            0x4330 ... 0x433A |
-                            0x4340 ... 0x434A |
-                            0x4350 ... 0x435A |
-                            0x4360 ... 0x436A |
-                            0x4370 ... 0x437A => {
-                                let adjusted_offset = offset - 0x4300;
-
-                                self.dmaregs[adjusted_offset] = data;
-                            },
-                            0x4500 ... 0x5FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x6000 ... 0x7FFF => {
-                                panic!("Reserved memory {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Cannot write to ROM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x40 ... 0x6F | 0xC0 ... 0xEF => {
-                        match offset {
-                            0x0000 ... 0x7FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Cannot write to ROM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x70 ... 0x7D | 0xF0 ... 0xFD => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("SRAM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7E => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                panic!("Unimplemented: LowRAM (WRAM) {:x}", addr)
-                            },
-                            0x2000 ... 0x7FFF => {
-                                panic!("Unimplemented: HighRAM (WRAM) {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Unimplemented: Extended RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7F => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("Unimplemented: Extended RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0xFE ... 0xFF => {
-                        match offset {
-                            0x0000 ... 0x7FFF => {
-                                panic!("SRAM {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Cannot write to ROM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    _ => unreachable!("Invalid address {:x}", addr)
-                }
+    fn write_io(&mut self, addr: usize, data: u8) {
+        let offset = addr & 0xFFFF;
+
+        match offset {
+            0x2121 => {
+                self.bregs[offset - 0x2100] = data;
+                self.cgram_addr = data as usize * 2;
             },
-            HiROM | FastHiROM => {
-                match bank {
-                    0x00 ... 0x1F | 0x80 ... 0x9F => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                self.ram[offset] = data;
-                            },
-                            0x2000 ... 0x20FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x2100 ... 0x2143 => {
-                                let adjusted_offset = offset - 0x2100;
-
-                                self.bregs[adjusted_offset] = data;
-                            },
-                            0x2180 ... 0x2183 => {
-                                let adjusted_offset = offset - 0x2180;
-
-                                self.wramregs[adjusted_offset] = data;
-                            },
-                            0x2200 ... 0x2FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x3000 ... 0x3FFF => {
-                                panic!("Unimplemented: DSP, SuperFX, hardware registers {:x}", addr)
-                            },
-                            0x4000 ... 0x40FF => {
-                                panic!("Unimplemented: Old style joypad registers {:x}", addr)
-                            },
-                            0x4100 ... 0x41FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x4200 ... 0x420D | 0x4210 ... 0x421F => {
-                                let adjusted_offset = offset - 0x4200;
-
-                                self.cpuregs[adjusted_offset] = data;
-                            },
-                            0x4300 ... 0x430A |
-                            0x4310 ... 0x431A |
-                            0x4320 ... 0x432A |
-                            0x4330 ... 0x433A |
-                            0x4340 ... 0x434A |
-                            0x4350 ... 0x435A |
-                            0x4360 ... 0x436A |
-                            0x4370 ... 0x437A => {
-                                let adjusted_offset = offset - 0x4300;
-
-                                self.dmaregs[adjusted_offset] = data;
-                            },
-                            0x4500 ... 0x5FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x6000 ... 0x7FFF => {
-                                panic!("Reserved {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Cannot write to ROM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x20 ... 0x3F | 0xA0 ... 0xBF => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                panic!("Unimplemented: LowRAM, shadowed from bank 0x7E {:x}", addr)
-                            },
-                            0x2000 ... 0x20FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x2100 ... 0x21FF => {
-                                panic!("Unimplemented: PPU1, APU, hardware registers {:x}", addr)
-                            },
-                            0x2200 ... 0x2FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x3000 ... 0x3FFF => {
-                                panic!("Unimplemented: DSP, SuperFX, hardware registers {:x}", addr)
-                            },
-                            0x4000 ... 0x40FF => {
-                                panic!("Unimplemented: Old style joypad registers {:x}", addr)
-                            },
-                            0x4100 ... 0x41FF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x4200 ... 0x44FF => {
-                                panic!("Unimplemented: DMA, PPU2, hardware registers {:x}", addr)
-                            },
-                            0x4500 ... 0x5FFF => {
-                                unreachable!("Invalid address {:x}", addr)
-                            },
-                            0x6000 ... 0x7FFF => {
-                                panic!("SRAM {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Cannot write to ROM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x40 ... 0x7D | 0xC0 ... 0xFD => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("Cannot write to ROM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7E => {
-                        match offset {
-                            0x0000 ... 0x1FFF => {
-                                panic!("Unimplemented: LowRAM (WRAM) {:x}", addr)
-                            },
-                            0x2000 ... 0x7FFF => {
-                                panic!("Unimplemented: HighRAM (WRAM) {:x}", addr)
-                            },
-                            0x8000 ... 0xFFFF => {
-                                panic!("Unimplemented: Expanded RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0x7F => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("Unimplemented: Expanded RAM (WRAM) {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    0xFE ... 0xFF => {
-                        match offset {
-                            0x0000 ... 0xFFFF => {
-                                panic!("Cannot write to ROM {:x}", addr)
-                            },
-                            _ => unreachable!("Invalid address {:x}", addr)
-                        }
-                    },
-                    _ => unreachable!("Invalid address {:x}", addr)
-                }
+            0x2122 => {
+                self.bregs[offset - 0x2100] = data;
+                self.write_cgram(data);
+            },
+            0x2100 ... 0x2143 => {
+                self.bregs[offset - 0x2100] = data;
             },
-            ExLoROM => {
-                unimplemented!()
+            0x2180 ... 0x2183 => {
+                self.wramregs[offset - 0x2180] = data;
             },
-            ExHiROM => {
-                unimplemented!()
+            0x4016 => {
+                self.write_joypad_latch(data);
             },
+            0x4200 ... 0x420D | 0x4210 ... 0x421F => {
+                self.write_cpu_register(offset, data);
+            },
+            0x4300 ... 0x437F if offset & 0x0F <= 0x0A => {
+                self.dmaregs[dma_register_index(offset)] = data;
+            },
+            _ => {
+                self.bus_error(addr, BusAccess::Write(data));
+            },
+        }
+    }
+
+    // Colors go in low byte first, and only land in CGRAM once the high byte follows
+    fn write_cgram(&mut self, data: u8) {
+        if self.cgram_addr & 1 == 0 {
+            self.cgram_latch = data;
+        } else {
+            self.cgram[self.cgram_addr - 1] = self.cgram_latch;
+            self.cgram[self.cgram_addr] = data & 0x7F;
         }
+
+        self.cgram_addr = (self.cgram_addr + 1) % CGRAM_SIZE;
+    }
+
+    /// Hands the pages covering `banks` x `addresses` (both inclusive, page aligned) to a device,
+    /// such as a coprocessor or a cartridge mapper, replacing whatever was mapped there
+    pub fn map_device(&mut self, banks: (usize, usize), addresses: (usize, usize), device: Box<dyn BusDevice>) {
+        assert!(addresses.0 & PAGE_MASK == 0 && addresses.1 & PAGE_MASK == PAGE_MASK,
+                "Device regions must cover whole pages");
+
+        let index = self.devices.len();
+        self.devices.push(device);
+
+        map_region(&mut self.pages, banks, addresses, |_, _| Page::Device(index));
     }
 
     pub fn get_interrupt_vector(&self, emu: bool) -> usize {
@@ -667,26 +815,148 @@ impl Memory {
                     HIROM_NATIVE_MODE_VECTORS[Vectors::IRQ as usize]
                 }
             },
-            _ => panic!("ExLo/HiROM formats not supported")
+            ExLoROM | ExHiROM => unreachable!("Ex mappers are rejected at load"),
+        }
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, addr: usize) -> u8 {
+        self.get_byte(addr)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) {
+        self.set_byte(addr, data)
+    }
+
+    // Starts in the FastROM mirror of the cartridge rather than bank 0
+    fn reset_vector(&mut self) -> (usize, usize) {
+        use self::RomType::*;
+
+        let bank = match self.rom.rom_type {
+            LoROM | FastLoROM => 0x80,
+            HiROM | FastHiROM => 0xC0,
+            ExLoROM | ExHiROM => unreachable!("Ex mappers are rejected at load"),
+        };
+
+        (bank, self.rom.reset_vector)
+    }
+}
+
+fn map_region<F>(pages: &mut [Page], banks: (usize, usize), addresses: (usize, usize), page: F)
+    where F: Fn(usize, usize) -> Page
+{
+    for bank in banks.0..banks.1 + 1 {
+        for addr in (addresses.0..addresses.1 + 1).step_by(PAGE_SIZE) {
+            pages[(bank << 16 | addr) >> PAGE_SHIFT] = page(bank, addr);
         }
     }
 }
 
+// Lays out the address space once at load time so accesses are a single table lookup
+fn build_memory_map(rom: &Rom) -> Box<[Page]> {
+    use self::RomType::*;
+
+    let mut pages = vec![Page::Unmapped; PAGE_COUNT];
+    let rom_size = rom.data.len();
+
+    // Images smaller than the space they're mapped into repeat through it
+    let rom_page = |offset: usize| Page::Rom(offset % rom_size);
+
+    for &banks in [(0x00, 0x3F), (0x80, 0xBF)].iter() {
+        map_region(&mut pages, banks, (0x0000, 0x1FFF), |_, addr| Page::Wram(addr));
+        map_region(&mut pages, banks, (0x2000, 0x5FFF), |_, _| Page::Io);
+    }
+
+    map_region(&mut pages, (0x7E, 0x7F), (0x0000, 0xFFFF), |bank, addr| Page::Wram((bank - 0x7E) << 16 | addr));
+
+    match rom.rom_type {
+        LoROM | FastLoROM => {
+            for &banks in [(0x00, 0x7D), (0x80, 0xFF)].iter() {
+                map_region(&mut pages, banks, (0x8000, 0xFFFF), |bank, addr| {
+                    rom_page((bank & 0x7F) * 0x8000 + addr - 0x8000)
+                });
+            }
+
+            for &banks in [(0x70, 0x7D), (0xF0, 0xFF)].iter() {
+                map_region(&mut pages, banks, (0x0000, 0x7FFF), |bank, addr| Page::Sram((bank & 0x0F) * 0x8000 + addr));
+            }
+        },
+        HiROM | FastHiROM => {
+            for &banks in [(0x00, 0x3F), (0x80, 0xBF)].iter() {
+                map_region(&mut pages, banks, (0x8000, 0xFFFF), |bank, addr| rom_page((bank & 0x3F) << 16 | addr));
+            }
+
+            for &banks in [(0x20, 0x3F), (0xA0, 0xBF)].iter() {
+                map_region(&mut pages, banks, (0x6000, 0x7FFF), |bank, addr| {
+                    Page::Sram((bank & 0x1F) * 0x2000 + addr - 0x6000)
+                });
+            }
+
+            for &banks in [(0x40, 0x7D), (0xC0, 0xFF)].iter() {
+                map_region(&mut pages, banks, (0x0000, 0xFFFF), |bank, addr| rom_page((bank & 0x3F) << 16 | addr));
+            }
+        },
+        ExLoROM | ExHiROM => unreachable!("Ex mappers are rejected at load"),
+    }
+
+    pages.into_boxed_slice()
+}
+
+// DMA channels are 16 bytes apart on the bus but only use their first 11 registers
+fn dma_register_index(offset: usize) -> usize {
+    ((offset >> 4) & 0x07) * 11 + (offset & 0x0F)
+}
+
 pub struct Rom {
     data: Box<[u8]>,
     pub rom_type: RomType,
     rom_name: String,
-    pub headered: bool,
+    pub copier_header: Option<Box<[u8]>>, // the SMC/SWC/FIG header some dumps start with, kept for inspection
+    pub interleaved: bool, // whether the dump had to be de-interleaved
     pub reset_vector: usize,
 }
 
 impl Rom {
-    fn new(rom: Vec<u8>) -> Rom {
+    /// CRC-32 of the ROM image, excluding any copier header
+    pub fn checksum(&self) -> u32 {
+        crc32::crc32(&self.data)
+    }
+
+    /// Parses the cartridge, or trusts `rom_type` instead of the header's map mode if given
+    fn new(mut rom: Vec<u8>, rom_type: Option<RomType>) -> Result<Rom, Error> {
         use self::RomType::*;
 
-        let headered = rom.len() % 1024 == HEADERED_OFFSET;
-        let offset = if headered { 0x200 } else { 0 };
-        let (rom_type, rom_name) = Rom::get_type_name(&rom, headered);
+        let copier_header = if rom.len() % 1024 == COPIER_HEADER_SIZE {
+            Some(rom.drain(..COPIER_HEADER_SIZE).collect::<Vec<u8>>().into_boxed_slice())
+        } else {
+            None
+        };
+
+        let forced_lorom = rom_type.is_some_and(|rom_type| rom_type.header_start() == LOROM_ROM_NAME_START);
+        let interleaved = !forced_lorom && Rom::is_interleaved(&rom);
+
+        if interleaved {
+            rom = Rom::deinterleave(&rom);
+        }
+
+        let (rom_type, rom_name) = match rom_type {
+            Some(rom_type) => {
+                let start = rom_type.header_start();
+                let name = rom.iter().skip(start).take(21).map(|character| *character as char).collect();
+
+                (rom_type, name)
+            },
+            None => Rom::get_type_name(&rom)?,
+        };
+
+        if rom_type == ExLoROM || rom_type == ExHiROM {
+            return Err(Error::UnsupportedMapper(rom_type));
+        }
+
+        if rom.len() < rom_type.header_start() + HEADER_AREA_SIZE {
+            return Err(Error::TruncatedRom(rom.len()));
+        }
 
         let reset_vector = {
             let vector_loc = match rom_type {
@@ -696,71 +966,198 @@ impl Rom {
                 HiROM | FastHiROM => {
                     HIROM_EMU_MODE_VECTORS[Vectors::RESET as usize]
                 },
-                _ => panic!("ExLo/HiROM formats not supported")
+                ExLoROM | ExHiROM => unreachable!(),
             };
 
-            rom[vector_loc + offset] as usize | ((rom[vector_loc + offset + 1] as usize) << 8)
+            rom[vector_loc] as usize | ((rom[vector_loc + 1] as usize) << 8)
         };
 
-        Rom {
+        Ok(Rom {
             data: rom.into_boxed_slice(),
             rom_type: rom_type,
             rom_name: rom_name,
-            headered: headered,
+            copier_header: copier_header,
+            interleaved: interleaved,
             reset_vector: reset_vector,
+        })
+    }
+
+    pub fn headered(&self) -> bool {
+        self.copier_header.is_some()
+    }
+
+    pub fn title(&self) -> &str {
+        self.rom_name.trim_end_matches([' ', '\0'])
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn header_byte(&self, offset: usize) -> u8 {
+        self.data.get(self.rom_type.header_start() + offset).cloned().unwrap_or(0)
+    }
+
+    fn header_word(&self, offset: usize) -> u16 {
+        self.header_byte(offset) as u16 | (self.header_byte(offset + 1) as u16) << 8
+    }
+
+    /// The ROM size the header claims, in KiB
+    pub fn header_rom_size(&self) -> usize {
+        header_size(self.header_byte(HEADER_ROM_SIZE))
+    }
+
+    /// Battery backed RAM on the cartridge, in KiB
+    pub fn sram_size(&self) -> usize {
+        header_size(self.header_byte(HEADER_SRAM_SIZE))
+    }
+
+    pub fn country(&self) -> u8 {
+        self.header_byte(HEADER_COUNTRY)
+    }
+
+    /// Japan, North America and a few others are NTSC; Europe and Australia are PAL
+    pub fn region(&self) -> Region {
+        match self.country() {
+            0x02 ... 0x0C | 0x11 => Region::PAL,
+            _ => Region::NTSC,
         }
     }
 
-    fn get_type_name(data: &Vec<u8>, headered: bool) -> (RomType, String) {
-        use std::ascii::AsciiExt;
-        use self::RomType::*;
+    pub fn version(&self) -> u8 {
+        self.header_byte(HEADER_VERSION)
+    }
 
-        let mut name = String::new();
-        let offset = if headered { HEADERED_OFFSET } else { 0 };
+    /// The checksum stored in the header and its complement
+    pub fn header_checksum(&self) -> (u16, u16) {
+        (self.header_word(HEADER_CHECKSUM), self.header_word(HEADER_COMPLEMENT))
+    }
 
-        if data[LOROM_ROM_NAME_START + offset].is_ascii() {
-            for character in data.into_iter().skip(LOROM_ROM_NAME_START + offset).take(21) {
-                name.push(*character as char);
-            }
+    /// The 16-bit sum of every byte, which the header checksum should match
+    pub fn computed_checksum(&self) -> u16 {
+        self.data.iter().fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16))
+    }
 
-            let rom_type = {
-                match data[LOROM_ROM_MAKEUP_BYTE] {
-                    LOROM_FASTROM_VAL => FastLoROM,
-                    EXLOROM_VAL => ExLoROM,
-                    _ => LoROM,
-                }
-            };
+    // Interleaved HiROM dumps (.swc/.fig/.ufo copiers) store the upper 32 KiB halves of every bank
+    // first, which puts the HiROM header where a LoROM one would be
+    fn is_interleaved(data: &[u8]) -> bool {
+        if !data.len().is_multiple_of(INTERLEAVE_BLOCK_SIZE * 2) || data.len() <= HIROM_ROM_MAKEUP_BYTE {
+            return false;
+        }
 
-            (rom_type, name)
-        } else if data[HIROM_ROM_NAME_START + offset].is_ascii() {
-            for character in data.into_iter().skip(HIROM_ROM_NAME_START + offset).take(21) {
-                name.push(*character as char);
-            }
+        let makeup = data[LOROM_ROM_MAKEUP_BYTE];
 
-            let rom_type = {
-                match data[HIROM_ROM_MAKEUP_BYTE] {
-                    HIROM_FASTROM_VAL => FastHiROM,
-                    EXHIROM_VAL => ExHiROM,
-                    _ => HiROM,
-                }
+        has_title(data, LOROM_ROM_NAME_START) && (makeup == HIROM_FASTROM_VAL || makeup == HIROM_VAL)
+    }
+
+    fn deinterleave(data: &[u8]) -> Vec<u8> {
+        let half = data.len() / 2;
+        let mut output = Vec::with_capacity(data.len());
+
+        for block in (0..half).step_by(INTERLEAVE_BLOCK_SIZE) {
+            output.extend_from_slice(&data[half + block..half + block + INTERLEAVE_BLOCK_SIZE]);
+            output.extend_from_slice(&data[block..block + INTERLEAVE_BLOCK_SIZE]);
+        }
+
+        output
+    }
+
+    // Picks whichever of the LoROM and HiROM header locations looks more like a header, LoROM
+    // on a tie
+    fn get_type_name(data: &[u8]) -> Result<(RomType, String), Error> {
+        use self::RomType::*;
+
+        let lorom = header_score(data, LOROM_ROM_NAME_START, &[LOROM_VAL, LOROM_FASTROM_VAL, EXLOROM_VAL]);
+        let hirom = header_score(data, HIROM_ROM_NAME_START, &[HIROM_VAL, HIROM_FASTROM_VAL, EXHIROM_VAL]);
+
+        if lorom.max(hirom) < MIN_HEADER_SCORE {
+            return Err(Error::NoHeader);
+        }
+
+        let (start, rom_type) = if lorom >= hirom {
+            let rom_type = match data[LOROM_ROM_MAKEUP_BYTE] {
+                LOROM_FASTROM_VAL => FastLoROM,
+                EXLOROM_VAL => ExLoROM,
+                _ => LoROM,
             };
 
-            (rom_type, name)
+            (LOROM_ROM_NAME_START, rom_type)
         } else {
-            unreachable!("Malformed header")
-        }
+            let rom_type = match data[HIROM_ROM_MAKEUP_BYTE] {
+                HIROM_FASTROM_VAL => FastHiROM,
+                EXHIROM_VAL => ExHiROM,
+                _ => HiROM,
+            };
+
+            (HIROM_ROM_NAME_START, rom_type)
+        };
+
+        let name = data[start..start + 21].iter().map(|character| *character as char).collect();
+
+        Ok((rom_type, name))
     }
 }
 
+// Sizes in the header are powers of two KiB, with zero meaning none
+fn header_size(exponent: u8) -> usize {
+    match exponent {
+        0 => 0,
+        exponent => 1usize.checked_shl(exponent as u32).unwrap_or(0),
+    }
+}
+
+// How much the area at `start` looks like a cartridge header: a title, a map mode byte meant
+// for this location, a checksum and complement that agree, and a reset vector into ROM. None of
+// these is reliable alone; homebrew often skips the checksum and some titles are blank.
+fn header_score(data: &[u8], start: usize, map_modes: &[u8]) -> u32 {
+    if data.len() < start + HEADER_AREA_SIZE {
+        return 0;
+    }
+
+    let word = |offset: usize| data[start + offset] as u16 | (data[start + offset + 1] as u16) << 8;
+    let mut score = 0;
+
+    if has_title(data, start) {
+        score += 2;
+    }
+
+    if map_modes.contains(&data[start + HEADER_MAKEUP]) {
+        score += 2;
+    }
+
+    if word(HEADER_CHECKSUM) ^ word(HEADER_COMPLEMENT) == 0xFFFF {
+        score += 4;
+    }
+
+    if word(HEADER_RESET_VECTOR) >= 0x8000 {
+        score += 1;
+    }
+
+    score
+}
+
+// Titles are ASCII or JIS X 0201 katakana, padded with spaces or NULs. A zero filled header
+// area would pass that, so it needs at least one character.
+fn has_title(data: &[u8], start: usize) -> bool {
+    let title = match data.get(start..start + 21) {
+        Some(title) => title,
+        None => return false,
+    };
+
+    title.iter().any(|&character| character != 0) &&
+        title.iter().all(|&character| matches!(character, 0x00 | 0x20 ... 0x7E | 0xA0 ... 0xDF))
+}
+
 impl fmt::Debug for Rom {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Rom {{
                     rom_type: {:?},
                     rom_name: {},
                     headered: {},
+                    interleaved: {},
                     reset_vector: {:x}
                   }}",
-               self.rom_type, self.rom_name, self.headered, self.reset_vector)
+               self.rom_type, self.rom_name, self.headered(), self.interleaved, self.reset_vector)
     }
 }
 
@@ -770,7 +1167,7 @@ impl fmt::Debug for Memory {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum RomType {
     LoROM,
     HiROM,
@@ -780,3 +1177,29 @@ pub enum RomType {
     ExHiROM,
 }
 
+impl RomType {
+    pub fn from_name(name: &str) -> Option<RomType> {
+        use self::RomType::*;
+
+        match &*name.to_lowercase() {
+            "lorom" => Some(LoROM),
+            "hirom" => Some(HiROM),
+            "fastlorom" => Some(FastLoROM),
+            "fasthirom" => Some(FastHiROM),
+            "exlorom" => Some(ExLoROM),
+            "exhirom" => Some(ExHiROM),
+            _ => None,
+        }
+    }
+
+    fn header_start(&self) -> usize {
+        use self::RomType::*;
+
+        match *self {
+            LoROM | FastLoROM | ExLoROM => LOROM_ROM_NAME_START,
+            HiROM | FastHiROM => HIROM_ROM_NAME_START,
+            ExHiROM => EXHIROM_ROM_NAME_START,
+        }
+    }
+}
+
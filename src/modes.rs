@@ -1,49 +1,39 @@
 use cpu::CPU;
-use memory::Memory;
+use bus::Bus;
 
-fn load_byte(memory: &Memory, addr: usize) -> u8 {
-    memory.get_byte(addr)
+fn load_byte<B: Bus>(memory: &mut B, addr: usize) -> u8 {
+    memory.read(addr)
 }
 
-fn load_two_bytes(memory: &Memory, addr: usize) -> u16 {
-    let low_addr = memory.get_byte(addr) as u16;
-    let high_addr = memory.get_byte(addr + 1) as u16;
+fn load_two_bytes<B: Bus>(memory: &mut B, addr: usize) -> u16 {
+    let low_addr = memory.read(addr) as u16;
+    let high_addr = memory.read(addr + 1) as u16;
     (high_addr << 8) | low_addr
 }
 
-fn load_three_bytes(memory: &Memory, addr: usize) -> u32 {
-    let low = memory.get_byte(addr) as usize;
-    let high = memory.get_byte(addr + 1) as usize;
-    let bank = memory.get_byte(addr + 2) as usize;
+fn load_three_bytes<B: Bus>(memory: &mut B, addr: usize) -> u32 {
+    let low = memory.read(addr) as usize;
+    let high = memory.read(addr + 1) as usize;
+    let bank = memory.read(addr + 2) as usize;
 
     ((bank << 16) | (high << 8) | low) as u32
 }
 
-fn store_byte(memory: &mut Memory, addr: usize, data: u8) {
-    memory.set_byte(addr, data);
+fn store_byte<B: Bus>(memory: &mut B, addr: usize, data: u8) {
+    memory.write(addr, data);
 }
 
-fn store_two_bytes(memory: &mut Memory, addr: usize, data: u16) {
+fn store_two_bytes<B: Bus>(memory: &mut B, addr: usize, data: u16) {
     let high = (data & 0xFF00) >> 8;
     let low = data & 0x00FF;
 
-    memory.set_byte(addr, low as u8);
-    memory.set_byte(addr + 1, high as u8);
-}
-
-fn store_three_bytes(memory: &mut Memory, addr: usize, data: u32) {
-    let bank = (data & 0xFF0000) >> 16;
-    let high = (data & 0xFF00) >> 8;
-    let low = data & 0xFF;
-
-    memory.set_byte(addr, low as u8);
-    memory.set_byte(addr + 1, high as u8);
-    memory.set_byte(addr + 2, bank as u8);
+    memory.write(addr, low as u8);
+    memory.write(addr + 1, high as u8);
 }
 
 pub trait Instruction {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32;
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32);
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32;
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32);
 }
 
 pub enum InstructionType {
@@ -53,7 +43,7 @@ pub enum InstructionType {
 
 pub struct Absolute { pub instruction_type: InstructionType }
 impl Instruction for Absolute {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let addr = self.get_addr(cpu, memory);
 
         cpu.program_counter += 2;
@@ -70,7 +60,7 @@ impl Instruction for Absolute {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
         let addr = self.get_addr(cpu, memory);
 
         if is_byte {
@@ -84,7 +74,7 @@ impl Instruction for Absolute {
 }
 
 impl Absolute {
-    fn get_addr(&self, cpu: &CPU, memory: &Memory) -> usize {
+    fn get_addr<B: Bus>(&self, cpu: &CPU, memory: &mut B) -> usize {
         let addr = (cpu.program_bank << 16) | cpu.program_counter;
         let bank = match self.instruction_type {
             InstructionType::LocatingData => cpu.data_bank,
@@ -97,62 +87,67 @@ impl Absolute {
 
 pub struct AbsoluteIndexedX;
 impl Instruction for AbsoluteIndexedX {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("AbsoluteIndexedX load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("AbsoluteIndexedX load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("AbsoluteIndexedX store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("AbsoluteIndexedX store")
     }
 }
 
 pub struct AbsoluteIndexedY;
 impl Instruction for AbsoluteIndexedY {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("AbsoluteIndexedY load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("AbsoluteIndexedY load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("AbsoluteIndexedY store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("AbsoluteIndexedY store")
     }
 }
 
 pub struct AbsoluteIndexedIndirect;
 impl Instruction for AbsoluteIndexedIndirect {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("AbsoluteIndexedIndirect load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("AbsoluteIndexedIndirect load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("AbsoluteIndexedIndirect store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("AbsoluteIndexedIndirect store")
     }
 }
 
 pub struct AbsoluteIndirect;
 impl Instruction for AbsoluteIndirect {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("AbsoluteIndirect load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("AbsoluteIndirect load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("AbsoluteIndirect store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("AbsoluteIndirect store")
     }
 }
 
 pub struct AbsoluteIndirectLong;
 impl Instruction for AbsoluteIndirectLong {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("AbsoluteIndirectLong load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("AbsoluteIndirectLong load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("AbsoluteIndirectLong store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("AbsoluteIndirectLong store")
     }
 }
 
 pub struct AbsoluteLong { pub instruction_type: InstructionType }
 impl Instruction for AbsoluteLong {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let addr = self.get_addr(cpu, memory);
 
         cpu.program_counter += 3;
@@ -163,13 +158,13 @@ impl Instruction for AbsoluteLong {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("AbsoluteLong store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("AbsoluteLong store")
     }
 }
 
 impl AbsoluteLong {
-    fn get_addr(&self, cpu: &CPU, memory: &Memory) -> usize {
+    fn get_addr<B: Bus>(&self, cpu: &CPU, memory: &mut B) -> usize {
         let addr = (cpu.program_bank << 16) | cpu.program_counter;
         load_three_bytes(memory, addr) as usize
     }
@@ -177,7 +172,7 @@ impl AbsoluteLong {
 
 pub struct AbsoluteLongIndexedX;
 impl Instruction for AbsoluteLongIndexedX {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let long = AbsoluteLong { instruction_type: InstructionType::LocatingData };
 
         let addr = long.load(cpu, memory, is_byte) as usize;
@@ -189,14 +184,14 @@ impl Instruction for AbsoluteLongIndexedX {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("AbsoluteLongIndexedX store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("AbsoluteLongIndexedX store")
     }
 }
 
 pub struct Accumulator;
 impl Instruction for Accumulator {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         if is_byte {
             (cpu.accumulator as u8) as u32
         } else {
@@ -204,7 +199,7 @@ impl Instruction for Accumulator {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
         if is_byte {
             cpu.accumulator = (cpu.accumulator & 0xFF00) + (data as u16);
         } else {
@@ -215,18 +210,19 @@ impl Instruction for Accumulator {
 
 pub struct BlockMove;
 impl Instruction for BlockMove {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("BlockMove load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("BlockMove load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("BlockMove store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("BlockMove store")
     }
 }
 
 pub struct DirectPage;
 impl Instruction for DirectPage {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let addr = cpu.direct_page + (load_byte(memory, cpu.program_counter) as usize);
         cpu.program_counter += 1;
 
@@ -237,7 +233,7 @@ impl Instruction for DirectPage {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
         let addr = cpu.direct_page + (load_byte(memory, cpu.program_counter) as usize);
 
         if is_byte {
@@ -252,73 +248,79 @@ impl Instruction for DirectPage {
 
 pub struct DirectPageIndexedX;
 impl Instruction for DirectPageIndexedX {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("DirectPageIndexedX load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("DirectPageIndexedX load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("DirectPageIndexedX store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("DirectPageIndexedX store")
     }
 }
 
 pub struct DirectPageIndexedY;
 impl Instruction for DirectPageIndexedY {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("DirectPageIndexedY load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("DirectPageIndexedY load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("DirectPageIndexedY store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("DirectPageIndexedY store")
     }
 }
 
 pub struct DirectPageIndexedIndirectX;
 impl Instruction for DirectPageIndexedIndirectX {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("DirectPageIndexedIndirectX load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("DirectPageIndexedIndirectX load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("DirectPageIndexedIndirectX store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("DirectPageIndexedIndirectX store")
     }
 }
 
 pub struct DirectPageIndirect;
 impl Instruction for DirectPageIndirect {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("DirectPageIndirect load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("DirectPageIndirect load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("DirectPageIndirect store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("DirectPageIndirect store")
     }
 }
 
 pub struct DirectPageIndirectLong;
 impl Instruction for DirectPageIndirectLong {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("DirectPageIndirectLong load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("DirectPageIndirectLong load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("DirectPageIndirectLong store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("DirectPageIndirectLong store")
     }
 }
 
 pub struct DirectPageIndirectIndexedY;
 impl Instruction for DirectPageIndirectIndexedY {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("DirectPageIndirectIndexedY load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("DirectPageIndirectIndexedY load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("DirectPageIndirectIndexedY store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("DirectPageIndirectIndexedY store")
     }
 }
 
 pub struct DirectPageIndirectLongIndexedY;
 impl Instruction for DirectPageIndirectLongIndexedY {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let offset = load_byte(memory, (cpu.program_bank << 16) + cpu.program_counter);
         cpu.program_counter += 1;
 
@@ -331,14 +333,14 @@ impl Instruction for DirectPageIndirectLongIndexedY {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("DirectPageIndirectLongIndexedY store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("DirectPageIndirectLongIndexedY store")
     }
 }
 
 pub struct Immediate;
 impl Instruction for Immediate {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let addr = (cpu.program_bank << 16) + cpu.program_counter;
 
         if is_byte {
@@ -350,69 +352,73 @@ impl Instruction for Immediate {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("Immediate store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("Immediate store")
     }
 }
 
 pub struct ProgramCounterRelative;
 impl Instruction for ProgramCounterRelative {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         load_byte(memory, (cpu.program_bank << 16) + cpu.program_counter) as u32
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("ProgramCounterRelative store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("ProgramCounterRelative store")
     }
 }
 
 pub struct ProgramCounterRelativeLong;
 impl Instruction for ProgramCounterRelativeLong {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("ProgramCounterRelativeLong load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("ProgramCounterRelativeLong load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("ProgramCounterRelativeLong store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("ProgramCounterRelativeLong store")
     }
 }
 
 pub struct StackAbsolute;
 impl Instruction for StackAbsolute {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("StackAbsolute load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("StackAbsolute load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("StackAbsolute store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("StackAbsolute store")
     }
 }
 
 pub struct StackDirectPageIndirect;
 impl Instruction for StackDirectPageIndirect {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("StackDirectPageIndirect load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("StackDirectPageIndirect load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("StackDirectPageIndirect store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("StackDirectPageIndirect store")
     }
 }
 
 pub struct StackProgramCounterRelative;
 impl Instruction for StackProgramCounterRelative {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("StackProgramCounterRelative load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("StackProgramCounterRelative load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("StackProgramCounterRelative store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("StackProgramCounterRelative store")
     }
 }
 
 pub struct StackPull;
 impl Instruction for StackPull {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let addr = cpu.stack_pointer + 1;
 
         if is_byte {
@@ -424,18 +430,18 @@ impl Instruction for StackPull {
         }
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
         unreachable!("StackPull doesn't have a store")
     }
 }
 
 pub struct StackPush;
 impl Instruction for StackPush {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         unreachable!("StackPush doesn't have a load")
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
         let addr = cpu.stack_pointer;
 
         if is_byte {
@@ -450,29 +456,31 @@ impl Instruction for StackPush {
 
 pub struct StackRTI;
 impl Instruction for StackRTI {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("StackRTI load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("StackRTI load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("StackRTI store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("StackRTI store")
     }
 }
 
 pub struct StackRTL;
 impl Instruction for StackRTL {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("StackRTL load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("StackRTL load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("StackRTL store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("StackRTL store")
     }
 }
 
 pub struct StackRTS;
 impl Instruction for StackRTS {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
         let pull = StackPull;
 
         let data = pull.load(cpu, memory, is_byte);
@@ -482,30 +490,32 @@ impl Instruction for StackRTS {
         (high << 8) + low
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
         unreachable!("StackRTS store doesn't exist")
     }
 }
 
 pub struct StackRelative;
 impl Instruction for StackRelative {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("StackRelative load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("StackRelative load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("StackRelative store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("StackRelative store")
     }
 }
 
 pub struct StackRelativeIndirectIndexedY;
 impl Instruction for StackRelativeIndirectIndexedY {
-    fn load(&self, cpu: &mut CPU, memory: &Memory, is_byte: bool) -> u32 {
-        panic!("StackRelativeIndirectIndexedY load not implemented")
+    fn load<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool) -> u32 {
+        cpu.unimplemented("StackRelativeIndirectIndexedY load");
+        0
     }
 
-    fn store(&self, cpu: &mut CPU, memory: &mut Memory, is_byte: bool, data: u32) {
-        panic!("StackRelativeIndirectIndexedY store not implemented")
+    fn store<B: Bus>(&self, cpu: &mut CPU, memory: &mut B, is_byte: bool, data: u32) {
+        cpu.unimplemented("StackRelativeIndirectIndexedY store")
     }
 }
 
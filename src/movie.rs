@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+use controller::PortInput;
+use snes::MachineEvent;
+
+const MAGIC: &str = "rustsnes-movie";
+const VERSION: u32 = 2;
+const FIRST_STATE_HASH_VERSION: u32 = 2; // version 1 checked a hash of just the CPU and WRAM
+
+/// How often, in frames, a hash of the machine state is written for desync detection
+pub const CHECK_INTERVAL: u64 = 60;
+
+/// A recording of everything fed into the machine, one line per frame:
+///
+/// ```text
+/// rustsnes-movie 2
+/// rom-crc32 1a2b3c4d
+/// ports pad multitap
+/// start power-on                  (or `start sram HEX...`)
+/// check 0 cbf29ce484222325        `SNES::state_hash` at the start of frame 0
+/// | 1000 0 0 0 0 0 | 0 0 0 0 0 0  frame 0: four button words, x and y for each port
+/// reset                           the console is reset before the next frame
+/// | 0 0 0 0 0 0 | 0 0 0 0 0 0
+/// ```
+///
+/// Version 1 movies still play, but their checks are ignored since they used another hash.
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub ports: [String; 2],
+    pub start: StartState,
+    pub frames: Vec<MovieFrame>,
+    checks: Vec<(u64, u64)>,
+}
+
+pub enum StartState {
+    PowerOn,
+    Sram(Vec<u8>),
+}
+
+pub struct MovieFrame {
+    pub event: Option<MachineEvent>,
+    pub input: [PortInput; 2],
+}
+
+impl Movie {
+    pub fn load(path: &str) -> Result<Movie, String> {
+        let mut text = String::new();
+
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("Could not read movie {}: {}", path, err))?;
+
+        Movie::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut lines = text.lines().enumerate();
+        let mut header = |name: &str| -> Result<Vec<String>, String> {
+            match lines.next() {
+                Some((_, line)) if line.starts_with(name) => {
+                    Ok(line.split_whitespace().skip(1).map(|field| field.to_string()).collect())
+                },
+                _ => Err(format!("Missing {} line", name)),
+            }
+        };
+
+        let version = header(MAGIC)?;
+        let version = match version.first().and_then(|version| version.parse::<u32>().ok()) {
+            Some(number) if (1..=VERSION).contains(&number) => number,
+            _ => return Err(format!("Unsupported movie version {:?}", version)),
+        };
+
+        let crc = header("rom-crc32")?;
+        let rom_crc32 = crc.first().and_then(|crc| u32::from_str_radix(crc, 16).ok()).ok_or("Bad ROM checksum")?;
+
+        let ports = header("ports")?;
+
+        if ports.len() != 2 {
+            return Err("Expected two port devices".to_string());
+        }
+
+        let start = header("start")?;
+        let start = match start.first().map(|kind| kind.as_str()) {
+            Some("power-on") => StartState::PowerOn,
+            Some("sram") => StartState::Sram(parse_hex_bytes(start.get(1).map(|hex| hex.as_str()).unwrap_or(""))?),
+            _ => return Err("Bad start state".to_string()),
+        };
+
+        let mut movie = Movie {
+            rom_crc32: rom_crc32,
+            ports: [ports[0].clone(), ports[1].clone()],
+            start: start,
+            frames: Vec::new(),
+            checks: Vec::new(),
+        };
+
+        let mut pending_event = None;
+
+        for (number, line) in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let bad_line = || format!("Line {}: can't parse {}", number + 1, line);
+
+            match fields.first() {
+                None => {},
+                Some(&"|") => {
+                    let input = parse_frame(&fields).ok_or_else(bad_line)?;
+                    movie.frames.push(MovieFrame { event: pending_event.take(), input: input });
+                },
+                Some(&"check") if fields.len() == 3 => {
+                    let frame = fields[1].parse::<u64>().map_err(|_| bad_line())?;
+                    let hash = u64::from_str_radix(fields[2], 16).map_err(|_| bad_line())?;
+
+                    if version >= FIRST_STATE_HASH_VERSION {
+                        movie.checks.push((frame, hash));
+                    }
+                },
+                Some(name) => pending_event = Some(MachineEvent::from_name(name).ok_or_else(bad_line)?),
+            }
+        }
+
+        Ok(movie)
+    }
+
+    /// The state hash recorded at the start of a frame, if one was
+    pub fn check(&self, frame: u64) -> Option<u64> {
+        self.checks.iter().find(|&&(check_frame, _)| check_frame == frame).map(|&(_, hash)| hash)
+    }
+}
+
+/// Writes a movie as it's recorded, flushing every frame so an interrupted run still leaves a
+/// playable file behind
+pub struct MovieRecorder {
+    out: BufWriter<File>,
+}
+
+impl MovieRecorder {
+    pub fn create(path: &str, rom_crc32: u32, ports: &[String; 2], start: &StartState) -> io::Result<MovieRecorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "{} {}", MAGIC, VERSION)?;
+        writeln!(out, "rom-crc32 {:08x}", rom_crc32)?;
+        writeln!(out, "ports {} {}", ports[0], ports[1])?;
+
+        match *start {
+            StartState::PowerOn => writeln!(out, "start power-on")?,
+            StartState::Sram(ref sram) => writeln!(out, "start sram {}", to_hex_bytes(sram))?,
+        }
+
+        Ok(MovieRecorder { out: out })
+    }
+
+    pub fn record_check(&mut self, frame: u64, hash: u64) -> io::Result<()> {
+        writeln!(self.out, "check {} {:016x}", frame, hash)
+    }
+
+    pub fn record_frame(&mut self, event: Option<MachineEvent>, input: &[PortInput; 2]) -> io::Result<()> {
+        if let Some(event) = event {
+            writeln!(self.out, "{}", event.name())?;
+        }
+
+        let ports: Vec<String> = input.iter().map(|port| {
+            format!("| {:x} {:x} {:x} {:x} {} {}",
+                    port.buttons[0], port.buttons[1], port.buttons[2], port.buttons[3], port.x, port.y)
+        }).collect();
+
+        writeln!(self.out, "{}", ports.join(" "))?;
+        self.out.flush()
+    }
+}
+
+/// Something that happened while playing a movie back
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MovieEvent {
+    /// Every recorded frame has been played, so the controllers are released from here on
+    Finished { frame: u64 },
+    /// The machine's hash differs from the recording's. Only the first desync is reported.
+    Desynced { frame: u64, hash: u64 },
+}
+
+/// Feeds a movie back in frame by frame, comparing state hashes as it goes
+pub struct MoviePlayer {
+    movie: Movie,
+    desynced_at: Option<u64>,
+}
+
+impl MoviePlayer {
+    pub fn new(movie: Movie) -> MoviePlayer {
+        MoviePlayer { movie: movie, desynced_at: None }
+    }
+
+    pub fn frame(&self, frame: u64) -> Option<&MovieFrame> {
+        self.movie.frames.get(frame as usize)
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.movie.frames.len() as u64
+    }
+
+    /// Compares the machine's state hash against the recording. Returns false the first time
+    /// they disagree; later mismatches are consequences of that one and aren't reported.
+    pub fn verify(&mut self, frame: u64, hash: u64) -> bool {
+        match self.movie.check(frame) {
+            Some(expected) if expected != hash && self.desynced_at.is_none() => {
+                self.desynced_at = Some(frame);
+                false
+            },
+            _ => true,
+        }
+    }
+
+    pub fn desynced_at(&self) -> Option<u64> {
+        self.desynced_at
+    }
+}
+
+fn parse_frame(fields: &[&str]) -> Option<[PortInput; 2]> {
+    let mut input = [PortInput::default(); 2];
+    let mut ports = fields.split(|field| *field == "|").skip(1);
+
+    for port in input.iter_mut() {
+        let values = ports.next()?;
+
+        if values.len() != 6 {
+            return None;
+        }
+
+        for (buttons, value) in port.buttons.iter_mut().zip(values) {
+            *buttons = u16::from_str_radix(value, 16).ok()?;
+        }
+
+        port.x = values[4].parse().ok()?;
+        port.y = values[5].parse().ok()?;
+    }
+
+    Some(input)
+}
+
+// SRAM is stored up to its last non-zero byte, since most of it is usually unused
+fn to_hex_bytes(data: &[u8]) -> String {
+    let length = data.iter().rposition(|byte| *byte != 0).map(|last| last + 1).unwrap_or(0);
+
+    data[..length].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("Odd length SRAM data".to_string());
+    }
+
+    // The pair is sliced by bytes, so anything but hex digits (or a + sign, which from_str_radix
+    // takes) is turned away before it can split a character
+    (0..hex.len()).step_by(2)
+        .map(|index| {
+            hex.get(index..index + 2)
+                .filter(|pair| pair.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or("Bad SRAM data".to_string())
+        })
+        .collect()
+}
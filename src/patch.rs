@@ -0,0 +1,293 @@
+use crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+const FOOTER_SIZE: usize = 12; // source, target and patch CRC-32s
+const MAX_TARGET_SIZE: usize = 0x1000000; // everything the CPU can address
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PatchFormat {
+    IPS,
+    UPS,
+    BPS,
+}
+
+impl PatchFormat {
+    pub fn detect(patch: &[u8]) -> Option<PatchFormat> {
+        if patch.starts_with(IPS_MAGIC) {
+            Some(PatchFormat::IPS)
+        } else if patch.starts_with(UPS_MAGIC) {
+            Some(PatchFormat::UPS)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Some(PatchFormat::BPS)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            PatchFormat::IPS => "ips",
+            PatchFormat::UPS => "ups",
+            PatchFormat::BPS => "bps",
+        }
+    }
+}
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    match PatchFormat::detect(patch) {
+        Some(PatchFormat::IPS) => apply_ips(source, patch),
+        Some(PatchFormat::UPS) => apply_ups(source, patch),
+        Some(PatchFormat::BPS) => apply_bps(source, patch),
+        None => Err("Not an IPS, UPS or BPS patch".to_string()),
+    }
+}
+
+/// IPS has no checksums, so any source is accepted
+pub fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = PatchReader::new(patch, IPS_MAGIC.len());
+    let mut target = source.to_vec();
+
+    loop {
+        if reader.remaining().starts_with(IPS_EOF) {
+            reader.skip(IPS_EOF.len());
+            break;
+        }
+
+        let offset = reader.read_be(3)?;
+        let size = reader.read_be(2)?;
+
+        // A zero size marks a run of one repeated byte
+        let (size, data) = if size == 0 {
+            let count = reader.read_be(2)?;
+            (count, vec![reader.read_byte()?; count])
+        } else {
+            (size, reader.read_bytes(size)?.to_vec())
+        };
+
+        if target.len() < offset + size {
+            target.resize(offset + size, 0);
+        }
+
+        target[offset..offset + size].copy_from_slice(&data);
+    }
+
+    // Some patches follow EOF with the size to truncate the result to
+    if reader.remaining().len() == 3 {
+        let size = reader.read_be(3)?;
+        target.truncate(size);
+    }
+
+    Ok(target)
+}
+
+pub fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let (source_crc, target_crc) = check_footer(source, patch)?;
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], UPS_MAGIC.len());
+
+    let source_size = reader.read_number()?;
+    let target_size = check_target_size(reader.read_number()?)?;
+
+    if source_size != source.len() || crc32::crc32(source) != source_crc {
+        return Err("The patch is for a different ROM".to_string());
+    }
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+
+    let mut offset: usize = 0;
+
+    // Each hunk skips ahead and then XORs bytes in until a zero, which also counts as a byte
+    while !reader.remaining().is_empty() {
+        offset = offset.checked_add(reader.read_number()?).ok_or("The patch is corrupt")?;
+
+        loop {
+            let data = reader.read_byte()?;
+
+            if offset < target.len() {
+                target[offset] ^= data;
+            }
+
+            offset += 1;
+
+            if data == 0 {
+                break;
+            }
+        }
+    }
+
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let (source_crc, target_crc) = check_footer(source, patch)?;
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], BPS_MAGIC.len());
+
+    let source_size = reader.read_number()?;
+    let target_size = check_target_size(reader.read_number()?)?;
+    let metadata_size = reader.read_number()?;
+    reader.skip(metadata_size);
+
+    if source_size != source.len() || crc32::crc32(source) != source_crc {
+        return Err("The patch is for a different ROM".to_string());
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+
+    while !reader.remaining().is_empty() {
+        let action = reader.read_number()?;
+        let length = (action >> 2) + 1;
+
+        if length > target_size - target.len() {
+            return Err("The patch writes past the end of the ROM".to_string());
+        }
+
+        match action & 3 {
+            0 => {
+                // SourceRead: the bytes at the same position in the source
+                let start = target.len();
+                let data = source.get(start..start + length).ok_or("Source read past the end")?;
+                target.extend_from_slice(data);
+            },
+            1 => {
+                // TargetRead: literal bytes from the patch
+                let data = reader.read_bytes(length)?;
+                target.extend_from_slice(data);
+            },
+            2 => {
+                // SourceCopy: bytes from anywhere in the source, relative to the last copy
+                source_offset = source_offset.checked_add(reader.read_signed()?).ok_or("Source copy out of range")?;
+
+                for _ in 0..length {
+                    let data = *source.get(source_offset as usize).ok_or("Source copy past the end")?;
+                    target.push(data);
+                    source_offset += 1;
+                }
+            },
+            _ => {
+                // TargetCopy: bytes already written, one at a time so runs can overlap
+                target_offset = target_offset.checked_add(reader.read_signed()?).ok_or("Target copy out of range")?;
+
+                for _ in 0..length {
+                    let data = *target.get(target_offset as usize).ok_or("Target copy past the end")?;
+                    target.push(data);
+                    target_offset += 1;
+                }
+            },
+        }
+    }
+
+    if target.len() != target_size {
+        return Err("The patch produced the wrong size ROM".to_string());
+    }
+
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+// Checks the patch's own CRC and returns the source and target CRCs it expects
+fn check_footer(source: &[u8], patch: &[u8]) -> Result<(u32, u32), String> {
+    if patch.len() < 4 + FOOTER_SIZE {
+        return Err("The patch is truncated".to_string());
+    }
+
+    let footer = &patch[patch.len() - FOOTER_SIZE..];
+    let read_crc = |index: usize| {
+        footer[index * 4..index * 4 + 4].iter().rev().fold(0u32, |crc, byte| (crc << 8) | *byte as u32)
+    };
+
+    if crc32::crc32(&patch[..patch.len() - 4]) != read_crc(2) {
+        return Err("The patch is corrupt".to_string());
+    }
+
+    Ok((read_crc(0), read_crc(1)))
+}
+
+fn check_target_size(size: usize) -> Result<usize, String> {
+    if size > MAX_TARGET_SIZE {
+        return Err("The patch makes a ROM bigger than the console can address".to_string());
+    }
+
+    Ok(size)
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), String> {
+    if crc32::crc32(target) != expected {
+        return Err("The patched ROM doesn't match the checksum in the patch".to_string());
+    }
+
+    Ok(())
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> PatchReader<'a> {
+        PatchReader { data: data, position: position }
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        &self.data[self.position.min(self.data.len())..]
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.position = self.position.saturating_add(count);
+    }
+
+    fn read_byte(&mut self) -> Result<u8, String> {
+        let data = *self.data.get(self.position).ok_or("The patch is truncated")?;
+        self.position += 1;
+
+        Ok(data)
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position.checked_add(count).ok_or("The patch is truncated")?;
+        let data = self.data.get(self.position..end).ok_or("The patch is truncated")?;
+        self.position += count;
+
+        Ok(data)
+    }
+
+    fn read_be(&mut self, count: usize) -> Result<usize, String> {
+        let bytes = self.read_bytes(count)?;
+
+        Ok(bytes.iter().fold(0, |value, byte| (value << 8) | *byte as usize))
+    }
+
+    // UPS and BPS numbers are little endian 7-bit groups, with each continuation adding one so
+    // every value has exactly one encoding. Anything that doesn't fit in a usize is corrupt.
+    fn read_number(&mut self) -> Result<usize, String> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.read_byte()?;
+            value = ((byte & 0x7F) as usize).checked_mul(shift).and_then(|part| value.checked_add(part))
+                .ok_or("The patch has a number that's too big")?;
+
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+
+            shift = shift.checked_mul(0x80).ok_or("The patch has a number that's too big")?;
+            value = value.checked_add(shift).ok_or("The patch has a number that's too big")?;
+        }
+    }
+
+    // BPS copy offsets keep their sign in the lowest bit
+    fn read_signed(&mut self) -> Result<isize, String> {
+        let value = self.read_number()?;
+        let magnitude = (value >> 1) as isize;
+
+        Ok(if value & 1 != 0 { -magnitude } else { magnitude })
+    }
+}
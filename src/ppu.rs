@@ -1,12 +1,152 @@
 use memory::Memory;
+use snes::Region;
+use state::{SectionReader, SectionWriter};
 
-pub struct PPU;
+pub const MASTER_CYCLES_PER_LINE: u32 = 1364;
+pub const VBLANK_START_LINE: u32 = 225;
+pub const HBLANK_START_CYCLE: u32 = 1096; // dot 274
+pub const FIRST_VISIBLE_DOT: u32 = 22;
+pub const FIRST_VISIBLE_LINE: u32 = 1;
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 224;
+
+/// Keeps the beam timing and draws the backdrop: each visible line is filled with CGRAM color 0
+/// at INIDISP's brightness as the beam finishes it, or black while the screen is force blanked.
+/// Backgrounds and sprites aren't drawn yet.
+#[derive(Clone)]
+pub struct PPU {
+    h_cycles: u32, // master cycles into the current scanline
+    v_counter: u32,
+    frame: u64,
+    lines_per_frame: u32,
+    framebuffer: Vec<u8>, // 24-bit RGB
+}
 
 impl PPU {
-    pub fn new() -> PPU {
-        PPU
+    pub fn new(region: Region) -> PPU {
+        PPU {
+            h_cycles: 0,
+            v_counter: 0,
+            frame: 0,
+            lines_per_frame: region.lines_per_frame(),
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3],
+        }
+    }
+
+    /// Restarts the beam at the top of the screen; the frame count carries on
+    pub fn reset(&mut self) {
+        self.h_cycles = 0;
+        self.v_counter = 0;
+    }
+
+    /// Advances the beam by the given number of master cycles and returns true once a frame completes
+    pub fn run(&mut self, memory: &mut Memory, master_cycles: u32) -> bool {
+        let mut frame_done = false;
+        let start = self.v_counter * MASTER_CYCLES_PER_LINE + self.h_cycles;
+
+        self.h_cycles += master_cycles;
+
+        while self.h_cycles >= MASTER_CYCLES_PER_LINE {
+            self.h_cycles -= MASTER_CYCLES_PER_LINE;
+
+            if self.v_counter >= FIRST_VISIBLE_LINE && self.v_counter < FIRST_VISIBLE_LINE + SCREEN_HEIGHT as u32 {
+                self.draw_line(memory, (self.v_counter - FIRST_VISIBLE_LINE) as usize);
+            }
+
+            self.v_counter += 1;
+
+            if self.v_counter == VBLANK_START_LINE {
+                memory.start_vblank();
+            }
+
+            if self.v_counter == self.lines_per_frame {
+                self.v_counter = 0;
+                self.frame += 1;
+                frame_done = true;
+
+                memory.end_vblank();
+            }
+        }
+
+        self.check_light_gun(memory, start, master_cycles);
+
+        memory.set_beam_position(self.h_counter() as u16, self.v_counter as u16);
+        memory.set_hblank(self.h_cycles >= HBLANK_START_CYCLE);
+        memory.advance_joypad(master_cycles);
+
+        frame_done
+    }
+
+    fn draw_line(&mut self, memory: &Memory, row: usize) {
+        let color = backdrop_color(memory);
+        let line = &mut self.framebuffer[row * SCREEN_WIDTH * 3..(row + 1) * SCREEN_WIDTH * 3];
+
+        for pixel in line.chunks_mut(3) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    // Latches the counters if the beam passed the spot a light gun is aimed at during this step
+    fn check_light_gun(&self, memory: &mut Memory, start: u32, master_cycles: u32) {
+        if let Some((x, y)) = memory.light_gun_target() {
+            let dot = x as u32 + FIRST_VISIBLE_DOT;
+            let line = y as u32 + FIRST_VISIBLE_LINE;
+            let target = line * MASTER_CYCLES_PER_LINE + dot * 4;
+            let frame_length = self.lines_per_frame * MASTER_CYCLES_PER_LINE;
+
+            let distance = (target + frame_length - start) % frame_length;
+
+            if distance < master_cycles {
+                memory.latch_counters(dot as u16, line as u16);
+            }
+        }
+    }
+
+    pub fn save_state(&self, out: &mut SectionWriter) {
+        out.write_u32(self.h_cycles);
+        out.write_u32(self.v_counter);
+        out.write_u64(self.frame);
+    }
+
+    pub fn load_state(&mut self, input: &mut SectionReader) -> Result<(), String> {
+        self.h_cycles = input.read_u32()?;
+        self.v_counter = input.read_u32()?;
+        self.frame = input.read_u64()?;
+
+        Ok(())
+    }
+
+    pub fn h_counter(&self) -> u32 {
+        self.h_cycles / 4
     }
 
-    pub fn run(&mut self, memory: &mut Memory) {
+    pub fn v_counter(&self) -> u32 {
+        self.v_counter
     }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+}
+
+// Five bits a channel, widened to eight and scaled by the brightness
+fn backdrop_color(memory: &Memory) -> [u8; 3] {
+    let inidisp = memory.inidisp();
+
+    if inidisp & 0x80 != 0 {
+        return [0; 3];
+    }
+
+    let brightness = (inidisp & 0x0F) as u32;
+    let color = memory.cgram()[0] as u32 | (memory.cgram()[1] as u32) << 8;
+    let channel = |shift: u32| {
+        let value = (color >> shift) & 0x1F;
+        ((value << 3 | value >> 2) * brightness / 15) as u8
+    };
+
+    [channel(0), channel(5), channel(10)]
 }
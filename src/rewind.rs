@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+/// Frames between keyframes. Every other frame is stored as the bytes that differ from its
+/// group's keyframe, so any frame can be restored from two pieces.
+const KEYFRAME_INTERVAL: usize = 60;
+
+// A changed run only ends after this many unchanged bytes, so short gaps don't cost a header each
+const MIN_GAP: usize = 8;
+
+struct Group {
+    keyframe: Vec<u8>, // deflated
+    deltas: Vec<Vec<u8>>,
+}
+
+/// A ring buffer of save states, one per frame, for stepping back through recent history.
+/// When it's full the oldest keyframe and the frames that depend on it are dropped together.
+pub struct RewindBuffer {
+    capacity: usize,
+    frames: usize,
+    groups: VecDeque<Group>,
+    reference: Vec<u8>, // the newest group's keyframe, uncompressed, for encoding against
+}
+
+impl RewindBuffer {
+    /// Keeps at least `capacity` frames once that many have been pushed
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            capacity: capacity,
+            frames: 0,
+            groups: VecDeque::new(),
+            reference: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.groups.clear();
+        self.reference.clear();
+    }
+
+    /// Adds the newest state
+    pub fn push(&mut self, state: Vec<u8>) {
+        let start_group = match self.groups.back() {
+            Some(group) => group.deltas.len() + 1 >= KEYFRAME_INTERVAL || state.len() != self.reference.len(),
+            None => true,
+        };
+
+        if start_group {
+            self.groups.push_back(Group { keyframe: deflate(&state), deltas: Vec::new() });
+            self.reference = state;
+        } else {
+            let delta = encode_delta(&self.reference, &state);
+            self.groups.back_mut().unwrap().deltas.push(delta);
+        }
+
+        self.frames += 1;
+
+        // Drop the oldest group while the rest still hold enough frames
+        while self.groups.len() > 1 && self.frames - self.groups[0].deltas.len() > self.capacity {
+            let group = self.groups.pop_front().unwrap();
+            self.frames -= group.deltas.len() + 1;
+        }
+    }
+
+    /// Removes and returns the newest state
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let group = self.groups.back_mut()?;
+        self.frames -= 1;
+
+        match group.deltas.pop() {
+            Some(delta) => Some(decode_delta(&self.reference, &delta)),
+            None => {
+                self.groups.pop_back();
+                let state = ::std::mem::take(&mut self.reference);
+
+                // A keyframe that won't inflate leaves everything before this state unusable
+                if let Some(reference) = self.groups.back().map(|group| inflate(&group.keyframe)) {
+                    match reference {
+                        Ok(reference) => self.reference = reference,
+                        Err(_) => self.clear(),
+                    }
+                }
+
+                Some(state)
+            },
+        }
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(data).expect("Deflating to memory can't fail");
+    encoder.finish().expect("Deflating to memory can't fail")
+}
+
+fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut state = Vec::new();
+    DeflateDecoder::new(data).read_to_end(&mut state)?;
+
+    Ok(state)
+}
+
+// Runs of changed bytes, each written as how far it is from the end of the last one, its length
+// and then the new bytes
+fn encode_delta(reference: &[u8], state: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut position = 0;
+
+    loop {
+        let start = match (position..state.len()).find(|&i| state[i] != reference[i]) {
+            Some(start) => start,
+            None => return delta,
+        };
+
+        let mut end = start;
+        let mut gap = 0;
+
+        while end < state.len() && gap < MIN_GAP {
+            gap = if state[end] == reference[end] { gap + 1 } else { 0 };
+            end += 1;
+        }
+
+        let end = end - gap;
+
+        write_number(&mut delta, start - position);
+        write_number(&mut delta, end - start);
+        delta.extend_from_slice(&state[start..end]);
+        position = end;
+    }
+}
+
+fn decode_delta(reference: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut state = reference.to_vec();
+    let mut position = 0;
+    let mut input = delta;
+
+    while !input.is_empty() {
+        let start = position + read_number(&mut input);
+        let length = read_number(&mut input);
+
+        state[start..start + length].copy_from_slice(&input[..length]);
+        input = &input[length..];
+        position = start + length;
+    }
+
+    state
+}
+
+// Seven bits a byte, low first, with the top bit set on all but the last
+fn write_number(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+fn read_number(input: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = input[0];
+        *input = &input[1..];
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crc32;
+use ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_COLOR_RGB: u8 = 2;
+const PNG_COLOR_RGBA: u8 = 6;
+
+/// Writes a 24-bit RGB framebuffer as a binary PPM, which most image tools can open
+pub fn write_ppm(path: &str, framebuffer: &[u8]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    write!(out, "P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT)?;
+    out.write_all(framebuffer)?;
+    out.flush()
+}
+
+pub fn write_png(path: &str, framebuffer: &[u8]) -> io::Result<()> {
+    File::create(path)?.write_all(&encode_png(framebuffer))
+}
+
+/// Saves a PPM if the path ends in .ppm and a PNG otherwise
+pub fn write_image(path: &str, framebuffer: &[u8]) -> io::Result<()> {
+    if path.to_lowercase().ends_with(".ppm") {
+        write_ppm(path, framebuffer)
+    } else {
+        write_png(path, framebuffer)
+    }
+}
+
+/// A 24-bit RGB framebuffer as an unfiltered 8-bit RGB PNG
+pub fn encode_png(framebuffer: &[u8]) -> Vec<u8> {
+    let row_size = SCREEN_WIDTH * 3;
+    let mut rows = Vec::with_capacity((row_size + 1) * SCREEN_HEIGHT);
+
+    for row in framebuffer.chunks(row_size) {
+        rows.push(0); // no filter
+        rows.extend_from_slice(row);
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&rows).expect("Compressing to memory can't fail");
+    let image_data = encoder.finish().expect("Compressing to memory can't fail");
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(SCREEN_WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(SCREEN_HEIGHT as u32).to_be_bytes());
+    header.extend_from_slice(&[8, PNG_COLOR_RGB, 0, 0, 0]); // depth, color type, compression, filter, interlace
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &image_data);
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+/// Reads an 8-bit RGB or RGBA PNG, such as a golden screenshot, as its width, height and 24-bit
+/// RGB pixels. Alpha is dropped; palettes, other depths and interlacing aren't supported.
+pub fn decode_png(png: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err("Not a PNG".to_string());
+    }
+
+    let mut position = PNG_SIGNATURE.len();
+    let mut header = None;
+    let mut image_data = Vec::new();
+
+    while position + 12 <= png.len() {
+        let length = u32::from_be_bytes([png[position], png[position + 1], png[position + 2], png[position + 3]]) as usize;
+        let kind = &png[position + 4..position + 8];
+        let data = png.get(position + 8..position + 8 + length).ok_or("The PNG is truncated")?;
+
+        match kind {
+            b"IHDR" if length >= 13 => header = Some((
+                u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize,
+                u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize,
+                data[8], data[9], data[12],
+            )),
+            b"IDAT" => image_data.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {},
+        }
+
+        position += 12 + length;
+    }
+
+    let (width, height, depth, color, interlace) = header.ok_or("The PNG has no header")?;
+    let channels = match (depth, color, interlace) {
+        (8, PNG_COLOR_RGB, 0) => 3,
+        (8, PNG_COLOR_RGBA, 0) => 4,
+        _ => return Err(format!("Unsupported PNG: depth {}, color type {}, interlace {}", depth, color, interlace)),
+    };
+
+    let mut rows = Vec::new();
+    ZlibDecoder::new(&image_data[..]).read_to_end(&mut rows).map_err(|err| format!("Bad PNG data: {}", err))?;
+
+    let stride = width * channels;
+
+    if rows.len() < (stride + 1) * height {
+        return Err("The PNG's image data is truncated".to_string());
+    }
+
+    let mut pixels = vec![0u8; stride * height];
+
+    for y in 0..height {
+        let filter = rows[y * (stride + 1)];
+        let line = &rows[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+
+        for x in 0..stride {
+            let left = if x >= channels { pixels[y * stride + x - channels] } else { 0 };
+            let up = if y > 0 { pixels[(y - 1) * stride + x] } else { 0 };
+            let up_left = if x >= channels && y > 0 { pixels[(y - 1) * stride + x - channels] } else { 0 };
+
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(format!("Bad PNG filter {}", filter)),
+            };
+
+            pixels[y * stride + x] = line[x].wrapping_add(predicted);
+        }
+    }
+
+    if channels == 4 {
+        pixels = pixels.chunks(4).flat_map(|pixel| pixel[..3].to_vec()).collect();
+    }
+
+    Ok((width, height, pixels))
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(),
+                                        (estimate - up_left as i16).abs());
+
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc32::update(crc32::crc32(kind), data).to_be_bytes());
+}
@@ -0,0 +1,250 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+
+use serde_json::{self, Value};
+
+use controller::{self, PortInput};
+use snes::MachineEvent;
+
+/// A schedule of controller changes keyed by frame number. Each entry replaces the state of
+/// one port (or one multitap pad) from its frame onwards.
+///
+/// The text format is one entry per line, `#` starting a comment:
+///
+/// ```text
+/// FRAME PORT[.PAD] [BUTTON...] [x=X] [y=Y]
+/// 120 1 start
+/// 125 1
+/// 300 2.3 a right
+/// 400 reset
+/// 500 rewind 60
+/// ```
+///
+/// The JSON format is an array of entries:
+///
+/// ```text
+/// [{"frame": 120, "port": 1, "buttons": ["start"]}, {"frame": 300, "port": 2, "pad": 3, "x": 10},
+///  {"frame": 400, "event": "reset"}, {"frame": 500, "event": "rewind", "frames": 60}]
+/// ```
+///
+/// `reset` and `power` press the console's reset button or cycle its power at the start of the frame.
+/// `rewind` goes back that many frames through the console's rewind buffer. The script doesn't
+/// go back with it, so the frames played again keep the input they had when the rewind
+/// happened, and later entries wait for their frame numbers to come round again.
+/// Ports and pads are numbered from 1, matching the labels on the console and the multitap.
+pub struct InputScript {
+    events: Vec<ScriptEvent>,
+    next: usize,
+    current: [PortInput; 2],
+}
+
+struct ScriptEvent {
+    frame: u64,
+    action: Action,
+}
+
+enum Action {
+    Input { port: usize, pad: usize, buttons: u16, x: Option<i16>, y: Option<i16> },
+    Machine(MachineEvent),
+}
+
+impl InputScript {
+    pub fn load(path: &str) -> Result<InputScript, String> {
+        let mut text = String::new();
+
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("Could not read input script {}: {}", path, err))?;
+
+        if path.ends_with(".json") {
+            InputScript::parse_json(&text)
+        } else {
+            InputScript::parse_text(&text)
+        }
+    }
+
+    pub fn parse_text(text: &str) -> Result<InputScript, String> {
+        let mut events = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let event = parse_line(line).map_err(|err| format!("Line {}: {}", number + 1, err))?;
+            events.push(event);
+        }
+
+        Ok(InputScript::new(events))
+    }
+
+    pub fn parse_json(text: &str) -> Result<InputScript, String> {
+        let json: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        let entries = json.as_array().ok_or("Expected an array of entries")?;
+        let mut events = Vec::new();
+
+        for (index, entry) in entries.iter().enumerate() {
+            let event = parse_entry(entry).map_err(|err| format!("Entry {}: {}", index, err))?;
+            events.push(event);
+        }
+
+        Ok(InputScript::new(events))
+    }
+
+    fn new(mut events: Vec<ScriptEvent>) -> InputScript {
+        // Stable, so entries for the same frame still apply in file order
+        events.sort_by_key(|event| event.frame);
+
+        InputScript {
+            events: events,
+            next: 0,
+            current: [PortInput::default(); 2],
+        }
+    }
+
+    /// The state of both ports for the given frame, and whether the console is reset or power
+    /// cycled before it. Frames must be asked for in increasing order.
+    pub fn advance(&mut self, frame: u64) -> ([PortInput; 2], Option<MachineEvent>) {
+        let mut machine_event = None;
+
+        while self.next < self.events.len() && self.events[self.next].frame <= frame {
+            match self.events[self.next].action {
+                Action::Input { port, pad, buttons, x, y } => {
+                    let input = &mut self.current[port];
+
+                    input.buttons[pad] = buttons;
+
+                    if let Some(x) = x {
+                        input.x = x;
+                    }
+
+                    if let Some(y) = y {
+                        input.y = y;
+                    }
+                },
+                Action::Machine(event) => machine_event = Some(event),
+            }
+
+            self.next += 1;
+        }
+
+        (self.current, machine_event)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    /// Whether the script needs a rewind buffer
+    pub fn rewinds(&self) -> bool {
+        self.events.iter().any(|event| matches!(event.action, Action::Machine(MachineEvent::Rewind(_))))
+    }
+}
+
+fn parse_line(line: &str) -> Result<ScriptEvent, String> {
+    let mut fields = line.split_whitespace();
+
+    let frame = fields.next().unwrap().parse::<u64>().map_err(|_| "Bad frame number")?;
+    let target = fields.next().ok_or("Missing port")?;
+
+    if let Some(event) = MachineEvent::from_name(target) {
+        return Ok(ScriptEvent { frame: frame, action: Action::Machine(event) });
+    }
+
+    if target == "rewind" {
+        let count = fields.next().ok_or("Missing frame count")?;
+        let frames = count.parse::<usize>().ok().filter(|&frames| frames > 0)
+            .ok_or(format!("Bad frame count {}", count))?;
+
+        return Ok(ScriptEvent { frame: frame, action: Action::Machine(MachineEvent::Rewind(frames)) });
+    }
+
+    let (port, pad) = parse_port(target)?;
+    let (mut buttons, mut x, mut y) = (0, None, None);
+
+    for field in fields {
+        if let Some(value) = field.strip_prefix("x=") {
+            x = Some(value.parse().map_err(|_| format!("Bad coordinate {}", field))?);
+        } else if let Some(value) = field.strip_prefix("y=") {
+            y = Some(value.parse().map_err(|_| format!("Bad coordinate {}", field))?);
+        } else {
+            buttons |= controller::button_from_name(field).ok_or(format!("Unknown button {}", field))?;
+        }
+    }
+
+    Ok(ScriptEvent {
+        frame: frame,
+        action: Action::Input { port: port, pad: pad, buttons: buttons, x: x, y: y },
+    })
+}
+
+fn parse_port(field: &str) -> Result<(usize, usize), String> {
+    let mut parts = field.splitn(2, '.');
+
+    let port = parts.next().unwrap().parse::<usize>().map_err(|_| format!("Bad port {}", field))?;
+    let pad = match parts.next() {
+        Some(pad) => pad.parse::<usize>().map_err(|_| format!("Bad pad {}", field))?,
+        None => 1,
+    };
+
+    check_port(port, pad)
+}
+
+fn check_port(port: usize, pad: usize) -> Result<(usize, usize), String> {
+    if !(1..=2).contains(&port) {
+        return Err(format!("Port {} doesn't exist", port));
+    }
+
+    if !(1..=4).contains(&pad) {
+        return Err(format!("Pad {} doesn't exist", pad));
+    }
+
+    Ok((port - 1, pad - 1))
+}
+
+fn parse_entry(entry: &Value) -> Result<ScriptEvent, String> {
+    let frame: u64 = integer(entry, "frame")?.ok_or("Missing frame")?;
+
+    if let Some(name) = entry.get("event") {
+        let name = name.as_str().ok_or("event should be a string")?;
+        let event = match name {
+            "rewind" => MachineEvent::Rewind(integer(entry, "frames")?.filter(|&frames| frames > 0)
+                .ok_or("rewind needs a number of frames")?),
+            _ => MachineEvent::from_name(name).ok_or(format!("Unknown event {}", name))?,
+        };
+
+        return Ok(ScriptEvent { frame: frame, action: Action::Machine(event) });
+    }
+
+    let (port, pad) = check_port(integer(entry, "port")?.unwrap_or(1), integer(entry, "pad")?.unwrap_or(1))?;
+    let mut buttons = 0;
+
+    if let Some(names) = entry.get("buttons") {
+        for name in names.as_array().ok_or("buttons should be an array")? {
+            let name = name.as_str().ok_or("Button names should be strings")?;
+            buttons |= controller::button_from_name(name).ok_or(format!("Unknown button {}", name))?;
+        }
+    }
+
+    Ok(ScriptEvent {
+        frame: frame,
+        action: Action::Input {
+            port: port,
+            pad: pad,
+            buttons: buttons,
+            x: integer(entry, "x")?,
+            y: integer(entry, "y")?,
+        },
+    })
+}
+
+// A field that has to be a whole number that fits in `T`, if it's there at all
+fn integer<T: TryFrom<i64>>(entry: &Value, key: &str) -> Result<Option<T>, String> {
+    match entry.get(key) {
+        Some(value) => value.as_i64().and_then(|number| T::try_from(number).ok()).map(Some)
+            .ok_or(format!("Bad {} {}", key, value)),
+        None => Ok(None),
+    }
+}
@@ -1,29 +1,573 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use error::Error;
 use memory::Memory;
 use cpu::CPU;
 use ppu::PPU;
 use apu::APU;
+use audio::AudioOutput;
+use controller::PortInput;
+use hashlog::HashLogger;
+use movie::{self, MovieEvent, MoviePlayer, MovieRecorder};
+use ppu::MASTER_CYCLES_PER_LINE;
+use rewind::RewindBuffer;
+use script::InputScript;
+use state::{StateReader, StateWriter};
+use trace::TraceLogger;
+use video::FrameSink;
+
+// The CPU's bus runs at 6, 8 or 12 master cycles per access; 8 is the common case for WRAM and SlowROM
+pub const MASTER_CYCLES_PER_CPU_CYCLE: u32 = 8;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// The console's video standard, which sets the master clock and the number of scanlines
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Region {
+    NTSC,
+    PAL,
+}
+
+impl Region {
+    pub fn from_name(name: &str) -> Option<Region> {
+        match &*name.to_lowercase() {
+            "ntsc" => Some(Region::NTSC),
+            "pal" => Some(Region::PAL),
+            _ => None,
+        }
+    }
+
+    pub fn master_clock(&self) -> u64 {
+        match *self {
+            Region::NTSC => 21_477_272,
+            Region::PAL => 21_281_370,
+        }
+    }
+
+    /// Frames per second, rounded
+    pub fn frame_rate(&self) -> u32 {
+        match *self {
+            Region::NTSC => 60,
+            Region::PAL => 50,
+        }
+    }
+
+    pub fn lines_per_frame(&self) -> u32 {
+        match *self {
+            Region::NTSC => 262,
+            Region::PAL => 312,
+        }
+    }
+}
+
+/// Things done to the console itself rather than through a controller
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MachineEvent {
+    Reset,
+    Power,
+    /// Goes back this many frames through the rewind buffer, if there is one. Only input
+    /// scripts ask for it; movies can't hold one.
+    Rewind(usize),
+}
+
+impl MachineEvent {
+    pub fn from_name(name: &str) -> Option<MachineEvent> {
+        match name {
+            "reset" => Some(MachineEvent::Reset),
+            "power" => Some(MachineEvent::Power),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MachineEvent::Reset => "reset",
+            MachineEvent::Power => "power",
+            MachineEvent::Rewind(_) => "rewind",
+        }
+    }
+}
 
 pub struct SNES {
     cpu: CPU,
     ppu: PPU,
     apu: APU,
     memory: Memory,
+    audio_output: Option<AudioOutput>,
+    frame_sinks: Vec<Box<dyn FrameSink>>,
+    input_script: Option<InputScript>,
+    recorder: Option<MovieRecorder>,
+    player: Option<MoviePlayer>,
+    movie_listener: Option<Box<dyn FnMut(MovieEvent)>>,
+    tracer: Option<TraceLogger>,
+    hash_logger: Option<HashLogger>,
+    rewind: Option<RewindBuffer>,
+    inputs: [PortInput; 2],
+    samples: Vec<i16>, // the audio of the last finished frame
+    frame_started: bool,
+    master_cycles: u64, // since the console was created
+    frame_limit: Option<u64>,
+    realtime: bool,
 }
 
 impl SNES {
-    pub fn new(cpu: CPU, ppu: PPU, apu: APU, mem: Memory) -> SNES {
+    /// Builds a console around a cartridge, with the mapper and region its header asks for
+    pub fn load_rom(rom: Vec<u8>) -> Result<SNES, Error> {
+        Ok(SNES::new(Memory::new(rom)?))
+    }
+
+    /// Builds a console around memory that's already had its cartridge, region and controllers
+    /// set up, and powers it on
+    pub fn new(mut mem: Memory) -> SNES {
+        let region = mem.region();
+
         SNES {
-            cpu: cpu,
-            ppu: ppu,
-            apu: apu,
+            cpu: CPU::new(&mut mem),
+            ppu: PPU::new(region),
+            apu: APU::new(region),
             memory: mem,
+            audio_output: None,
+            frame_sinks: Vec::new(),
+            input_script: None,
+            recorder: None,
+            player: None,
+            movie_listener: None,
+            tracer: None,
+            hash_logger: None,
+            rewind: None,
+            inputs: [PortInput::default(); 2],
+            samples: Vec::new(),
+            frame_started: false,
+            master_cycles: 0,
+            frame_limit: None,
+            realtime: false,
         }
     }
-    pub fn run(&mut self) {
+
+    pub fn set_audio_output(&mut self, output: AudioOutput) {
+        self.audio_output = Some(output);
+    }
+
+    /// Hands every finished frame and its audio to a sink, after any added before it
+    pub fn add_frame_sink(&mut self, sink: Box<dyn FrameSink>) {
+        self.frame_sinks.push(sink);
+    }
+
+    /// Drives the controllers from a schedule instead of a human
+    pub fn set_input_script(&mut self, script: InputScript) {
+        self.input_script = Some(script);
+    }
+
+    /// Writes every frame's input to a movie as it's played
+    pub fn set_recorder(&mut self, recorder: MovieRecorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Replays a movie's input, taking priority over an input script
+    pub fn set_player(&mut self, player: MoviePlayer) {
+        self.player = Some(player);
+    }
+
+    /// Tells `listener` when the movie being played finishes or desyncs
+    pub fn set_movie_listener(&mut self, listener: Box<dyn FnMut(MovieEvent)>) {
+        self.movie_listener = Some(listener);
+    }
+
+    /// Makes `run` return after emulating this many frames
+    pub fn set_frame_limit(&mut self, frames: Option<u64>) {
+        self.frame_limit = frames;
+    }
+
+    /// Paces emulation to the console's frame rate instead of running as fast as possible
+    pub fn set_realtime(&mut self, realtime: bool) {
+        self.realtime = realtime;
+    }
+
+    /// Logs every instruction before it runs
+    pub fn set_tracer(&mut self, tracer: TraceLogger) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Logs a hash of the whole machine state at the start of every frame the logger wants
+    pub fn set_hash_logger(&mut self, logger: HashLogger) {
+        self.hash_logger = Some(logger);
+    }
+
+    /// Snapshots the machine at the start of every frame, keeping at least the last `frames`
+    /// of them for `rewind`
+    pub fn set_rewind(&mut self, frames: usize) {
+        self.rewind = Some(RewindBuffer::new(frames));
+    }
+
+    /// How many frames `rewind` can go back
+    pub fn rewind_frames(&self) -> usize {
+        self.rewind.as_ref().map_or(0, |buffer| buffer.len())
+    }
+
+    /// Goes back to the start of the frame `frames` before the current one, or as far back as
+    /// the buffer reaches, and returns how many frames that was. The current frame counts as
+    /// one if it has already started.
+    pub fn rewind(&mut self, frames: usize) -> Result<usize, Error> {
+        let mut state = None;
+        let mut count = 0;
+
+        if let Some(ref mut buffer) = self.rewind {
+            while count < frames {
+                match buffer.pop() {
+                    Some(frame_state) => state = Some(frame_state),
+                    None => break,
+                }
+
+                count += 1;
+            }
+        }
+
+        if let Some(state) = state {
+            self.load_state(&state)?;
+        }
+
+        Ok(count)
+    }
+
+    /// What the device in a port sees from the next frame on. A movie or input script, if
+    /// there is one, overrides it.
+    pub fn set_input(&mut self, port: usize, input: PortInput) {
+        self.inputs[port] = input;
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// The last frame drawn, as 24-bit RGB rows of `SCREEN_WIDTH` pixels
+    pub fn framebuffer(&self) -> &[u8] {
+        self.ppu.framebuffer()
+    }
+
+    /// The last finished frame's audio, interleaved left/right at `apu::SAMPLE_RATE`
+    pub fn audio_samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// How many frames have finished since power on
+    pub fn frame(&self) -> u64 {
+        self.ppu.frame()
+    }
+
+    /// The PPU's current dot and scanline
+    pub fn beam_position(&self) -> (u32, u32) {
+        (self.ppu.h_counter(), self.ppu.v_counter())
+    }
+
+    /// Snapshots the whole machine. Host-side things such as the audio output, movies and the
+    /// tracer aren't part of it.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+
+        state.section(b"ROM ", |out| {
+            out.write_u32(self.memory.rom.checksum());
+            out.write_u8(self.memory.region() as u8);
+        });
+        state.section(b"CPU ", |out| self.cpu.save_state(out));
+        state.section(b"MEM ", |out| self.memory.save_state(out));
+        state.section(b"PPU ", |out| self.ppu.save_state(out));
+        state.section(b"APU ", |out| self.apu.save_state(out));
+        state.section(b"SYS ", |out| {
+            out.write_u64(self.master_cycles);
+            out.write_bool(self.frame_started);
+
+            for input in self.inputs.iter() {
+                for &buttons in input.buttons.iter() {
+                    out.write_u16(buttons);
+                }
+
+                out.write_u16(input.x as u16);
+                out.write_u16(input.y as u16);
+            }
+        });
+
+        state.finish()
+    }
+
+    /// Restores a snapshot taken by `save_state` with this cartridge and region. If it can't be
+    /// read, the machine is left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.read_state(data).map_err(Error::BadState)
+    }
+
+    // Components are read into copies that only replace the running ones once every section
+    // has been read
+    fn read_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let state = StateReader::parse(data)?;
+
+        // Version 1 states don't say which cartridge they came from
+        if let Some(mut rom) = state.optional_section(b"ROM ") {
+            let checksum = rom.read_u32()?;
+            let region = rom.read_u8()?;
+
+            if checksum != self.memory.rom.checksum() {
+                return Err(format!("The save state is for a different cartridge (CRC32 {:08x}, this one is {:08x})",
+                                   checksum, self.memory.rom.checksum()));
+            }
+
+            if region != self.memory.region() as u8 {
+                return Err("The save state is for a console of a different region".to_string());
+            }
+        }
+
+        let mut cpu = self.cpu.clone();
+        let mut ppu = self.ppu.clone();
+        let mut apu = self.apu.clone();
+        cpu.load_state(&mut state.section(b"CPU ")?)?;
+        ppu.load_state(&mut state.section(b"PPU ")?)?;
+        apu.load_state(&mut state.section(b"APU ")?)?;
+
+        let mut sys_state = None;
+
+        if let Some(mut sys) = state.optional_section(b"SYS ") {
+            let master_cycles = sys.read_u64()?;
+            let frame_started = sys.read_bool()?;
+            let mut inputs = [PortInput::default(); 2];
+
+            for input in inputs.iter_mut() {
+                for buttons in input.buttons.iter_mut() {
+                    *buttons = sys.read_u16()?;
+                }
+
+                input.x = sys.read_u16()? as i16;
+                input.y = sys.read_u16()? as i16;
+            }
+
+            sys_state = Some((master_cycles, frame_started, inputs));
+        }
+
+        // Memory holds devices that can't be copied, but it leaves itself alone unless its whole
+        // section reads
+        self.memory.load_state(&mut state.section(b"MEM ")?)?;
+        self.cpu = cpu;
+        self.ppu = ppu;
+        self.apu = apu;
+
+        if let Some((master_cycles, frame_started, inputs)) = sys_state {
+            self.master_cycles = master_cycles;
+            self.frame_started = frame_started;
+            self.inputs = inputs;
+
+            for (port, input) in inputs.iter().enumerate() {
+                self.memory.controller_mut(port).set_input(input);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Presses the reset button: registers and the CPU start over, RAM is left alone
+    pub fn reset(&mut self) {
+        self.memory.reset();
+        self.cpu.reset(&mut self.memory);
+        self.ppu.reset();
+    }
+
+    /// Turns the console off and on again, clearing RAM. SRAM is battery backed and survives.
+    pub fn power_cycle(&mut self) {
+        self.memory.power_cycle();
+        self.cpu.reset(&mut self.memory);
+        self.ppu.reset();
+    }
+
+    /// FNV-1a hash of everything a save state holds, so two machines with the same hash will
+    /// behave the same from here on
+    pub fn state_hash(&self) -> u64 {
+        self.save_state().iter()
+            .fold(FNV_OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+    }
+
+    /// Runs one CPU instruction and the rest of the machine alongside it. Returns whether it
+    /// finished a frame, or why the console stopped.
+    pub fn step_instruction(&mut self) -> Result<bool, Error> {
+        if !self.frame_started {
+            self.take_snapshot();
+            self.start_frame()?;
+            self.frame_started = true;
+        }
+
+        if let (Some(tracer), false) = (self.tracer.as_mut(), self.cpu.stopped()) {
+            tracer.log(&self.cpu, &self.memory, self.master_cycles, self.ppu.h_counter(), self.ppu.v_counter())?;
+        }
+
+        let cpu_cycles = self.cpu.run(&mut self.memory)?;
+        let cycles = cpu_cycles * MASTER_CYCLES_PER_CPU_CYCLE;
+        self.master_cycles += cycles as u64;
+        self.memory.run_alu(cpu_cycles);
+        let frame_done = self.ppu.run(&mut self.memory, cycles);
+        self.apu.run(&mut self.memory, cycles);
+
+        if frame_done {
+            self.flush_audio()?;
+            self.frame_started = false;
+
+            for sink in self.frame_sinks.iter_mut() {
+                sink.write_frame(self.ppu.frame() - 1, self.ppu.framebuffer(), &self.samples)?;
+            }
+        }
+
+        match self.memory.take_bus_error() {
+            Some(error) => Err(Error::Bus(error)),
+            None => Ok(frame_done),
+        }
+    }
+
+    /// Runs until the current frame finishes
+    pub fn run_frame(&mut self) -> Result<(), Error> {
+        while !self.step_instruction()? {}
+
+        Ok(())
+    }
+
+    /// Runs until the frame limit is reached, or until the CPU, a bus error or failing to write
+    /// an output stops the console
+    pub fn run(&mut self) -> Result<(), Error> {
+        let region = self.memory.region();
+        let frame_time = Duration::from_nanos(
+            region.lines_per_frame() as u64 * MASTER_CYCLES_PER_LINE as u64 * 1_000_000_000 / region.master_clock());
+        let mut next_frame = Instant::now() + frame_time;
+        let stop_at = self.frame_limit.map(|frames| self.ppu.frame() + frames);
+
         loop {
-            self.cpu.run(&mut self.memory);
-            self.ppu.run(&mut self.memory);
-            self.apu.run(&mut self.memory);
+            if let Some(stop_at) = stop_at {
+                if self.ppu.frame() >= stop_at {
+                    return Ok(());
+                }
+            }
+
+            if let Err(error) = self.run_frame() {
+                // Keep the audio up to the point it stopped, unless that's what failed
+                return Err(self.flush_audio().err().map_or(error, Error::Io));
+            }
+
+            if self.realtime {
+                let now = Instant::now();
+
+                if next_frame > now {
+                    thread::sleep(next_frame - now);
+                }
+
+                // Don't try to catch up after falling far behind, just carry on from here
+                next_frame = (next_frame + frame_time).max(now);
+            }
+        }
+    }
+
+    fn start_frame(&mut self) -> Result<(), Error> {
+        let frame = self.ppu.frame();
+        let mut event = None;
+
+        if frame.is_multiple_of(movie::CHECK_INTERVAL) {
+            self.check_movie(frame)?;
+        }
+
+        if let Some(mut logger) = self.hash_logger.take() {
+            let result = if logger.wants(frame) { logger.record(frame, self.state_hash()) } else { Ok(()) };
+            self.hash_logger = Some(logger);
+            result?;
+        }
+
+        if let Some(ref player) = self.player {
+            match player.frame(frame) {
+                Some(movie_frame) => {
+                    self.inputs = movie_frame.input;
+                    event = movie_frame.event;
+                },
+                None if frame == player.frame_count() => {
+                    self.inputs = [PortInput::default(); 2];
+
+                    if let Some(ref mut listener) = self.movie_listener {
+                        listener(MovieEvent::Finished { frame: frame });
+                    }
+                },
+                None => {},
+            }
+        } else if let Some(ref mut script) = self.input_script {
+            let (inputs, script_event) = script.advance(frame);
+
+            self.inputs = inputs;
+            event = script_event;
+        }
+
+        match event {
+            Some(MachineEvent::Reset) => self.reset(),
+            Some(MachineEvent::Power) => self.power_cycle(),
+            Some(MachineEvent::Rewind(frames)) if self.rewind.is_some() => {
+                // This frame's snapshot is already in the buffer, so that's one more to go back.
+                // The frame rewound to then starts over, snapshot and all.
+                self.rewind(frames + 1)?;
+                self.take_snapshot();
+                return self.start_frame();
+            },
+            _ => {},
+        }
+
+        for (port, input) in self.inputs.iter().enumerate() {
+            self.memory.controller_mut(port).set_input(input);
+        }
+
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record_frame(event.filter(|event| !matches!(event, MachineEvent::Rewind(_))), &self.inputs)?;
+        }
+
+        Ok(())
+    }
+
+    fn take_snapshot(&mut self) {
+        if let Some(mut buffer) = self.rewind.take() {
+            buffer.push(self.save_state());
+            self.rewind = Some(buffer);
+        }
+    }
+
+    fn check_movie(&mut self, frame: u64) -> Result<(), Error> {
+        if self.recorder.is_none() && self.player.is_none() {
+            return Ok(());
+        }
+
+        let hash = self.state_hash();
+
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.record_check(frame, hash)?;
+        }
+
+        if let Some(ref mut player) = self.player {
+            if !player.verify(frame, hash) {
+                if let Some(ref mut listener) = self.movie_listener {
+                    listener(MovieEvent::Desynced { frame: frame, hash: hash });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush_audio(&mut self) -> io::Result<()> {
+        self.samples = self.apu.take_samples();
+
+        match self.audio_output {
+            Some(ref mut output) => output.write(&self.samples),
+            None => Ok(()),
         }
     }
 }
@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Save states are a magic number and format version followed by tagged sections, one per
+/// component, each prefixed with its length so readers can skip sections they don't know.
+/// States from older versions still load, with whatever they lack left as it was; states from
+/// newer versions are refused. Version 2 added the cartridge and scheduler sections and the
+/// CPU's STP flag, and version 3 added CGRAM.
+const MAGIC: &[u8] = b"RSNS";
+pub const VERSION: u32 = 3;
+
+/// How many numbered slots a ROM gets
+pub const SLOT_COUNT: u32 = 10;
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&u32_bytes(VERSION));
+
+        StateWriter { data: data }
+    }
+
+    pub fn section<F>(&mut self, tag: &[u8; 4], write: F)
+        where F: FnOnce(&mut SectionWriter)
+    {
+        let mut section = SectionWriter { data: Vec::new() };
+        write(&mut section);
+
+        self.data.extend_from_slice(tag);
+        self.data.extend_from_slice(&u32_bytes(section.data.len() as u32));
+        self.data.extend_from_slice(&section.data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct SectionWriter {
+    data: Vec<u8>,
+}
+
+impl SectionWriter {
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&u32_bytes(value));
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_u32(value as u32);
+        self.write_u32((value >> 32) as u32);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    version: u32,
+    sections: Vec<(&'a [u8], &'a [u8])>,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<StateReader<'a>, String> {
+        if !data.starts_with(MAGIC) || data.len() < MAGIC.len() + 4 {
+            return Err("Not a save state".to_string());
+        }
+
+        let mut reader = SectionReader::new(&data[MAGIC.len()..], VERSION);
+        let version = reader.read_u32()?;
+
+        if version > VERSION {
+            return Err(format!("The save state is from a newer version (format {}, this build reads up to {})",
+                               version, VERSION));
+        }
+
+        let mut sections = Vec::new();
+
+        while !reader.remaining().is_empty() {
+            let tag = reader.read_bytes(4)?;
+            let length = reader.read_u32()? as usize;
+            let body = reader.read_bytes(length)?;
+
+            sections.push((tag, body));
+        }
+
+        Ok(StateReader { version: version, sections: sections })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn section(&self, tag: &[u8; 4]) -> Result<SectionReader<'a>, String> {
+        self.optional_section(tag)
+            .ok_or(format!("The save state has no {} section", String::from_utf8_lossy(tag)))
+    }
+
+    /// A section that states from older versions may not have
+    pub fn optional_section(&self, tag: &[u8; 4]) -> Option<SectionReader<'a>> {
+        self.sections.iter()
+            .find(|&&(section_tag, _)| section_tag == tag)
+            .map(|&(_, body)| SectionReader::new(body, self.version))
+    }
+}
+
+pub struct SectionReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    version: u32,
+}
+
+impl<'a> SectionReader<'a> {
+    fn new(data: &'a [u8], version: u32) -> SectionReader<'a> {
+        SectionReader { data: data, position: 0, version: version }
+    }
+
+    /// The format version the state was written with, for fields that were added later
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        &self.data[self.position..]
+    }
+
+    pub fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + count).ok_or("The save state is truncated")?;
+        self.position += count;
+
+        Ok(bytes)
+    }
+
+    pub fn read_into(&mut self, destination: &mut [u8]) -> Result<(), String> {
+        let bytes = self.read_bytes(destination.len())?;
+        destination.copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        let bytes = self.read_bytes(2)?;
+
+        Ok(bytes[0] as u16 | (bytes[1] as u16) << 8)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        let low = self.read_u16()? as u32;
+        let high = self.read_u16()? as u32;
+
+        Ok(low | high << 16)
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        let low = self.read_u32()? as u64;
+        let high = self.read_u32()? as u64;
+
+        Ok(low | high << 32)
+    }
+}
+
+/// Numbered save state files next to a ROM: slot 3 of game.sfc is game.state3
+pub struct StateSlots {
+    base: PathBuf,
+}
+
+impl StateSlots {
+    pub fn new(rom_path: &str) -> StateSlots {
+        StateSlots { base: Path::new(rom_path).with_extension("") }
+    }
+
+    pub fn path(&self, slot: u32) -> PathBuf {
+        self.base.with_extension(format!("state{}", slot))
+    }
+
+    pub fn save(&self, slot: u32, state: &[u8]) -> io::Result<()> {
+        File::create(self.path(slot)).and_then(|mut file| file.write_all(state))
+    }
+
+    pub fn load(&self, slot: u32) -> Result<Vec<u8>, String> {
+        let path = self.path(slot);
+        let mut data = Vec::new();
+
+        File::open(&path).and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|err| format!("Could not read slot {} ({}): {}", slot, path.display(), err))?;
+
+        Ok(data)
+    }
+}
+
+/// A slot number from 0 to `SLOT_COUNT - 1`
+pub fn parse_slot(text: &str) -> Option<u32> {
+    text.parse::<u32>().ok().filter(|&slot| slot < SLOT_COUNT)
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
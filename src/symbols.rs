@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+
+/// Labels from an assembler's symbol file, by 24-bit address and by name. These formats are
+/// understood, and can be mixed in one file:
+///
+/// ```text
+/// [labels]                        WLA-DX .sym (asar --symbols=wla writes the same)
+/// 00:8000 Reset
+/// al 008000 .Reset                ca65/ld65 VICE label file (-Ln)
+/// sym id=0,name="Reset",...,val=0x8000,...,type=lab
+///                                 ca65/ld65 debug info (--dbgfile)
+/// 00808000 Reset                  nocash lists, as bass and asar write them
+/// Reset = $808000                 equates
+/// ```
+#[derive(Clone)]
+pub struct Symbols {
+    by_addr: BTreeMap<usize, String>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols {
+            by_addr: BTreeMap::new(),
+            by_name: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Symbols, String> {
+        let mut data = Vec::new();
+
+        File::open(path).and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|err| format!("Could not read symbols {}: {}", path, err))?;
+
+        let symbols = Symbols::parse(&String::from_utf8_lossy(&data));
+
+        if symbols.is_empty() {
+            return Err(format!("No symbols found in {}", path));
+        }
+
+        Ok(symbols)
+    }
+
+    /// Reads every label it can find in `text`, skipping lines in formats it doesn't know
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::new();
+        let mut section = None;
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+
+            // WLA-DX files are split into sections, and only [labels] holds addresses
+            if line.starts_with('[') {
+                section = Some(line.to_ascii_lowercase());
+                continue;
+            }
+
+            if section.as_ref().is_some_and(|section| section != "[labels]") {
+                continue;
+            }
+
+            if let Some((addr, name)) = parse_line(line) {
+                symbols.add(addr, name);
+            }
+        }
+
+        symbols
+    }
+
+    /// Adds a label. The first name given to an address is the one shown for it, but every
+    /// name can be looked up.
+    pub fn add(&mut self, addr: usize, name: &str) {
+        let addr = addr & 0xFFFFFF;
+
+        self.by_addr.entry(addr).or_insert_with(|| name.to_string());
+        self.by_name.insert(name.to_string(), addr);
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// The label at exactly `addr`
+    pub fn name(&self, addr: usize) -> Option<&str> {
+        self.by_addr.get(&addr).map(|name| name.as_str())
+    }
+
+    /// The label at `addr` or at one of its mirrors: banks $80-$FF mirror $00-$7F, and the
+    /// system area below $8000 in banks $00-$3F and $80-$BF is the same as bank $00's
+    pub fn lookup(&self, addr: usize) -> Option<&str> {
+        let bank = addr >> 16;
+        let offset = addr & 0xFFFF;
+        let system_area = offset < 0x8000 && (bank & 0x7F) < 0x40;
+
+        self.name(addr)
+            .or_else(|| self.name(addr ^ 0x800000))
+            .or_else(|| if system_area { self.name(offset) } else { None })
+    }
+
+    /// The closest label at or before `addr` in the same bank or its mirror, and how far past it
+    /// `addr` is
+    pub fn nearest(&self, addr: usize) -> Option<(&str, usize)> {
+        if let Some(name) = self.lookup(addr) {
+            return Some((name, 0));
+        }
+
+        let before = |addr: usize| self.by_addr.range(addr & 0xFF0000..=addr).next_back()
+            .map(|(&label, name)| (name.as_str(), addr - label));
+
+        before(addr).into_iter().chain(before(addr ^ 0x800000)).min_by_key(|&(_, offset)| offset)
+    }
+
+    /// The label's address, or the address plus an offset for `name+OFFSET`
+    pub fn address(&self, name: &str) -> Option<usize> {
+        if let Some(&addr) = self.by_name.get(name) {
+            return Some(addr);
+        }
+
+        let mut parts = name.splitn(2, '+');
+        let addr = *self.by_name.get(parts.next()?.trim())?;
+        let offset = parts.next()?.trim();
+        let offset = match offset.strip_prefix('$').or_else(|| offset.strip_prefix("0x")) {
+            Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+            None => offset.parse().ok()?,
+        };
+
+        Some((addr + offset) & 0xFFFFFF)
+    }
+
+    /// `name` or `name+offset` for an address, if there's a label before it in its bank
+    pub fn describe(&self, addr: usize) -> Option<String> {
+        self.nearest(addr).map(|(name, offset)| {
+            if offset == 0 { name.to_string() } else { format!("{}+{}", name, offset) }
+        })
+    }
+}
+
+fn parse_line(line: &str) -> Option<(usize, &str)> {
+    if line.starts_with("sym") && line.contains("type=lab") {
+        return parse_dbg(line);
+    }
+
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    match fields.as_slice() {
+        // VICE labels put a dot before the name
+        ["al", addr, name] => Some((parse_hex(addr)?, name.trim_start_matches('.'))),
+        [name, "=", value] => Some((parse_hex(value.trim_start_matches('$'))?, name)),
+        [addr, name] if is_address(addr) => Some((parse_hex(addr)?, name)),
+        _ => None,
+    }
+}
+
+fn parse_dbg(line: &str) -> Option<(usize, &str)> {
+    let mut name = None;
+    let mut value = None;
+
+    for field in line["sym".len()..].trim().split(',') {
+        let mut parts = field.splitn(2, '=');
+
+        match (parts.next(), parts.next()) {
+            (Some("name"), Some(text)) => name = Some(text.trim_matches('"')),
+            (Some("val"), Some(text)) => value = parse_hex(text.trim_start_matches("0x")),
+            _ => {},
+        }
+    }
+
+    Some((value?, name?))
+}
+
+/// WLA-DX writes `BB:AAAA`, the nocash lists six or eight hex digits
+fn is_address(text: &str) -> bool {
+    let digits = text.replace(':', "");
+
+    (digits.len() >= 4 && digits.len() <= 8) && digits.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(&text.replace(':', ""), 16).ok()
+}
@@ -0,0 +1,87 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use cpu::{CPU, StatusFlags};
+use disasm;
+use memory::Memory;
+use symbols::Symbols;
+
+const FLAG_NAMES: &[u8; 8] = b"czidxmvn";
+
+/// Writes a line per instruction, before it runs:
+///
+/// ```text
+/// 80:8000 lda #$12             A:0000 X:0000 Y:0000 S:01ff D:0000 DB:00 P:nvMXdIzc E:1 CYC:1234 H:  0 V:  0
+/// ```
+///
+/// CYC counts master cycles since the console was created, and H and V are the PPU's dot and
+/// scanline. With address ranges added, only instructions inside one of them are logged, and with
+/// symbols set, operands that have a label are written as it.
+pub struct TraceLogger {
+    output: Box<dyn Write>,
+    ranges: Vec<(usize, usize)>,
+    symbols: Option<Symbols>,
+}
+
+impl TraceLogger {
+    pub fn new(output: Box<dyn Write>) -> TraceLogger {
+        TraceLogger {
+            output: output,
+            ranges: Vec::new(),
+            symbols: None,
+        }
+    }
+
+    pub fn create(path: &str) -> io::Result<TraceLogger> {
+        let file = File::create(path)?;
+
+        Ok(TraceLogger::new(Box::new(BufWriter::new(file))))
+    }
+
+    /// Limits logging to 24-bit addresses from `start` to `end` inclusive, on top of any
+    /// ranges already added
+    pub fn add_range(&mut self, start: usize, end: usize) {
+        self.ranges.push((start, end));
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = Some(symbols);
+    }
+
+    pub fn wants(&self, addr: usize) -> bool {
+        self.ranges.is_empty() || self.ranges.iter().any(|&(start, end)| addr >= start && addr <= end)
+    }
+
+    pub fn log(&mut self, cpu: &CPU, memory: &Memory, cycle: u64, h: u32, v: u32) -> io::Result<()> {
+        let addr = (cpu.program_bank << 16) | cpu.program_counter;
+
+        if !self.wants(addr) {
+            return Ok(());
+        }
+
+        writeln!(self.output, "{} CYC:{} H:{:3} V:{:3}", describe(cpu, memory, self.symbols.as_ref()), cycle, h, v)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// The next instruction and the registers it will see, as a trace line shows them
+pub fn describe(cpu: &CPU, memory: &Memory, symbols: Option<&Symbols>) -> String {
+    let addr = (cpu.program_bank << 16) | cpu.program_counter;
+    let status = &cpu.processor_status;
+    let m8 = cpu.emulation_mode || status.get_flag(StatusFlags::AccumulatorRegisterSize);
+    let x8 = cpu.emulation_mode || status.get_flag(StatusFlags::IndexRegisterSize);
+    let instruction = disasm::disassemble(memory, addr, m8, x8);
+
+    let flags: String = (0..8).rev().map(|bit| {
+        let name = FLAG_NAMES[bit] as char;
+        if status.as_byte() & (1 << bit) != 0 { name.to_ascii_uppercase() } else { name }
+    }).collect();
+
+    format!("{:02x}:{:04x} {:<20} A:{:04x} X:{:04x} Y:{:04x} S:{:04x} D:{:04x} DB:{:02x} P:{} E:{}",
+            cpu.program_bank, cpu.program_counter, instruction.text(symbols),
+            cpu.accumulator, cpu.index_x, cpu.index_y, cpu.stack_pointer, cpu.direct_page, cpu.data_bank,
+            flags, cpu.emulation_mode as u8)
+}
@@ -0,0 +1,342 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use apu::{CHANNELS, SAMPLE_RATE};
+use ppu::{MASTER_CYCLES_PER_LINE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use screenshot;
+use snes::Region;
+
+/// Takes every finished frame, with the audio played during it
+pub trait FrameSink {
+    /// `frame` counts from 0 at power on, `pixels` is 24-bit RGB and `samples` interleaved
+    /// stereo at `apu::SAMPLE_RATE`
+    fn write_frame(&mut self, frame: u64, pixels: &[u8], samples: &[i16]) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Which frames a `PngDumper` saves
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FrameSelection {
+    Every(u64),
+    Only(u64),
+}
+
+impl FrameSelection {
+    pub fn wants(&self, frame: u64) -> bool {
+        match *self {
+            FrameSelection::Every(interval) => frame.is_multiple_of(interval),
+            FrameSelection::Only(only) => frame == only,
+        }
+    }
+}
+
+/// Saves frames as numbered PNGs in a directory: frame-000120.png and so on
+pub struct PngDumper {
+    directory: PathBuf,
+    selection: FrameSelection,
+}
+
+impl PngDumper {
+    pub fn create(directory: &str, selection: FrameSelection) -> io::Result<PngDumper> {
+        fs::create_dir_all(directory)?;
+
+        Ok(PngDumper {
+            directory: PathBuf::from(directory),
+            selection: selection,
+        })
+    }
+}
+
+impl FrameSink for PngDumper {
+    fn write_frame(&mut self, frame: u64, pixels: &[u8], samples: &[i16]) -> io::Result<()> {
+        if !self.selection.wants(frame) {
+            return Ok(());
+        }
+
+        let path = self.directory.join(format!("frame-{:06}.png", frame));
+        File::create(path)?.write_all(&screenshot::encode_png(pixels))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Uncompressed YUV4MPEG2 video, 4:2:0 with full range BT.601 colors. It has no audio, so
+/// record that alongside with a `WavWriter`.
+pub struct Y4mWriter {
+    out: BufWriter<File>,
+}
+
+impl Y4mWriter {
+    pub fn create(path: &str, region: Region) -> io::Result<Y4mWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let (rate, scale) = frame_rate(region);
+
+        writeln!(out, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg", SCREEN_WIDTH, SCREEN_HEIGHT, rate, scale)?;
+
+        Ok(Y4mWriter { out: out })
+    }
+}
+
+impl FrameSink for Y4mWriter {
+    fn write_frame(&mut self, frame: u64, pixels: &[u8], samples: &[i16]) -> io::Result<()> {
+        let mut luma = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+        let mut blue = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT / 4);
+        let mut red = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT / 4);
+
+        for pixel in pixels.chunks(3) {
+            luma.push(yuv(pixel).0);
+        }
+
+        // Each chroma sample covers a 2x2 block of pixels
+        for y in (0..SCREEN_HEIGHT).step_by(2) {
+            for x in (0..SCREEN_WIDTH).step_by(2) {
+                let (mut u, mut v) = (0, 0);
+
+                for &(dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                    let offset = ((y + dy) * SCREEN_WIDTH + x + dx) * 3;
+                    let (_, pixel_u, pixel_v) = yuv(&pixels[offset..offset + 3]);
+                    u += pixel_u as u32;
+                    v += pixel_v as u32;
+                }
+
+                blue.push(((u + 2) / 4) as u8);
+                red.push(((v + 2) / 4) as u8);
+            }
+        }
+
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&luma)?;
+        self.out.write_all(&blue)?;
+        self.out.write_all(&red)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl Drop for Y4mWriter {
+    fn drop(&mut self) {
+        let _res = self.finish();
+    }
+}
+
+const AVI_HEADER_SIZE: u32 = 326; // everything before the first frame's chunk
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+const BYTES_PER_SAMPLE_FRAME: u32 = CHANNELS as u32 * 2;
+const AVI_MAX_SIZE: u64 = 1 << 30; // as far as AVI 1.0 players reliably read
+
+/// Uncompressed RGB video and 16-bit PCM audio in an AVI 1.0 file, which players handle up to
+/// about 1 GB: a little under two minutes. A frame that would take the file past that is an
+/// error, leaving the ones before it playable. The header and index are filled in by `finish`.
+pub struct AviWriter {
+    out: BufWriter<File>,
+    frames: u32,
+    audio_samples: u32,
+    movie_size: u32, // bytes of chunks in the movi list
+    index: Vec<(&'static [u8; 4], u32, u32)>, // chunk id, offset from the movi list's type, size
+    rate: (u32, u32),
+    finished: bool,
+}
+
+impl AviWriter {
+    pub fn create(path: &str, region: Region) -> io::Result<AviWriter> {
+        let mut writer = AviWriter {
+            out: BufWriter::new(File::create(path)?),
+            frames: 0,
+            audio_samples: 0,
+            movie_size: 0,
+            index: Vec::new(),
+            rate: frame_rate(region),
+            finished: false,
+        };
+
+        writer.write_header()?;
+
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let frame_size = (SCREEN_WIDTH * SCREEN_HEIGHT * 3) as u32;
+        let (rate, scale) = self.rate;
+        let out = &mut self.out;
+
+        out.write_all(b"RIFF")?;
+        write_u32(out, AVI_HEADER_SIZE - 8 + self.movie_size + 8 + self.index.len() as u32 * 16)?;
+        out.write_all(b"AVI LIST")?;
+        write_u32(out, 294)?;
+        out.write_all(b"hdrlavih")?;
+        write_u32(out, 56)?;
+        write_u32(out, (scale as u64 * 1_000_000 / rate as u64) as u32)?;
+        write_u32(out, (frame_size as u64 * rate as u64 / scale as u64) as u32 + SAMPLE_RATE * BYTES_PER_SAMPLE_FRAME)?;
+        write_u32(out, 0)?;
+        write_u32(out, AVIF_HASINDEX)?;
+        write_u32(out, self.frames)?;
+        write_u32(out, 0)?;
+        write_u32(out, 2)?; // streams
+        write_u32(out, frame_size)?;
+        write_u32(out, SCREEN_WIDTH as u32)?;
+        write_u32(out, SCREEN_HEIGHT as u32)?;
+        out.write_all(&[0; 16])?;
+
+        // The video stream, bottom-up BGR rows
+        out.write_all(b"LIST")?;
+        write_u32(out, 116)?;
+        out.write_all(b"strlstrh")?;
+        write_u32(out, 56)?;
+        out.write_all(b"vidsDIB ")?;
+        out.write_all(&[0; 12])?; // flags, priority, language, initial frames
+        write_u32(out, scale)?;
+        write_u32(out, rate)?;
+        write_u32(out, 0)?;
+        write_u32(out, self.frames)?;
+        write_u32(out, frame_size)?;
+        write_u32(out, 0xFFFFFFFF)?; // default quality
+        write_u32(out, 0)?;
+        write_u16(out, 0)?;
+        write_u16(out, 0)?;
+        write_u16(out, SCREEN_WIDTH as u16)?;
+        write_u16(out, SCREEN_HEIGHT as u16)?;
+        out.write_all(b"strf")?;
+        write_u32(out, 40)?;
+        write_u32(out, 40)?;
+        write_u32(out, SCREEN_WIDTH as u32)?;
+        write_u32(out, SCREEN_HEIGHT as u32)?;
+        write_u16(out, 1)?; // planes
+        write_u16(out, 24)?;
+        write_u32(out, 0)?; // uncompressed
+        write_u32(out, frame_size)?;
+        out.write_all(&[0; 16])?;
+
+        // The audio stream
+        out.write_all(b"LIST")?;
+        write_u32(out, 94)?;
+        out.write_all(b"strlstrh")?;
+        write_u32(out, 56)?;
+        out.write_all(b"auds")?;
+        out.write_all(&[0; 16])?; // handler, flags, priority, language, initial frames
+        write_u32(out, 1)?;
+        write_u32(out, SAMPLE_RATE)?;
+        write_u32(out, 0)?;
+        write_u32(out, self.audio_samples)?;
+        write_u32(out, SAMPLE_RATE * BYTES_PER_SAMPLE_FRAME / 10)?;
+        write_u32(out, 0xFFFFFFFF)?;
+        write_u32(out, BYTES_PER_SAMPLE_FRAME)?;
+        out.write_all(&[0; 8])?;
+        out.write_all(b"strf")?;
+        write_u32(out, 18)?;
+        write_u16(out, 1)?; // PCM
+        write_u16(out, CHANNELS as u16)?;
+        write_u32(out, SAMPLE_RATE)?;
+        write_u32(out, SAMPLE_RATE * BYTES_PER_SAMPLE_FRAME)?;
+        write_u16(out, BYTES_PER_SAMPLE_FRAME as u16)?;
+        write_u16(out, 16)?;
+        write_u16(out, 0)?;
+
+        out.write_all(b"LIST")?;
+        write_u32(out, 4 + self.movie_size)?;
+        out.write_all(b"movi")
+    }
+
+    fn write_chunk(&mut self, id: &'static [u8; 4], data: &[u8]) -> io::Result<()> {
+        self.out.write_all(id)?;
+        write_u32(&mut self.out, data.len() as u32)?;
+        self.out.write_all(data)?;
+
+        // Chunks are padded to an even length
+        if !data.len().is_multiple_of(2) {
+            self.out.write_all(&[0])?;
+        }
+
+        self.index.push((id, 4 + self.movie_size, data.len() as u32));
+        self.movie_size += 8 + ((data.len() as u32 + 1) & !1);
+
+        Ok(())
+    }
+}
+
+impl FrameSink for AviWriter {
+    fn write_frame(&mut self, frame: u64, pixels: &[u8], samples: &[i16]) -> io::Result<()> {
+        let mut bitmap = Vec::with_capacity(pixels.len());
+
+        for row in pixels.chunks(SCREEN_WIDTH * 3).rev() {
+            for pixel in row.chunks(3) {
+                bitmap.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            }
+        }
+
+        let audio: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+
+        // Keeping the whole file under the limit keeps every size in it well within 32 bits
+        let chunk_size = |data: &[u8]| 8 + ((data.len() as u64 + 1) & !1);
+        let size = AVI_HEADER_SIZE as u64 + self.movie_size as u64 + chunk_size(&bitmap) + chunk_size(&audio)
+            + 8 + (self.index.len() as u64 + 2) * 16;
+
+        if size > AVI_MAX_SIZE {
+            return Err(io::Error::other("The AVI file would pass its 1 GiB limit"));
+        }
+
+        self.write_chunk(b"00db", &bitmap)?;
+        self.write_chunk(b"01wb", &audio)?;
+        self.frames += 1;
+        self.audio_samples += (samples.len() / CHANNELS) as u32;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+
+        self.finished = true;
+
+        self.out.write_all(b"idx1")?;
+        write_u32(&mut self.out, self.index.len() as u32 * 16)?;
+
+        for &(id, offset, size) in self.index.iter() {
+            self.out.write_all(id)?;
+            write_u32(&mut self.out, AVIIF_KEYFRAME)?;
+            write_u32(&mut self.out, offset)?;
+            write_u32(&mut self.out, size)?;
+        }
+
+        self.out.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.out.flush()
+    }
+}
+
+impl Drop for AviWriter {
+    fn drop(&mut self) {
+        let _res = self.finish();
+    }
+}
+
+/// The exact frame rate as a fraction, master clocks per second over master clocks per frame
+pub fn frame_rate(region: Region) -> (u32, u32) {
+    (region.master_clock() as u32, region.lines_per_frame() * MASTER_CYCLES_PER_LINE)
+}
+
+// Full range BT.601, as JPEG uses
+fn yuv(pixel: &[u8]) -> (u8, u8, u8) {
+    let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+
+    let y = (77 * r + 150 * g + 29 * b + 128) >> 8;
+    let u = ((-43 * r - 85 * g + 128 * b + 128) >> 8) + 128;
+    let v = ((128 * r - 107 * g - 21 * b + 128) >> 8) + 128;
+
+    (y.clamp(0, 255) as u8, u.clamp(0, 255) as u8, v.clamp(0, 255) as u8)
+}
+
+fn write_u16<W: Write>(out: &mut W, data: u16) -> io::Result<()> {
+    out.write_all(&data.to_le_bytes())
+}
+
+fn write_u32<W: Write>(out: &mut W, data: u32) -> io::Result<()> {
+    out.write_all(&data.to_le_bytes())
+}
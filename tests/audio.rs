@@ -0,0 +1,104 @@
+//! Resampling keeps the rate it's asked for across calls, and WAV files describe what's in
+//! them, so audio from two revisions can be compared.
+
+extern crate rustsnes;
+
+use std::env;
+use std::fs;
+
+use rustsnes::apu::{CHANNELS, SAMPLE_RATE};
+use rustsnes::audio::{AudioOutput, AudioSink, Resampler, WavWriter};
+
+// A second of a ramp on the left and its negation on the right, as the APU would hand it over
+fn one_second() -> Vec<i16> {
+    (0..SAMPLE_RATE as usize).flat_map(|frame| {
+        let sample = (frame % 1000) as i16;
+        vec![sample, -sample]
+    }).collect()
+}
+
+fn resampled_frames(to: u32, chunk_frames: usize) -> usize {
+    let mut resampler = Resampler::new(SAMPLE_RATE, to);
+
+    one_second().chunks(chunk_frames * CHANNELS).map(|chunk| resampler.process(chunk).len() / CHANNELS).sum()
+}
+
+#[test]
+fn a_second_resamples_to_a_second() {
+    assert_eq!(resampled_frames(44100, SAMPLE_RATE as usize), 44100);
+    assert_eq!(resampled_frames(48000, SAMPLE_RATE as usize), 48000);
+}
+
+#[test]
+fn resampling_in_pieces_loses_nothing() {
+    // About what the APU makes in an NTSC frame, which doesn't divide the second evenly
+    assert_eq!(resampled_frames(44100, 533), 44100);
+    assert_eq!(resampled_frames(48000, 533), 48000);
+}
+
+#[test]
+fn resampling_interpolates_between_samples() {
+    let mut resampler = Resampler::new(SAMPLE_RATE, 64000);
+    let output = resampler.process(&[100, -100, 200, -200]);
+
+    // Each output frame is halfway on from the last, starting from the silence before the input
+    assert_eq!(output, vec![0, 0, 50, -50, 100, -100, 150, -150]);
+}
+
+#[test]
+fn wav_header_describes_the_data() {
+    let path = env::temp_dir().join(format!("rustsnes-audio-{}.wav", std::process::id()));
+    let path = path.to_str().unwrap();
+    let second = one_second();
+
+    {
+        let writer = WavWriter::create(path, 48000).unwrap();
+        let mut output = AudioOutput::new(Box::new(writer), 48000);
+
+        for chunk in second.chunks(533 * CHANNELS) {
+            output.write(chunk).unwrap();
+        }
+
+        output.finish().unwrap();
+    }
+
+    let wav = fs::read(path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    let u16_at = |offset: usize| u16::from_le_bytes([wav[offset], wav[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes([wav[offset], wav[offset + 1], wav[offset + 2], wav[offset + 3]]);
+    let data_size = 48000 * CHANNELS as u32 * 2;
+
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32_at(4), 36 + data_size);
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32_at(16), 16);
+    assert_eq!(u16_at(20), 1); // PCM
+    assert_eq!(u16_at(22), CHANNELS as u16);
+    assert_eq!(u32_at(24), 48000);
+    assert_eq!(u32_at(28), 48000 * 4); // bytes per second
+    assert_eq!(u16_at(32), 4); // bytes per frame
+    assert_eq!(u16_at(34), 16);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32_at(40), data_size);
+    assert_eq!(wav.len(), 44 + data_size as usize);
+}
+
+#[test]
+fn wav_sizes_are_written_when_the_writer_is_dropped() {
+    let path = env::temp_dir().join(format!("rustsnes-audio-drop-{}.wav", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    {
+        let mut writer = WavWriter::create(path, SAMPLE_RATE).unwrap();
+        writer.write_samples(&[1, 2, 3, 4]).unwrap();
+    }
+
+    let wav = fs::read(path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(wav.len(), 52);
+    assert_eq!(&wav[4..8], &44u32.to_le_bytes());
+    assert_eq!(&wav[40..44], &8u32.to_le_bytes());
+    assert_eq!(&wav[44..], &[1, 0, 2, 0, 3, 0, 4, 0]);
+}
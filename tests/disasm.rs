@@ -0,0 +1,80 @@
+//! Checks that the disassembler follows the register widths through REP, SEP and XCE, which
+//! decide how long every immediate operand after them is.
+
+extern crate rustsnes;
+
+#[allow(dead_code)]
+mod homebrew;
+
+use rustsnes::disasm::Disassembler;
+use rustsnes::memory::Memory;
+
+// Disassembles code starting at $00:8000 in emulation mode, as the console starts, and returns
+// each instruction as its text and length
+fn listing(code: &[u8]) -> Vec<(String, usize)> {
+    let memory = Memory::new(homebrew::lorom("DISASM", code)).unwrap();
+
+    Disassembler::new(&memory, 0x8000, true, true, true)
+        .take_while(|instruction| instruction.addr < 0x8000 + code.len())
+        .map(|instruction| (instruction.text(None), instruction.length))
+        .collect()
+}
+
+fn texts(code: &[u8]) -> Vec<String> {
+    listing(code).into_iter().map(|(text, _)| text).collect()
+}
+
+#[test]
+fn rep_and_sep_set_immediate_sizes() {
+    assert_eq!(texts(&[
+        0x18,             // clc
+        0xFB,             // xce
+        0xC2, 0x30,       // rep #$30
+        0xA9, 0x34, 0x12, // lda #$1234
+        0xA2, 0x78, 0x56, // ldx #$5678
+        0xE2, 0x20,       // sep #$20
+        0xA9, 0x12,       // lda #$12
+        0xA0, 0x34, 0x12, // ldy #$1234
+    ]), vec!["clc", "xce", "rep #$30", "lda #$1234", "ldx #$5678", "sep #$20", "lda #$12", "ldy #$1234"]);
+}
+
+#[test]
+fn rep_cannot_widen_registers_in_emulation_mode() {
+    assert_eq!(texts(&[
+        0xC2, 0x30,       // rep #$30
+        0xA9, 0x12,       // lda #$12
+        0xA2, 0x34,       // ldx #$34
+    ]), vec!["rep #$30", "lda #$12", "ldx #$34"]);
+}
+
+#[test]
+fn xce_follows_carry_from_sep_and_rep() {
+    assert_eq!(texts(&[
+        0xC2, 0x31,       // rep #$31        clears carry, but can't widen in emulation mode
+        0xFB,             // xce             native
+        0xC2, 0x30,       // rep #$30
+        0xA9, 0x34, 0x12, // lda #$1234
+        0xE2, 0x01,       // sep #$01
+        0xFB,             // xce             emulation, which forces 8 bits
+        0xA9, 0x12,       // lda #$12
+        0xA2, 0x34,       // ldx #$34
+        0xC2, 0x31,       // rep #$31
+        0xFB,             // xce             native, still 8 bits until a REP
+        0xA9, 0x12,       // lda #$12
+        0xC2, 0x20,       // rep #$20
+        0xA9, 0x34, 0x12, // lda #$1234
+        0xA2, 0x12,       // ldx #$12
+    ]), vec!["rep #$31", "xce", "rep #$30", "lda #$1234", "sep #$01", "xce", "lda #$12", "ldx #$34",
+             "rep #$31", "xce", "lda #$12", "rep #$20", "lda #$1234", "ldx #$12"]);
+}
+
+#[test]
+fn xce_with_unknown_carry_leaves_the_mode_alone() {
+    let listing = listing(&[
+        0xFB,             // xce             carry unknown, so still emulation as far as we know
+        0xC2, 0x30,       // rep #$30
+        0xA9, 0x12,       // lda #$12
+    ]);
+
+    assert_eq!(listing.iter().map(|&(_, length)| length).collect::<Vec<_>>(), vec![1, 2, 2]);
+}
@@ -0,0 +1,42 @@
+//! Comparing hash logs finds the first frame two runs differ on, and refuses to call logs
+//! with no frames in common a match.
+
+extern crate rustsnes;
+
+use rustsnes::hashlog::{Comparison, HashLog};
+
+fn log(interval: u64, hashes: &[(u64, u64)]) -> HashLog {
+    let mut text = format!("rustsnes-hashes 1\nrom-crc32 1a2b3c4d\ninterval {}\n", interval);
+
+    for &(frame, hash) in hashes.iter() {
+        text.push_str(&format!("{} {:x}\n", frame, hash));
+    }
+
+    HashLog::parse(&text).unwrap()
+}
+
+#[test]
+fn matching_runs_match_over_their_common_frames() {
+    let first = log(60, &[(0, 1), (60, 2), (120, 3), (180, 4)]);
+    let second = log(120, &[(0, 1), (120, 3), (240, 5)]);
+
+    assert_eq!(first.compare(&second), Comparison::Match { compared: 2 });
+    assert_eq!(second.compare(&first), Comparison::Match { compared: 2 });
+}
+
+#[test]
+fn the_first_difference_is_reported() {
+    let first = log(60, &[(0, 1), (60, 2), (120, 3)]);
+    let second = log(60, &[(0, 1), (60, 7), (120, 8)]);
+
+    assert_eq!(first.compare(&second), Comparison::Diverged { frame: 60, first: 2, second: 7 });
+}
+
+#[test]
+fn logs_without_common_frames_are_not_a_match() {
+    let first = log(60, &[(0, 1), (60, 2)]);
+    let second = log(60, &[(30, 1), (90, 2)]);
+
+    assert_eq!(first.compare(&second), Comparison::Disjoint);
+    assert_eq!(first.compare(&log(60, &[])), Comparison::Disjoint);
+}
@@ -0,0 +1,101 @@
+//! Small test programs, assembled by hand so the tests don't need an assembler or ROMs that
+//! can't be redistributed. Each is a 32 KiB LoROM image with its code at $00:8000 and every
+//! vector pointing there.
+
+const ROM_SIZE: usize = 0x8000;
+const HEADER: usize = 0x7FC0;
+const VECTORS: usize = 0x7FE0;
+
+/// Looks a program up by the name cases files use
+pub fn build(name: &str) -> Option<Vec<u8>> {
+    match name {
+        "forced-blank" => Some(forced_blank()),
+        "backdrop" => Some(backdrop()),
+        "joypad-backdrop" => Some(joypad_backdrop()),
+        _ => None,
+    }
+}
+
+/// Turns the display off and spins
+pub fn forced_blank() -> Vec<u8> {
+    lorom("FORCED BLANK", &[
+        0x78,             // 8000 sei
+        0x18,             // 8001 clc
+        0xFB,             // 8002 xce
+        0xE2, 0x30,       // 8003 sep #$30
+        0xA9, 0x80,       // 8005 lda #$80
+        0x8D, 0x00, 0x21, // 8007 sta $2100      force blank
+        0x4C, 0x0A, 0x80, // 800a jmp $800a
+    ])
+}
+
+/// Sets the backdrop color to pure red and turns the screen on at full brightness
+pub fn backdrop() -> Vec<u8> {
+    lorom("BACKDROP", &[
+        0x78,             // 8000 sei
+        0x18,             // 8001 clc
+        0xFB,             // 8002 xce
+        0xE2, 0x30,       // 8003 sep #$30
+        0x9C, 0x21, 0x21, // 8005 stz $2121      CGRAM address 0
+        0xA9, 0x1F,       // 8008 lda #$1f
+        0x8D, 0x22, 0x21, // 800a sta $2122      red, low byte
+        0x9C, 0x22, 0x21, // 800d stz $2122
+        0xA9, 0x0F,       // 8010 lda #$0f
+        0x8D, 0x00, 0x21, // 8012 sta $2100      full brightness
+        0x4C, 0x15, 0x80, // 8015 jmp $8015
+    ])
+}
+
+/// Turns on the auto-joypad read and keeps copying controller 1's high byte (B, Y, Select,
+/// Start and the d-pad) into the red and low green bits of a blue backdrop, so the screen
+/// shows what's held
+pub fn joypad_backdrop() -> Vec<u8> {
+    lorom("JOYPAD BACKDROP", &[
+        0x78,             // 8000 sei
+        0x18,             // 8001 clc
+        0xFB,             // 8002 xce
+        0xE2, 0x30,       // 8003 sep #$30
+        0xA9, 0x01,       // 8005 lda #$01
+        0x8D, 0x00, 0x42, // 8007 sta $4200      auto-joypad read
+        0xA9, 0x0F,       // 800a lda #$0f
+        0x8D, 0x00, 0x21, // 800c sta $2100
+        0x9C, 0x21, 0x21, // 800f stz $2121
+        0xAD, 0x19, 0x42, // 8012 lda $4219
+        0x8D, 0x22, 0x21, // 8015 sta $2122
+        0xA9, 0x7C,       // 8018 lda #$7c
+        0x8D, 0x22, 0x21, // 801a sta $2122      full blue
+        0x4C, 0x0F, 0x80, // 801d jmp $800f
+    ])
+}
+
+/// Puts code at $00:8000 of a cartridge with a valid header
+pub fn lorom(title: &str, code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0xFF; ROM_SIZE];
+    rom[..code.len()].copy_from_slice(code);
+
+    let mut name = [b' '; 21];
+    name[..title.len()].copy_from_slice(title.as_bytes());
+    rom[HEADER..HEADER + 21].copy_from_slice(&name);
+
+    rom[HEADER + 0x15] = 0x20; // LoROM, SlowROM
+    rom[HEADER + 0x16] = 0x00; // ROM only
+    rom[HEADER + 0x17] = 0x05; // 32 KiB
+    rom[HEADER + 0x18] = 0x00; // no SRAM
+    rom[HEADER + 0x19] = 0x01; // North America
+    rom[HEADER + 0x1A] = 0x00;
+    rom[HEADER + 0x1B] = 0x00; // version
+
+    for vector in (VECTORS..ROM_SIZE).step_by(2) {
+        rom[vector] = 0x00;
+        rom[vector + 1] = 0x80;
+    }
+
+    // The checksum and its complement always add up to $1fe between them, so set them to a
+    // pair that does before summing
+    rom[HEADER + 0x1C..HEADER + 0x20].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00]);
+    let checksum = rom.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    rom[HEADER + 0x1C..HEADER + 0x20].copy_from_slice(&[!checksum as u8, (!checksum >> 8) as u8,
+                                                         checksum as u8, (checksum >> 8) as u8]);
+
+    rom
+}
@@ -0,0 +1,72 @@
+//! The CPU's view of memory through the cartridge's memory map.
+
+extern crate rustsnes;
+
+#[allow(dead_code)]
+mod homebrew;
+
+use rustsnes::SNES;
+
+fn run(snes: &mut SNES, instructions: usize) {
+    for _ in 0..instructions {
+        snes.step_instruction().unwrap();
+    }
+}
+
+#[test]
+fn long_reads_wrap_from_bank_ff_to_bank_00() {
+    let rom = homebrew::lorom("LONG WRAP", &[
+        0x18,                   // 8000 clc
+        0xFB,                   // 8001 xce
+        0xC2, 0x20,             // 8002 rep #$20
+        0xA9, 0x34, 0x12,       // 8004 lda #$1234
+        0x8D, 0x00, 0x00,       // 8007 sta $0000
+        0xAF, 0xFF, 0xFF, 0xFF, // 800a lda $ffffff   $ff:ffff, then $00:0000
+        0x4C, 0x0E, 0x80,       // 800e jmp $800e
+    ]);
+    let mut snes = SNES::load_rom(rom).unwrap();
+
+    run(&mut snes, 6);
+
+    // The low byte is the last in the ROM, the top half of a vector; the high byte is from WRAM
+    assert_eq!(snes.cpu().accumulator, 0x3480);
+    assert_eq!(snes.memory().peek_byte(0x1000000), snes.memory().peek_byte(0x000000));
+}
+
+#[test]
+fn brk_jumps_through_its_vector() {
+    let mut code = vec![
+        0x18,             // 8000 clc
+        0xFB,             // 8001 xce
+        0x00, 0x00,       // 8002 brk
+    ];
+    code.resize(0x20, 0xEA);
+    code.extend(&[0x4C, 0x20, 0x80]); // 8020 jmp $8020
+
+    let mut rom = homebrew::lorom("BRK VECTOR", &code);
+    rom[0x7FE6..0x7FE8].copy_from_slice(&[0x20, 0x80]); // native BRK, at $00:ffe6
+    let mut snes = SNES::load_rom(rom).unwrap();
+    snes.cpu_mut().stack_pointer = 0x1FF;
+
+    run(&mut snes, 3);
+
+    assert_eq!(snes.cpu().program_bank, 0);
+    assert_eq!(snes.cpu().program_counter, 0x8020);
+}
+
+#[test]
+fn brk_in_emulation_mode_jumps_through_the_irq_vector() {
+    let mut code = vec![0x00, 0x00]; // 8000 brk
+    code.resize(0x20, 0xEA);
+    code.extend(&[0x4C, 0x20, 0x80]); // 8020 jmp $8020
+
+    let mut rom = homebrew::lorom("BRK VECTOR", &code);
+    rom[0x7FFE..0x8000].copy_from_slice(&[0x20, 0x80]); // IRQ and BRK, at $00:fffe
+    let mut snes = SNES::load_rom(rom).unwrap();
+    snes.cpu_mut().stack_pointer = 0x1FF;
+
+    run(&mut snes, 1);
+
+    assert_eq!(snes.cpu().program_bank, 0);
+    assert_eq!(snes.cpu().program_counter, 0x8020);
+}
@@ -0,0 +1,108 @@
+//! Records a movie of one of the homebrew programs and plays it back, checking that the
+//! playback stays in sync and that a wrong hash in the movie is reported, and turns away movies
+//! that are malformed.
+
+extern crate rustsnes;
+
+#[allow(dead_code)]
+mod homebrew;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::rc::Rc;
+
+use rustsnes::SNES;
+use rustsnes::movie::{Movie, MovieEvent, MoviePlayer, MovieRecorder, StartState};
+use rustsnes::script::InputScript;
+
+const FRAMES: u64 = 125;
+
+fn console() -> SNES {
+    SNES::load_rom(homebrew::joypad_backdrop()).unwrap()
+}
+
+// Records FRAMES frames of input and returns the movie's text
+fn record(name: &str) -> String {
+    let path = env::temp_dir().join(format!("rustsnes-{}-{}.movie", name, std::process::id()));
+    let path = path.to_str().unwrap();
+    let ports = ["pad".to_string(), "pad".to_string()];
+
+    {
+        let mut snes = console();
+        let rom_crc32 = snes.memory().rom.checksum();
+
+        snes.set_input_script(InputScript::parse_text("10 1 right\n50 1 b y\n90 1").unwrap());
+        snes.set_recorder(MovieRecorder::create(path, rom_crc32, &ports, &StartState::PowerOn).unwrap());
+
+        while snes.frame() < FRAMES {
+            snes.run_frame().unwrap();
+        }
+    }
+
+    let text = fs::read_to_string(path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    text
+}
+
+// Plays a movie a few frames past its end and returns what the player reported
+fn play(text: &str) -> Vec<MovieEvent> {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let listener_events = events.clone();
+
+    let mut snes = console();
+    snes.set_player(MoviePlayer::new(Movie::parse(text).unwrap()));
+    snes.set_movie_listener(Box::new(move |event| listener_events.borrow_mut().push(event)));
+
+    while snes.frame() < FRAMES + 5 {
+        snes.run_frame().unwrap();
+    }
+
+    let events = events.borrow().clone();
+    events
+}
+
+// Changes the hash recorded for a frame
+fn tamper(text: &str, frame: u64) -> String {
+    let prefix = format!("check {} ", frame);
+
+    text.lines().map(|line| match line.strip_prefix(&prefix) {
+        Some(hash) => format!("{}{:016x}\n", prefix, u64::from_str_radix(hash, 16).unwrap() ^ 1),
+        None => format!("{}\n", line),
+    }).collect()
+}
+
+#[test]
+fn a_recording_plays_back_in_sync() {
+    let text = record("sync");
+
+    assert!(text.contains("check 60 "), "no check for frame 60 in:\n{}", text);
+    assert_eq!(play(&text), vec![MovieEvent::Finished { frame: FRAMES }]);
+}
+
+#[test]
+fn a_wrong_hash_is_a_desync() {
+    let text = tamper(&record("desync"), 60);
+    let events = play(&text);
+
+    assert!(matches!(events[0], MovieEvent::Desynced { frame: 60, .. }), "{:?}", events);
+    assert_eq!(events[1..], [MovieEvent::Finished { frame: FRAMES }]);
+}
+
+#[test]
+fn version_1_checks_are_ignored() {
+    let text = tamper(&record("version-1"), 60).replacen("rustsnes-movie 2", "rustsnes-movie 1", 1);
+
+    assert_eq!(play(&text), vec![MovieEvent::Finished { frame: FRAMES }]);
+}
+
+#[test]
+fn bad_sram_data_is_an_error() {
+    let header = |sram: &str| format!("rustsnes-movie 2\nrom-crc32 00000000\nports pad pad\nstart sram {}\n", sram);
+
+    assert!(Movie::parse(&header("00ff7e")).is_ok());
+    assert_eq!(Movie::parse(&header("a\u{e9}0")).err().unwrap(), "Bad SRAM data");
+    assert_eq!(Movie::parse(&header("+f")).err().unwrap(), "Bad SRAM data");
+    assert_eq!(Movie::parse(&header("0ff")).err().unwrap(), "Odd length SRAM data");
+}
@@ -0,0 +1,238 @@
+//! Applies small hand-built IPS, UPS and BPS patches, including ones with bad checksums and
+//! numbers too big for the parser to hold.
+
+extern crate rustsnes;
+
+use rustsnes::patch::{self, PatchFormat};
+
+const SOURCE: &[u8] = b"The quick brown fox";
+
+#[test]
+fn formats_are_detected() {
+    assert_eq!(PatchFormat::detect(b"PATCHEOF"), Some(PatchFormat::IPS));
+    assert_eq!(PatchFormat::detect(b"UPS1"), Some(PatchFormat::UPS));
+    assert_eq!(PatchFormat::detect(b"BPS1"), Some(PatchFormat::BPS));
+    assert_eq!(PatchFormat::detect(b"NOPE"), None);
+    assert!(patch::apply(SOURCE, b"NOPE").is_err());
+}
+
+#[test]
+fn ips_records_and_runs_apply() {
+    let mut ips = b"PATCH".to_vec();
+    ips.extend(&[0x00, 0x00, 0x04, 0x00, 0x04]);
+    ips.extend(b"slow");
+    // A run of three dots past the end, which grows the ROM
+    ips.extend(&[0x00, 0x00, 0x14, 0x00, 0x00, 0x00, 0x03, b'.']);
+    ips.extend(b"EOF");
+
+    assert_eq!(patch::apply(SOURCE, &ips).unwrap(), b"The slowk brown fox\0...".to_vec());
+}
+
+#[test]
+fn ips_truncates_to_the_size_after_eof() {
+    let mut ips = b"PATCHEOF".to_vec();
+    ips.extend(&[0x00, 0x00, 0x09]);
+
+    assert_eq!(patch::apply(SOURCE, &ips).unwrap(), b"The quick".to_vec());
+}
+
+#[test]
+fn truncated_ips_is_an_error() {
+    let mut ips = b"PATCH".to_vec();
+    ips.extend(&[0x00, 0x00, 0x04, 0x00, 0x04]);
+    ips.extend(b"sl");
+
+    assert!(patch::apply(SOURCE, &ips).is_err());
+}
+
+#[test]
+fn ups_applies() {
+    let target = b"The quick brown cat!".to_vec();
+    let ups = ups(SOURCE, &target);
+
+    assert_eq!(patch::apply(SOURCE, &ups).unwrap(), target);
+}
+
+#[test]
+fn ups_checks_the_source() {
+    let ups = ups(SOURCE, b"The quick brown cat!");
+
+    assert_eq!(patch::apply(b"The quick brown dog", &ups).unwrap_err(), "The patch is for a different ROM");
+}
+
+#[test]
+fn ups_checks_itself() {
+    let mut ups = ups(SOURCE, b"The quick brown cat!");
+    ups[8] ^= 1;
+
+    assert_eq!(patch::apply(SOURCE, &ups).unwrap_err(), "The patch is corrupt");
+}
+
+#[test]
+fn ups_checks_the_target() {
+    let mut body = b"UPS1".to_vec();
+    body.extend(number(SOURCE.len()));
+    body.extend(number(SOURCE.len()));
+    body.extend(&[0x80, 0x01, 0x00]);
+
+    let ups = with_footer(body, crc32(SOURCE), crc32(SOURCE));
+
+    assert_eq!(patch::apply(SOURCE, &ups).unwrap_err(), "The patched ROM doesn't match the checksum in the patch");
+}
+
+#[test]
+fn bps_applies_every_action() {
+    let target = b"The quick brown fox jumps, The quick quick!!!!".to_vec();
+    let mut body = b"BPS1".to_vec();
+    body.extend(number(SOURCE.len()));
+    body.extend(number(target.len()));
+    body.extend(number(0));
+
+    // SourceRead "The quick brown fox"
+    body.extend(number((SOURCE.len() - 1) << 2));
+    // TargetRead " jumps, "
+    body.extend(number(((8 - 1) << 2) | 1));
+    body.extend(b" jumps, ");
+    // SourceCopy "The quick " from the start of the source
+    body.extend(number(((10 - 1) << 2) | 2));
+    body.extend(number(0));
+    // SourceCopy "quick" from back where the copy above read "quick"
+    body.extend(number(((5 - 1) << 2) | 2));
+    body.extend(number((6 << 1) | 1));
+    // TargetRead "!", then TargetCopy it three more times, overlapping itself
+    body.extend(number(1));
+    body.extend(b"!");
+    body.extend(number(((3 - 1) << 2) | 3));
+    body.extend(number((target.len() - 4) << 1));
+
+    let bps = with_footer(body, crc32(SOURCE), crc32(&target));
+
+    assert_eq!(patch::apply(SOURCE, &bps).unwrap(), target);
+}
+
+#[test]
+fn bps_checks_the_source_and_target() {
+    let target = b"The quick".to_vec();
+    let mut body = b"BPS1".to_vec();
+    body.extend(number(SOURCE.len()));
+    body.extend(number(target.len()));
+    body.extend(number(0));
+    body.extend(number((target.len() - 1) << 2));
+
+    let bps = with_footer(body.clone(), crc32(SOURCE), crc32(&target));
+    assert_eq!(patch::apply(SOURCE, &bps).unwrap(), target);
+    assert_eq!(patch::apply(b"The quick brown dog", &bps).unwrap_err(), "The patch is for a different ROM");
+
+    let bps = with_footer(body, crc32(SOURCE), crc32(&target) ^ 1);
+    assert_eq!(patch::apply(SOURCE, &bps).unwrap_err(), "The patched ROM doesn't match the checksum in the patch");
+}
+
+#[test]
+fn bps_checks_itself() {
+    let mut body = b"BPS1".to_vec();
+    body.extend(number(SOURCE.len()));
+    body.extend(number(SOURCE.len()));
+    body.extend(number(0));
+
+    let mut bps = with_footer(body, crc32(SOURCE), crc32(SOURCE));
+    let last = bps.len() - 1;
+    bps[last] ^= 1;
+
+    assert_eq!(patch::apply(SOURCE, &bps).unwrap_err(), "The patch is corrupt");
+}
+
+#[test]
+fn bps_cannot_write_past_the_target() {
+    let mut body = b"BPS1".to_vec();
+    body.extend(number(SOURCE.len()));
+    body.extend(number(1));
+    body.extend(number(0));
+    body.extend(number(((2 - 1) << 2) | 3));
+    body.extend(number(0));
+
+    let bps = with_footer(body, crc32(SOURCE), 0);
+
+    assert!(patch::apply(SOURCE, &bps).is_err());
+}
+
+#[test]
+fn oversized_numbers_are_errors() {
+    // Twelve continuation bytes is more than 64 bits' worth
+    let mut body = b"UPS1".to_vec();
+    body.extend(&[0x7F; 12]);
+    body.push(0xFF);
+
+    let ups = with_footer(body, crc32(SOURCE), 0);
+    assert!(patch::apply(SOURCE, &ups).is_err());
+
+    let mut body = b"BPS1".to_vec();
+    body.extend(number(SOURCE.len()));
+    body.extend(number(usize::MAX));
+
+    let bps = with_footer(body, crc32(SOURCE), 0);
+    assert!(patch::apply(SOURCE, &bps).is_err());
+}
+
+// A UPS patch with a hunk for every run of differing bytes
+fn ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut body = b"UPS1".to_vec();
+    body.extend(number(source.len()));
+    body.extend(number(target.len()));
+
+    let xor = |offset: usize| source.get(offset).cloned().unwrap_or(0) ^ target[offset];
+    let mut offset = 0;
+    let mut last = 0;
+
+    while offset < target.len() {
+        if xor(offset) == 0 {
+            offset += 1;
+            continue;
+        }
+
+        body.extend(number(offset - last));
+
+        while offset < target.len() && xor(offset) != 0 {
+            body.push(xor(offset));
+            offset += 1;
+        }
+
+        body.push(0x00);
+        offset += 1;
+        last = offset;
+    }
+
+    with_footer(body, crc32(source), crc32(target))
+}
+
+fn with_footer(mut body: Vec<u8>, source_crc: u32, target_crc: u32) -> Vec<u8> {
+    body.extend(&source_crc.to_le_bytes());
+    body.extend(&target_crc.to_le_bytes());
+
+    let patch_crc = crc32(&body);
+    body.extend(&patch_crc.to_le_bytes());
+
+    body
+}
+
+fn number(mut value: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let low = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(0x80 | low);
+            return bytes;
+        }
+
+        bytes.push(low);
+        value -= 1;
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 })
+    })
+}
@@ -0,0 +1,159 @@
+//! The rewind buffer gives back exactly the states pushed into it, newest first, and input
+//! scripts can rewind the console as it runs.
+
+extern crate rustsnes;
+
+#[allow(dead_code)]
+mod homebrew;
+
+use rustsnes::SNES;
+use rustsnes::rewind::RewindBuffer;
+use rustsnes::script::InputScript;
+
+const STATE_SIZE: usize = 40000;
+
+// xorshift, so the states are the same every run
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn below(&mut self, limit: usize) -> usize {
+        self.next() as usize % limit
+    }
+}
+
+// Each state changes the last in a few places: single bytes, runs, bytes a few apart that
+// should share a run, and now and then the first and last bytes
+fn states(count: usize) -> Vec<Vec<u8>> {
+    let mut random = Random(0x1234_5678);
+    let mut state: Vec<u8> = (0..STATE_SIZE).map(|_| random.next() as u8).collect();
+    let mut states = Vec::new();
+
+    for index in 0..count {
+        for _ in 0..random.below(20) {
+            let start = random.below(STATE_SIZE);
+            let length = (random.below(300) + 1).min(STATE_SIZE - start);
+
+            for byte in state[start..start + length].iter_mut().step_by(random.below(12) + 1) {
+                *byte = random.next() as u8;
+            }
+        }
+
+        if index % 7 == 0 {
+            state[0] ^= 0xFF;
+            state[STATE_SIZE - 1] ^= 0xFF;
+        }
+
+        states.push(state.clone());
+    }
+
+    states
+}
+
+#[test]
+fn states_come_back_unchanged() {
+    let states = states(150);
+    let mut buffer = RewindBuffer::new(states.len());
+
+    for state in states.iter() {
+        buffer.push(state.clone());
+    }
+
+    assert_eq!(buffer.len(), states.len());
+
+    for (index, state) in states.iter().enumerate().rev() {
+        assert!(buffer.pop().as_ref() == Some(state), "state {} changed", index);
+    }
+
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.pop(), None);
+}
+
+#[test]
+fn unchanged_and_resized_states_round_trip() {
+    let mut buffer = RewindBuffer::new(10);
+    let first = vec![0x55; 1000];
+    let mut longer = vec![0x55; 1200];
+    longer[1100] = 1;
+
+    buffer.push(first.clone());
+    buffer.push(first.clone());
+    buffer.push(longer.clone());
+    buffer.push(longer.clone());
+    buffer.push(first.clone());
+
+    assert_eq!(buffer.pop(), Some(first.clone()));
+    assert_eq!(buffer.pop(), Some(longer.clone()));
+    assert_eq!(buffer.pop(), Some(longer));
+    assert_eq!(buffer.pop(), Some(first.clone()));
+    assert_eq!(buffer.pop(), Some(first));
+    assert_eq!(buffer.pop(), None);
+}
+
+#[test]
+fn pushing_and_popping_can_interleave() {
+    let states = states(200);
+    let mut buffer = RewindBuffer::new(states.len());
+    let mut pushed = Vec::new();
+
+    // Two steps forward and one back, the way a rewinding run goes
+    for state in states.iter() {
+        buffer.push(state.clone());
+        pushed.push(state.clone());
+
+        if pushed.len() % 3 == 0 {
+            assert!(buffer.pop() == pushed.pop());
+        }
+    }
+
+    while let Some(state) = pushed.pop() {
+        assert!(buffer.pop() == Some(state));
+    }
+
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn a_full_buffer_keeps_the_newest_states() {
+    let states = states(300);
+    let mut buffer = RewindBuffer::new(100);
+
+    for state in states.iter() {
+        buffer.push(state.clone());
+    }
+
+    // Whole groups are dropped, so there can be more than asked for, but never fewer
+    assert!(buffer.len() >= 100 && buffer.len() < 300, "{} frames kept", buffer.len());
+
+    for state in states.iter().rev().take(buffer.len()) {
+        assert!(buffer.pop().as_ref() == Some(state));
+    }
+
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn an_input_script_rewinds_the_console() {
+    let mut snes = SNES::load_rom(homebrew::joypad_backdrop()).unwrap();
+    snes.set_rewind(60);
+    snes.set_input_script(InputScript::parse_text("10 rewind 5").unwrap());
+
+    let mut hashes = vec![snes.state_hash()];
+
+    while snes.frame() < 10 {
+        snes.run_frame().unwrap();
+        hashes.push(snes.state_hash());
+    }
+
+    // Frame 10 goes back to the start of frame 5 and plays it again
+    snes.run_frame().unwrap();
+
+    assert_eq!(snes.frame(), 6);
+    assert_eq!(snes.state_hash(), hashes[6]);
+}
@@ -0,0 +1,70 @@
+//! Save states restore the machine exactly, and a state that can't be read leaves it alone.
+
+extern crate rustsnes;
+
+#[allow(dead_code)]
+mod homebrew;
+
+use rustsnes::SNES;
+
+fn console() -> SNES {
+    SNES::load_rom(homebrew::joypad_backdrop()).unwrap()
+}
+
+// Rewrites a state with one section's body cut short
+fn truncate_section(state: &[u8], tag: &[u8], length: usize) -> Vec<u8> {
+    let mut output = state[..8].to_vec();
+    let mut offset = 8;
+
+    while offset < state.len() {
+        let size = state[offset + 4..offset + 8].iter().rev().fold(0, |size, &byte| (size << 8) | byte as usize);
+        let body = &state[offset + 8..offset + 8 + size];
+        let body = if &state[offset..offset + 4] == tag { &body[..length] } else { body };
+
+        output.extend_from_slice(&state[offset..offset + 4]);
+        output.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        output.extend_from_slice(body);
+        offset += 8 + size;
+    }
+
+    output
+}
+
+#[test]
+fn loading_a_state_restores_the_machine() {
+    let mut snes = console();
+    snes.run_frame().unwrap();
+    let state = snes.save_state();
+    let hash = snes.state_hash();
+
+    for _ in 0..3 {
+        snes.run_frame().unwrap();
+    }
+
+    assert_ne!(snes.state_hash(), hash);
+    snes.load_state(&state).unwrap();
+    assert_eq!(snes.state_hash(), hash);
+}
+
+#[test]
+fn a_bad_state_leaves_the_machine_alone() {
+    let mut snes = console();
+    snes.run_frame().unwrap();
+    let state = snes.save_state();
+
+    for _ in 0..3 {
+        snes.run_frame().unwrap();
+    }
+
+    let hash = snes.state_hash();
+
+    // Each comes up short after other sections have read fine
+    for tag in [&b"MEM "[..], &b"SYS "[..], &b"CPU "[..]].iter() {
+        assert!(snes.load_state(&truncate_section(&state, tag, 4)).is_err());
+        assert_eq!(snes.state_hash(), hash, "after a short {} section", String::from_utf8_lossy(tag));
+    }
+
+    assert!(snes.load_state(&state[..state.len() - 1]).is_err());
+    assert!(snes.load_state(b"RSNS\x63\x00\x00\x00").is_err());
+    assert_eq!(snes.state_hash(), hash);
+}
@@ -0,0 +1,182 @@
+//! Screenshot regression tests: each case runs a ROM for some frames, optionally with an input
+//! script, and compares the last frame with a golden PNG in tests/screenshots/golden. The cases
+//! are listed in tests/screenshots/cases.txt, one per line:
+//!
+//! ```text
+//! NAME ROM FRAMES [INPUT]
+//! ```
+//!
+//! ROM is a path relative to the list, or `homebrew:NAME` for one of the programs in
+//! tests/homebrew. INPUT is an input script, also relative to the list.
+//!
+//! Set RUSTSNES_SCREENSHOT_CASES to another list to run ROMs that can't live in the tree; its
+//! goldens go in a golden directory next to it. Set UPDATE_SCREENSHOTS=1 to write the goldens
+//! from the current output instead of checking them. A mismatch leaves the actual frame and an
+//! image of the differing pixels in target/screenshot-diffs.
+
+#![allow(clippy::redundant_field_names)]
+
+extern crate rustsnes;
+
+mod homebrew;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rustsnes::{screenshot, SNES};
+use rustsnes::loader;
+use rustsnes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rustsnes::script::InputScript;
+
+struct Case {
+    name: String,
+    rom: String,
+    frames: u64,
+    input: Option<PathBuf>,
+}
+
+#[test]
+fn screenshots_match_goldens() {
+    let list = match env::var("RUSTSNES_SCREENSHOT_CASES") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/screenshots/cases.txt"),
+    };
+
+    let directory = list.parent().unwrap().to_path_buf();
+    let text = fs::read_to_string(&list).unwrap_or_else(|err| panic!("Could not read {}: {}", list.display(), err));
+    let cases = parse_cases(&text, &directory).unwrap_or_else(|err| panic!("{}: {}", list.display(), err));
+    let update = env::var("UPDATE_SCREENSHOTS").is_ok_and(|value| value == "1");
+
+    let failures: Vec<String> = cases.iter()
+        .filter_map(|case| check(case, &directory.join("golden"), update).err())
+        .collect();
+
+    if !failures.is_empty() {
+        panic!("{} of {} screenshots differ:\n{}", failures.len(), cases.len(), failures.join("\n"));
+    }
+}
+
+// Guards against a golden that matches whatever the input is, such as one of a blank screen
+#[test]
+fn held_buttons_change_the_picture() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/screenshots");
+    let case = |name: &str, input: Option<PathBuf>| Case {
+        name: name.to_string(),
+        rom: "homebrew:joypad-backdrop".to_string(),
+        frames: 30,
+        input: input,
+    };
+
+    let idle = run(&case("joypad-idle", None)).unwrap();
+    let held = run(&case("joypad-held", Some(directory.join("joypad-held.txt")))).unwrap();
+
+    assert!(idle != held, "holding buttons didn't change the picture");
+}
+
+fn parse_cases(text: &str, directory: &Path) -> Result<Vec<Case>, String> {
+    let mut cases = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        match fields.as_slice() {
+            [] => continue,
+            [name, rom, frames] | [name, rom, frames, _] => cases.push(Case {
+                name: name.to_string(),
+                rom: rom.to_string(),
+                frames: frames.parse().map_err(|_| format!("Line {}: bad frame count {}", number + 1, frames))?,
+                input: fields.get(3).map(|input| directory.join(input)),
+            }),
+            _ => return Err(format!("Line {}: expected NAME ROM FRAMES [INPUT]", number + 1)),
+        }
+    }
+
+    Ok(cases)
+}
+
+// Runs a case and compares it with its golden, or writes the golden when updating
+fn check(case: &Case, golden_directory: &Path, update: bool) -> Result<(), String> {
+    let frame = run(case).map_err(|err| format!("{}: {}", case.name, err))?;
+    let golden_path = golden_directory.join(format!("{}.png", case.name));
+
+    if update {
+        fs::create_dir_all(golden_directory).and_then(|_| fs::write(&golden_path, screenshot::encode_png(&frame)))
+            .map_err(|err| format!("{}: could not write {}: {}", case.name, golden_path.display(), err))?;
+        return Ok(());
+    }
+
+    let golden = fs::read(&golden_path)
+        .map_err(|err| format!("{}: no golden at {} ({}); run with UPDATE_SCREENSHOTS=1 to make one",
+                               case.name, golden_path.display(), err))?;
+    let (width, height, golden) = screenshot::decode_png(&golden).map_err(|err| format!("{}: {}", case.name, err))?;
+
+    if (width, height) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+        return Err(format!("{}: the golden is {}x{}, not {}x{}", case.name, width, height, SCREEN_WIDTH, SCREEN_HEIGHT));
+    }
+
+    if golden == frame {
+        return Ok(());
+    }
+
+    Err(report_difference(&case.name, &frame, &golden))
+}
+
+fn run(case: &Case) -> Result<Vec<u8>, String> {
+    let rom = match case.rom.strip_prefix("homebrew:") {
+        Some(name) => homebrew::build(name).ok_or(format!("no homebrew program called {}", name))?,
+        None => loader::load_rom(&case.rom, None)?.0,
+    };
+
+    let mut snes = SNES::load_rom(rom).map_err(|err| err.to_string())?;
+
+    if let Some(ref input) = case.input {
+        snes.set_input_script(InputScript::load(&input.to_string_lossy())?);
+    }
+
+    for _ in 0..case.frames {
+        snes.run_frame().map_err(|err| format!("stopped at frame {}: {}", snes.frame(), err))?;
+    }
+
+    Ok(snes.framebuffer().to_vec())
+}
+
+// Saves the actual frame and a copy of it with the differing pixels in magenta and the rest
+// dimmed, and describes where they are
+fn report_difference(name: &str, frame: &[u8], golden: &[u8]) -> String {
+    let mut diff = Vec::with_capacity(frame.len());
+    let mut count = 0;
+    let (mut left, mut top, mut right, mut bottom) = (SCREEN_WIDTH, SCREEN_HEIGHT, 0, 0);
+
+    for (index, (actual, expected)) in frame.chunks(3).zip(golden.chunks(3)).enumerate() {
+        if actual == expected {
+            diff.extend(actual.iter().map(|channel| channel / 4));
+            continue;
+        }
+
+        let (x, y) = (index % SCREEN_WIDTH, index / SCREEN_WIDTH);
+        left = left.min(x);
+        right = right.max(x);
+        top = top.min(y);
+        bottom = bottom.max(y);
+        count += 1;
+
+        diff.extend_from_slice(&[0xFF, 0x00, 0xFF]);
+    }
+
+    let output = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/screenshot-diffs");
+    let actual_path = output.join(format!("{}.png", name));
+    let diff_path = output.join(format!("{}-diff.png", name));
+
+    let saved = fs::create_dir_all(&output)
+        .and_then(|_| fs::write(&actual_path, screenshot::encode_png(frame)))
+        .and_then(|_| fs::write(&diff_path, screenshot::encode_png(&diff)));
+
+    let files = match saved {
+        Ok(()) => format!("see {} and {}", actual_path.display(), diff_path.display()),
+        Err(err) => format!("could not save the images: {}", err),
+    };
+
+    format!("{}: {} pixels differ between ({}, {}) and ({}, {}); {}", name, count, left, top, right, bottom, files)
+}
@@ -0,0 +1,249 @@
+//! Runs the CPU against single-step test vectors: JSON files of cases that each give the
+//! registers and the memory an instruction touches before and after running it, and the bus
+//! cycles it took:
+//!
+//! ```text
+//! [{"name": "a9 e 1",
+//!   "initial": {"pc": 32768, "s": 511, "p": 52, "a": 4660, "x": 0, "y": 0,
+//!               "dbr": 0, "d": 0, "pbr": 18, "e": 1, "ram": [[1212416, 169], [1212417, 0]]},
+//!   "final": {...},
+//!   "cycles": [[1212416, 169, "dp-remx-"], [1212417, 0, "-p-remx-"]]}, ...]
+//! ```
+//!
+//! That's the format of the widely used 65816 suite, which has a file per opcode and mode
+//! (`a9.e.json`, `a9.n.json`) covering every opcode. It's too big for the tree, so this isn't
+//! per-opcode coverage: what runs by default is a smoke test of a dozen hand-made cases in
+//! tests/single_step, over nine opcodes (LDA, STA, CLC, REP, SEP, XCE, LDY, TAX and PLB), each
+//! in one addressing mode. The full suite can't pass yet either, as 48 instructions, reached
+//! through 116 of the 256 opcodes, are still unimplemented.
+//!
+//! To run it anyway, point RUSTSNES_SINGLE_STEP_TESTS at its directory and ask for the ignored
+//! test with `cargo test --test single_step -- --ignored`. RUSTSNES_SINGLE_STEP_OPCODES limits
+//! the run to a comma-separated list of opcodes in hex, such as `a9,8d`.
+//!
+//! The CPU runs on a flat 16 MiB bus with no I/O registers or mirroring, so only it is under test.
+//! Only the number of cycles is checked, not what happens on each.
+
+#![allow(clippy::redundant_field_names)]
+
+extern crate rustsnes;
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use rustsnes::bus::Bus;
+use rustsnes::cpu::{CPU, ProcessorStatus};
+
+// 16 MiB of RAM, of which only the bytes a case touches are kept
+#[derive(Default)]
+struct FlatBus {
+    bytes: HashMap<usize, u8>,
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, addr: usize) -> u8 {
+        self.bytes.get(&addr).cloned().unwrap_or(0)
+    }
+
+    fn write(&mut self, addr: usize, data: u8) {
+        self.bytes.insert(addr, data);
+    }
+}
+
+struct State {
+    pc: usize,
+    s: usize,
+    p: u8,
+    a: u16,
+    x: u16,
+    y: u16,
+    dbr: usize,
+    d: usize,
+    pbr: usize,
+    e: bool,
+    ram: Vec<(usize, u8)>,
+}
+
+struct Case {
+    name: String,
+    initial: State,
+    expected: State,
+    cycles: usize,
+}
+
+struct Machine {
+    cpu: CPU,
+    bus: FlatBus,
+}
+
+#[test]
+fn sample_vectors_pass() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/single_step/sample.json");
+    let mut machine = Machine::new();
+
+    let failures = run_file(&mut machine, &path).unwrap_or_else(|err| panic!("{}", err));
+
+    if !failures.is_empty() {
+        panic!("{} sample cases failed:\n{}", failures.len(), failures.join("\n"));
+    }
+}
+
+#[test]
+#[ignore]
+fn external_vectors_pass() {
+    let directory = match env::var("RUSTSNES_SINGLE_STEP_TESTS") {
+        Ok(directory) => PathBuf::from(directory),
+        Err(_) => panic!("Set RUSTSNES_SINGLE_STEP_TESTS to the directory of the single-step suite"),
+    };
+
+    let opcodes: Option<Vec<String>> = env::var("RUSTSNES_SINGLE_STEP_OPCODES").ok()
+        .map(|list| list.split(',').map(|opcode| opcode.trim().to_lowercase()).collect());
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&directory)
+        .unwrap_or_else(|err| panic!("Could not read {}: {}", directory.display(), err))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .filter(|path| opcodes.as_ref().is_none_or(|opcodes| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            opcodes.iter().any(|opcode| name.split('.').next() == Some(opcode))
+        }))
+        .collect();
+    files.sort();
+
+    assert!(!files.is_empty(), "No test vectors in {}", directory.display());
+
+    let mut machine = Machine::new();
+    let mut failed_files = Vec::new();
+
+    for path in files.iter() {
+        let failures = run_file(&mut machine, path).unwrap_or_else(|err| panic!("{}", err));
+
+        if !failures.is_empty() {
+            println!("{}: {} failed, first: {}", path.display(), failures.len(), failures[0]);
+            failed_files.push(path.file_name().unwrap().to_string_lossy().into_owned());
+        }
+    }
+
+    if !failed_files.is_empty() {
+        panic!("{} of {} files have failing cases: {}", failed_files.len(), files.len(), failed_files.join(" "));
+    }
+}
+
+impl Machine {
+    fn new() -> Machine {
+        let mut bus = FlatBus::default();
+
+        Machine {
+            cpu: CPU::new(&mut bus),
+            bus: bus,
+        }
+    }
+
+    // Runs a case and describes every way it went wrong
+    fn run(&mut self, case: &Case) -> Result<(), String> {
+        self.bus.bytes.clear();
+        self.bus.bytes.extend(case.initial.ram.iter().cloned());
+        self.cpu.reset(&mut self.bus);
+
+        let initial = &case.initial;
+        self.cpu.program_counter = initial.pc;
+        self.cpu.stack_pointer = initial.s;
+        self.cpu.processor_status = ProcessorStatus::from_byte(initial.p);
+        self.cpu.accumulator = initial.a;
+        self.cpu.index_x = initial.x;
+        self.cpu.index_y = initial.y;
+        self.cpu.data_bank = initial.dbr;
+        self.cpu.direct_page = initial.d;
+        self.cpu.program_bank = initial.pbr;
+        self.cpu.emulation_mode = initial.e;
+
+        let cycles = self.cpu.run(&mut self.bus).map_err(|err| format!("{}: {}", case.name, err))?;
+
+        let expected = &case.expected;
+        let cpu = &self.cpu;
+        let mut differences = Vec::new();
+
+        {
+            let mut check = |what: &str, actual: usize, wanted: usize| {
+                if actual != wanted {
+                    differences.push(format!("{} {:x} (expected {:x})", what, actual, wanted));
+                }
+            };
+
+            check("pc", cpu.program_counter, expected.pc);
+            check("s", cpu.stack_pointer, expected.s);
+            check("p", cpu.processor_status.as_byte() as usize, expected.p as usize);
+            check("a", cpu.accumulator as usize, expected.a as usize);
+            check("x", cpu.index_x as usize, expected.x as usize);
+            check("y", cpu.index_y as usize, expected.y as usize);
+            check("dbr", cpu.data_bank, expected.dbr);
+            check("d", cpu.direct_page, expected.d);
+            check("pbr", cpu.program_bank, expected.pbr);
+            check("e", cpu.emulation_mode as usize, expected.e as usize);
+            check("cycles", cycles as usize, case.cycles);
+
+            for &(addr, data) in expected.ram.iter() {
+                check(&format!("[{:06x}]", addr), self.bus.read(addr) as usize, data as usize);
+            }
+        }
+
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{}: {}", case.name, differences.join(", ")))
+        }
+    }
+}
+
+fn run_file(machine: &mut Machine, path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    let cases = parse_cases(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    Ok(cases.iter().filter_map(|case| machine.run(case).err()).collect())
+}
+
+fn parse_cases(text: &str) -> Result<Vec<Case>, String> {
+    let json: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+    let cases = json.as_array().ok_or("Expected an array of cases")?;
+
+    cases.iter().enumerate().map(|(index, case)| {
+        let name = case["name"].as_str().map(str::to_string).unwrap_or(format!("case {}", index));
+
+        Ok(Case {
+            initial: parse_state(&case["initial"]).map_err(|err| format!("{}: initial {}", name, err))?,
+            expected: parse_state(&case["final"]).map_err(|err| format!("{}: final {}", name, err))?,
+            cycles: case["cycles"].as_array().ok_or(format!("{}: no cycles", name))?.len(),
+            name: name,
+        })
+    }).collect()
+}
+
+fn parse_state(state: &Value) -> Result<State, String> {
+    let field = |name: &str| state[name].as_u64().map(|value| value as usize).ok_or(format!("has no {}", name));
+
+    let ram = state["ram"].as_array().ok_or("has no ram")?.iter().map(|entry| {
+        match (entry[0].as_u64(), entry[1].as_u64()) {
+            (Some(addr), Some(data)) => Ok((addr as usize, data as u8)),
+            _ => Err(format!("has a bad ram entry {}", entry)),
+        }
+    }).collect::<Result<Vec<_>, String>>()?;
+
+    Ok(State {
+        pc: field("pc")?,
+        s: field("s")?,
+        p: field("p")? as u8,
+        a: field("a")? as u16,
+        x: field("x")? as u16,
+        y: field("y")? as u16,
+        dbr: field("dbr")?,
+        d: field("d")?,
+        pbr: field("pbr")?,
+        e: field("e")? != 0,
+        ram: ram,
+    })
+}